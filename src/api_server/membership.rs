@@ -1,20 +1,28 @@
 use std::str::FromStr;
 
-use anyhow::{bail, Ok, Result};
 use num_bigint::BigUint;
 
 use super::Member;
+use crate::error::StealthnoteError;
 
-pub fn create_membership(member: Member, path: String) -> Result<bool> {
+pub fn create_membership(member: Member, path: String) -> Result<bool, StealthnoteError> {
+    let pubkey = BigUint::from_str(member.pubkey.as_str()).map_err(|e| {
+        StealthnoteError::InvalidKey(format!(
+            "create_membership: pubkey {}: {}",
+            member.pubkey, e
+        ))
+    })?;
     let valid = member.clone().provider.verify_proof(
         member.clone().proof,
         member.clone().group_id,
-        BigUint::from_str(member.clone().pubkey.as_str()).unwrap(),
+        pubkey,
         member.clone().pubkey_expiry,
         member.clone().proof_args,
-    );
+    )?;
     if !valid {
-        bail!("create_membership: Invalid proof.")
+        return Err(StealthnoteError::ProofFailed(
+            "create_membership: Invalid proof.".to_string(),
+        ));
     }
 
     Ok(true)
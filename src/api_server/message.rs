@@ -1,15 +1,16 @@
 use std::{mem, str::FromStr};
 
-use super::{api::Api, Message, SignedMessage};
-use anyhow::{bail, Ok, Result};
+use super::{api::Api, Member, Message, Provider, ProviderRegistry, SignedMessage};
+use anyhow::{bail, Result};
 use chrono::{DateTime, Utc};
-use ed25519_dalek::{Signature, Signer, SigningKey};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use num_bigint::BigUint;
 use reqwest::Client;
 use serde::Serialize;
 use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
+use crate::error::StealthnoteError;
 
 pub fn fetch_message(path: String) -> Vec<SignedMessage> {
     vec![]
@@ -79,15 +80,18 @@ fn generate_short_id() -> String {
     format!("{}{}", parts[0], parts[1]) // join first two segments
 }
 
+/// Sign a message to the AnonGroup `anon_group_id` of `provider`, which must
+/// be registered, and return the payload to post.
 #[uniffi::export]
 pub fn sign_message(
+    provider: Provider,
     anon_group_id: String,
     text: String,
     internal: bool,
     ephemeral_public_key: String,
     ephemeral_private_key: String,
     ephemeral_pubkey_expiry: String,
-) -> String {
+) -> Result<String, StealthnoteError> {
     // timestamp
     let now = Utc::now();
     let timestamp = now.to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
@@ -95,13 +99,21 @@ pub fn sign_message(
     // id
     let id = generate_short_id();
 
+    let provider = provider.resolve()?;
+
     let ephemeral_pubkey_expiry = ephemeral_pubkey_expiry;
-    let private_key = BigUint::from_str(&ephemeral_private_key).unwrap();
+    let private_key = BigUint::from_str(&ephemeral_private_key)
+        .map_err(|e| StealthnoteError::InvalidKey(format!("ephemeral private key: {}", e)))?;
+    if private_key.bits() > 256 {
+        return Err(StealthnoteError::InvalidKey(
+            "ephemeral private key is longer than 32 bytes".to_string(),
+        ));
+    }
 
     let message = Message {
         id,
         anonGroupId: anon_group_id,
-        anonGroupProvider: "google-oauth".to_string(),
+        anonGroupProvider: provider.name(),
         text,
         timestamp,
         internal,
@@ -125,7 +137,75 @@ pub fn sign_message(
             likes: 0,
         },
     };
-    serde_json::to_string(&payload).unwrap()
+    serde_json::to_string(&payload).map_err(|e| StealthnoteError::InvalidInput(e.to_string()))
+}
+
+/// Check a signed message against its sender's membership: the provider the
+/// message names must be registered and be the member's, the member must be
+/// in the message's group with the same ephemeral key, the signature must
+/// verify under that key before it expired, and that provider must accept the
+/// member's proof.
+pub fn verify_message(
+    signed_message: &SignedMessage,
+    member: &Member,
+) -> Result<bool, StealthnoteError> {
+    let provider = ProviderRegistry::global().get(&signed_message.anonGroupProvider)?;
+    if provider.name() != member.provider.name()
+        || signed_message.anonGroupId != member.group_id
+        || signed_message.ephemeralPubkeyExpiry != member.pubkey_expiry
+    {
+        return Ok(false);
+    }
+    let parse_number = |value: &str, what: &str| {
+        BigUint::from_str(value)
+            .map_err(|e| StealthnoteError::InvalidInput(format!("{} {}: {}", what, value, e)))
+    };
+    let pubkey = parse_number(&signed_message.ephemeralPubkey, "ephemeral pubkey")?;
+    if pubkey != parse_number(&member.pubkey, "member pubkey")? {
+        return Ok(false);
+    }
+    let parse_time = |value: &str, what: &str| {
+        value
+            .parse::<DateTime<Utc>>()
+            .map_err(|e| StealthnoteError::InvalidInput(format!("{} {}: {}", what, value, e)))
+    };
+    let timestamp = parse_time(&signed_message.timestamp, "message timestamp")?;
+    if timestamp >= parse_time(&member.pubkey_expiry, "ephemeral expiry")? {
+        return Ok(false);
+    }
+
+    let signature = parse_number(&signed_message.signature, "signature")?;
+    if pubkey.bits() > 256 || signature.bits() > 512 {
+        return Ok(false);
+    }
+    let Some(verifying_key) = VerifyingKey::from_bytes(&big_int_to_bytes(&pubkey, 32)).ok() else {
+        return Ok(false);
+    };
+    let mut signature_bytes = [0u8; 64];
+    let bytes = signature.to_bytes_be();
+    signature_bytes[64 - bytes.len()..].copy_from_slice(&bytes);
+    let message_hash = hash_message(Message {
+        id: signed_message.id.clone(),
+        anonGroupId: signed_message.anonGroupId.clone(),
+        anonGroupProvider: signed_message.anonGroupProvider.clone(),
+        text: signed_message.text.clone(),
+        timestamp: signed_message.timestamp.clone(),
+        internal: signed_message.internal,
+        likes: signed_message.likes,
+    });
+    if verifying_key
+        .verify(&message_hash, &Signature::from_bytes(&signature_bytes))
+        .is_err()
+    {
+        return Ok(false);
+    }
+    provider.verify_proof(
+        member.proof.clone(),
+        &member.group_id,
+        &pubkey,
+        &member.pubkey_expiry,
+        &member.proof_args,
+    )
 }
 
 pub async fn create_message(signed_message_str: String) -> Result<()> {
@@ -151,6 +231,8 @@ pub async fn create_message(signed_message_str: String) -> Result<()> {
 mod tests {
 
     use super::*;
+    use crate::api_server::{AnonGroupProvider, MembershipProof};
+    use std::{collections::HashMap, sync::Arc};
 
     #[tokio::test]
     async fn test_sign_message() {
@@ -174,16 +256,152 @@ mod tests {
         let internal = false;
         let text = "sent from Rust".to_string();
         let signed_message_str = sign_message(
+            Provider::Google,
             anon_group_id,
             text,
             internal,
             public_key.to_string(),
             private_key.to_string(),
             expiry.to_string(),
-        );
+        )
+        .unwrap();
         create_message(signed_message_str).await.unwrap();
     }
 
+    /// A provider whose members prove membership by knowing a passphrase.
+    struct Passphrase;
+
+    impl AnonGroupProvider for Passphrase {
+        fn name(&self) -> String {
+            "passphrase".to_string()
+        }
+
+        fn slug(&self) -> String {
+            "passphrase".to_string()
+        }
+
+        fn generate_proof(
+            &self,
+            _ephemeral_key: &crate::proof::ephemeral_key::EphemeralKey,
+            _inputs: &HashMap<String, Vec<String>>,
+        ) -> Result<MembershipProof, StealthnoteError> {
+            Err(StealthnoteError::Unsupported("passphrase".to_string()))
+        }
+
+        fn verify_proof(
+            &self,
+            proof: Vec<u8>,
+            _anon_group_id: &str,
+            _ephemeral_pubkey: &BigUint,
+            _ephemeral_pubkey_expiry: &str,
+            _proof_args: &HashMap<String, Vec<String>>,
+        ) -> Result<bool, StealthnoteError> {
+            Ok(proof == b"open sesame")
+        }
+    }
+
+    /// Sign `message` again, as its sender would have.
+    fn resign(message: &mut SignedMessage, signing_key: &SigningKey) {
+        let message_hash = hash_message(Message {
+            id: message.id.clone(),
+            anonGroupId: message.anonGroupId.clone(),
+            anonGroupProvider: message.anonGroupProvider.clone(),
+            text: message.text.clone(),
+            timestamp: message.timestamp.clone(),
+            internal: message.internal,
+            likes: message.likes,
+        });
+        let signature = ed25519_sign(&message_hash, signing_key.as_bytes());
+        message.signature = signature.to_string();
+    }
+
+    #[test]
+    fn test_verify_message() {
+        ProviderRegistry::global()
+            .register(Arc::new(Passphrase))
+            .unwrap();
+        let provider = Provider::Registered {
+            name: "passphrase".to_string(),
+        };
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let private_key = BigUint::from_bytes_be(signing_key.as_bytes()).to_string();
+        let public_key = BigUint::from_bytes_be(signing_key.verifying_key().as_bytes()).to_string();
+        let expiry = "2099-01-01T00:00:00.000Z";
+        let payload = sign_message(
+            provider.clone(),
+            "pse.dev".to_string(),
+            "gm".to_string(),
+            false,
+            public_key.clone(),
+            private_key.clone(),
+            expiry.to_string(),
+        )
+        .unwrap();
+        let signed_message: SignedMessage = serde_json::from_str(&payload).unwrap();
+        assert_eq!(signed_message.anonGroupProvider, "passphrase");
+        let member = Member {
+            provider,
+            pubkey: public_key.clone(),
+            pubkey_expiry: expiry.to_string(),
+            proof: b"open sesame".to_vec(),
+            proof_args: HashMap::new(),
+            group_id: "pse.dev".to_string(),
+        };
+        assert!(verify_message(&signed_message, &member).unwrap());
+
+        // The member's provider must accept their proof
+        let impostor = Member {
+            proof: b"letmein".to_vec(),
+            ..member.clone()
+        };
+        assert!(!verify_message(&signed_message, &impostor).unwrap());
+
+        let mut tampered = signed_message.clone();
+        tampered.text = "gn".to_string();
+        assert!(!verify_message(&tampered, &member).unwrap());
+
+        let other_group = Member {
+            group_id: "ethereum.org".to_string(),
+            ..member.clone()
+        };
+        assert!(!verify_message(&signed_message, &other_group).unwrap());
+        let other_provider = Member {
+            provider: Provider::Google,
+            ..member.clone()
+        };
+        assert!(!verify_message(&signed_message, &other_provider).unwrap());
+
+        let mut unknown = signed_message.clone();
+        unknown.anonGroupProvider = "myspace".to_string();
+        assert!(matches!(
+            verify_message(&unknown, &member),
+            Err(StealthnoteError::Unsupported(_))
+        ));
+        assert!(matches!(
+            sign_message(
+                Provider::Registered {
+                    name: "myspace".to_string()
+                },
+                "pse.dev".to_string(),
+                "gm".to_string(),
+                false,
+                public_key,
+                private_key,
+                expiry.to_string(),
+            ),
+            Err(StealthnoteError::Unsupported(_))
+        ));
+
+        // Correctly signed, but after the ephemeral key expired
+        let mut late = signed_message;
+        late.timestamp = "2099-01-02T00:00:00.000Z".to_string();
+        resign(&mut late, &signing_key);
+        assert!(!verify_message(&late, &member).unwrap());
+        late.timestamp = "2098-12-31T00:00:00.000Z".to_string();
+        resign(&mut late, &signing_key);
+        assert!(verify_message(&late, &member).unwrap());
+    }
+
     #[tokio::test]
     async fn test_create_message() {
         let signed_message = SignedMessage {
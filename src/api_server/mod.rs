@@ -1,12 +1,20 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use ed25519::Signature;
 use num_bigint::BigUint;
 use serde::{Deserialize, Serialize};
 
+use crate::error::StealthnoteError;
+
 mod api;
 mod provider;
-use provider::*;
+pub(crate) use provider::email::parse_expiry;
+pub(crate) use provider::oidc;
+pub use provider::{
+    AnonGroup, AnonGroupProvider, AnonGroupRegistry, AnonGroupSource, EmailProvider,
+    GoogleOAuthProvider, GroupClaim, MembershipProof, MicrosoftOAuthProvider, OidcIssuerConfig,
+    ProviderRegistry,
+};
 
 pub mod likes;
 pub mod membership;
@@ -16,9 +24,37 @@ pub mod message;
 pub enum Provider {
     Google,
     Microsoft,
+    /// DKIM-signed email, proven with the zkEmail circuit
+    Email,
+    /// Any other provider in the [`ProviderRegistry`] by name, e.g. an issuer
+    /// registered from the OIDC issuers config
+    Registered {
+        name: String,
+    },
 }
 
 impl Provider {
+    /// Name of the provider in the [`ProviderRegistry`], as messages carry it
+    /// in `anonGroupProvider`.
+    pub fn name(&self) -> String {
+        match self {
+            Self::Google => GoogleOAuthProvider::NAME.to_string(),
+            Self::Microsoft => MicrosoftOAuthProvider::NAME.to_string(),
+            Self::Email => EmailProvider::NAME.to_string(),
+            Self::Registered { name } => name.clone(),
+        }
+    }
+
+    /// The registered provider this names.
+    pub fn resolve(&self) -> Result<Arc<dyn AnonGroupProvider>, StealthnoteError> {
+        ProviderRegistry::global().get(&self.name())
+    }
+
+    /// What kind of id the provider's AnonGroups have, e.g. `domain`.
+    pub fn slug(&self) -> Result<String, StealthnoteError> {
+        Ok(self.resolve()?.slug())
+    }
+
     pub fn verify_proof(
         &self,
         proof: Vec<u8>,
@@ -26,17 +62,14 @@ impl Provider {
         ephemeral_pubkey: BigUint,
         ephemeral_pubkey_expiry: String,
         proof_args: HashMap<String, Vec<String>>,
-    ) -> bool {
-        match self {
-            Self::Google => GoogleOAuthProvider::verify_proof(
-                proof,
-                anon_group_id,
-                ephemeral_pubkey,
-                ephemeral_pubkey_expiry,
-                proof_args,
-            ),
-            Self::Microsoft => panic!("Not supported yet."),
-        }
+    ) -> Result<bool, StealthnoteError> {
+        self.resolve()?.verify_proof(
+            proof,
+            &anon_group_id,
+            &ephemeral_pubkey,
+            &ephemeral_pubkey_expiry,
+            &proof_args,
+        )
     }
 }
 
@@ -1,64 +1,217 @@
 use std::collections::HashMap;
 
+use chrono::Utc;
 use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
 
+use crate::error::StealthnoteError;
+use crate::proof::{
+    envelope::split_bb_proof, ephemeral_key::EphemeralKey, jwks::JwksStore,
+    jwt_input::JwtCircuitInput, jwt_proof::JsonWebKey, versions::JwtCircuitVersion,
+};
+
+pub mod email;
 pub mod google;
+pub mod groups;
+pub mod microsoft;
+pub mod oidc;
+pub mod registry;
+pub use email::EmailProvider;
 pub use google::GoogleOAuthProvider;
+pub use groups::{AnonGroupRegistry, AnonGroupSource};
+pub use microsoft::MicrosoftOAuthProvider;
+pub use oidc::{GroupClaim, OidcIssuerConfig};
+pub use registry::ProviderRegistry;
+
+/// A membership proof and the `Member` fields that go with it.
+#[derive(uniffi::Record, Debug, Clone)]
+pub struct MembershipProof {
+    pub proof: Vec<u8>,
+    /// AnonGroup id, e.g. the company domain
+    pub group_id: String,
+    /// Provider-specific args the proof is verified with
+    pub proof_args: HashMap<String, Vec<String>>,
+}
 
-struct AnonGroup {
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnonGroup {
     /** Unique identifier for the group (e.g: company domain) */
-    id: String,
+    pub id: String,
     /** Display name of the group */
-    title: String,
+    pub title: String,
     /** URL to the group's logo image */
-    logo_url: String,
-}
-
-struct EphemeralKey {
-    private_key: BigUint,
-    public_key: BigUint,
-    salt: BigUint,
-    expiry: u32,
-    ephemeral_pubkey_hash: BigUint,
+    pub logo_url: String,
 }
 
-pub trait AnonGroupProvider {
-    /** Get the provider's unique identifier */
-    fn name() -> String;
+/// An identity provider members prove AnonGroup membership with. Providers
+/// are looked up by name in a [`ProviderRegistry`], so third-party ones can
+/// be registered next to the built-in ones.
+pub trait AnonGroupProvider: Send + Sync {
+    /** Get the provider's unique identifier, as messages carry it in `anonGroupProvider` */
+    fn name(&self) -> String;
 
     /** Slug is a key that represents the type of the AnonGroup identifier (to be used in URLs). Example: "domain" */
-    fn get_slug() -> String;
+    fn slug(&self) -> String;
 
     /**
      * Generate a ZK proof that the current user is a member of an AnonGroup
-     * @param ephemeralPubkeyHash - Hash of the ephemeral pubkey, expiry and salt
-     * @returns Returns the AnonGroup and membership proof, along with additional args that may be needed for verification
+     * @param ephemeralKey - Ephemeral key whose pubkey hash the proof binds
+     * @param inputs - Provider-specific inputs, e.g. the ID token
+     * @returns Returns the AnonGroup id and membership proof, along with additional args that may be needed for verification
      */
     fn generate_proof(
-        ephemeral_key: EphemeralKey,
-        inputs: HashMap<String, Vec<String>>,
-    ) -> Vec<u8>;
+        &self,
+        ephemeral_key: &EphemeralKey,
+        inputs: &HashMap<String, Vec<String>>,
+    ) -> Result<MembershipProof, StealthnoteError>;
 
     /**
      * Verify a ZK proof of group membership
      * @param proof - The ZK proof to verify
-     * @param ephemeralPubkey - Pubkey modulus of the ephemeral key that was used when generating the proof
-     * @param anonGroup - AnonGroup that the proof claims membership in
+     * @param anonGroupId - AnonGroup that the proof claims membership in
+     * @param ephemeralPubkey - Pubkey of the ephemeral key that was used when generating the proof
      * @param proofArgs - Additional args that was returned when the proof was generated
-     * @returns Promise resolving to true if the proof is valid
+     * @returns true if the proof is valid
      */
     fn verify_proof(
+        &self,
         proof: Vec<u8>,
-        anon_group_id: String,
-        ephemeral_pubkey: BigUint,
-        ephemeral_pubkey_expiry: String,
-        proof_args: HashMap<String, Vec<String>>,
-    ) -> bool;
+        anon_group_id: &str,
+        ephemeral_pubkey: &BigUint,
+        ephemeral_pubkey_expiry: &str,
+        proof_args: &HashMap<String, Vec<String>>,
+    ) -> Result<bool, StealthnoteError>;
 
     /**
      * Get the AnonGroup by its unique identifier
      * @param groupId - Unique identifier for the AnonGroup
-     * @returns Promise resolving to the AnonGroup
+     * @returns the AnonGroup, from the global AnonGroupRegistry by default
      */
-    fn get_anon_group(group_id: String) -> AnonGroup;
+    fn get_anon_group(&self, group_id: &str) -> AnonGroup {
+        AnonGroupRegistry::global().get(self.slug(), group_id.to_string())
+    }
+
+    /**
+     * Get the config of the OIDC issuer this provider was registered for
+     * @returns the config, or None for providers that are not generic OIDC issuers
+     */
+    fn oidc_config(&self) -> Option<OidcIssuerConfig> {
+        None
+    }
+}
+
+fn proof_arg<'a>(
+    args: &'a HashMap<String, Vec<String>>,
+    name: &str,
+) -> Result<&'a str, StealthnoteError> {
+    match args.get(name).map(Vec::as_slice) {
+        Some([value]) => Ok(value),
+        _ => Err(StealthnoteError::InvalidInput(format!(
+            "proofArgs.{} must hold one value",
+            name
+        ))),
+    }
+}
+
+/// The optional `srsPath` input, for proving.
+fn srs_path_arg(args: &HashMap<String, Vec<String>>) -> Option<&str> {
+    args.get("srsPath")
+        .and_then(|v| v.first())
+        .map(String::as_str)
+}
+
+/// The signing key a JWT provider's `generate_proof` takes as its `jwk`
+/// input, and the optional `srsPath` input.
+fn jwt_proof_inputs(
+    inputs: &HashMap<String, Vec<String>>,
+) -> Result<(JsonWebKey, Option<&str>), StealthnoteError> {
+    let jwk = serde_json::from_str(proof_arg(inputs, "jwk")?)
+        .map_err(|e| StealthnoteError::InvalidKey(format!("JsonWebKey: {}", e)))?;
+    Ok((jwk, srs_path_arg(inputs)))
+}
+
+/// Prove `input` with the JWT circuit and strip the public inputs, as the
+/// membership API stores proofs. Sets the `keyId` and `jwtCircuitVersion`
+/// proof args.
+fn prove_jwt_membership(
+    srs_path: Option<&str>,
+    input: &JwtCircuitInput,
+    jwk: &JsonWebKey,
+    group_id: String,
+) -> Result<MembershipProof, StealthnoteError> {
+    let version = JwtCircuitVersion::for_modulus_limbs(input.jwt_pubkey_modulus_limbs.len())?;
+    let circuit = input.circuit()?;
+    let bb_proof = circuit.prove_witness(srs_path, input.to_witness()?)?;
+    let (_, proof) = split_bb_proof(&bb_proof, circuit.artifact.num_public_inputs())?;
+    Ok(MembershipProof {
+        proof,
+        group_id,
+        proof_args: HashMap::from([
+            ("keyId".to_string(), vec![jwk.kid.clone()]),
+            (
+                "jwtCircuitVersion".to_string(),
+                vec![version.version.to_string()],
+            ),
+        ]),
+    })
+}
+
+/// Verify a stripped JWT membership proof with the circuit its proof args
+/// name and `issuer`'s signing key `proofArgs.keyId`, resolved through `store`,
+/// against the global [`ProviderRegistry`]'s SRS. Proofs for an expired
+/// ephemeral key are rejected.
+fn verify_jwt_membership(
+    proof: Vec<u8>,
+    anon_group_id: &str,
+    ephemeral_pubkey: &BigUint,
+    ephemeral_pubkey_expiry: &str,
+    proof_args: &HashMap<String, Vec<String>>,
+    store: &JwksStore,
+    issuer: &str,
+) -> Result<bool, StealthnoteError> {
+    verify_jwt_membership_at(
+        proof,
+        anon_group_id,
+        ephemeral_pubkey,
+        ephemeral_pubkey_expiry,
+        proof_args,
+        store,
+        issuer,
+        ProviderRegistry::global().srs_path().as_deref(),
+        Utc::now().timestamp(),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn verify_jwt_membership_at(
+    proof: Vec<u8>,
+    anon_group_id: &str,
+    ephemeral_pubkey: &BigUint,
+    ephemeral_pubkey_expiry: &str,
+    proof_args: &HashMap<String, Vec<String>>,
+    store: &JwksStore,
+    issuer: &str,
+    srs_path: Option<&str>,
+    now: i64,
+) -> Result<bool, StealthnoteError> {
+    let expiry = i64::from(email::parse_expiry(ephemeral_pubkey_expiry)?);
+    if expiry <= now {
+        return Err(StealthnoteError::InvalidInput(format!(
+            "ephemeral key expired at {}",
+            ephemeral_pubkey_expiry
+        )));
+    }
+    // The domain is a public input, so a proof only verifies for the group it
+    // was generated for
+    let version = JwtCircuitVersion::get(proof_arg(proof_args, "jwtCircuitVersion")?)?;
+    let jwk = store.key_at(issuer, proof_arg(proof_args, "keyId")?, now, Some(expiry))?;
+    version.verify(
+        srs_path,
+        proof,
+        anon_group_id.to_string(),
+        jwk.n.clone(),
+        ephemeral_pubkey.to_string(),
+        ephemeral_pubkey_expiry.to_string(),
+    )
 }
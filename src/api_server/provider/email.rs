@@ -0,0 +1,283 @@
+use chrono::{DateTime, Utc};
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, str::FromStr};
+
+use super::{proof_arg, srs_path_arg, AnonGroupProvider, MembershipProof, ProviderRegistry};
+use crate::error::StealthnoteError;
+use crate::proof::{
+    cache::CircuitHandle,
+    dkim::{parse_dkim_key, Email},
+    dkim_registry::DkimKeyRegistry,
+    ephemeral_key::{ephemeral_pubkey_hash, EphemeralKey},
+    pedersen::dkim_pubkey_hash,
+    rsa::RsaPublicKey,
+    zkemail_input::ZkEmailCircuitInput,
+    zkemail_proof::ZkEmailClaims,
+};
+
+/// Proves membership of a domain with a DKIM-signed email whose subject
+/// carries the ephemeral pubkey hash, e.g. `Stealthnote 6226...9546`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EmailProvider;
+
+impl EmailProvider {
+    pub const NAME: &'static str = "email";
+
+    /// `group_id` is the sender domain; `proof_args` hold `dkimSelector`,
+    /// `dkimKeyHash` and `ephemeralPubkeySalt`.
+    pub fn prove_membership(
+        srs_path: Option<&str>,
+        eml: &[u8],
+        dkim_key: &RsaPublicKey,
+        ephemeral_pubkey: &BigUint,
+        ephemeral_salt: &BigUint,
+        ephemeral_expiry: u32,
+    ) -> Result<MembershipProof, StealthnoteError> {
+        let pubkey_hash = ephemeral_pubkey_hash(ephemeral_pubkey, ephemeral_salt, ephemeral_expiry)
+            .ok_or_else(|| {
+                StealthnoteError::InvalidKey("ephemeral pubkey does not fit a field".to_string())
+            })?;
+
+        let email = Email::parse(eml)?;
+        let signature = email.dkim_signature(None)?;
+        let input = ZkEmailCircuitInput::from_email(&email, &signature, dkim_key)?;
+        let subject = &input.header.storage[input.subject_sequence.index as usize..]
+            [..input.subject_sequence.length as usize];
+        if !subject_binds(&String::from_utf8_lossy(subject), &pubkey_hash) {
+            return Err(StealthnoteError::InvalidInput(format!(
+                "email subject does not contain the ephemeral pubkey hash {}",
+                pubkey_hash
+            )));
+        }
+
+        let circuit = CircuitHandle::zkemail()?;
+        let proof = circuit.prove_witness(srs_path, input.to_witness()?)?;
+        let claims = ZkEmailClaims::from_bb_proof(&proof)?;
+        Ok(MembershipProof {
+            proof,
+            group_id: claims.from_domain,
+            proof_args: HashMap::from([
+                ("dkimSelector".to_string(), vec![signature.selector]),
+                ("dkimKeyHash".to_string(), vec![claims.dkim_pubkey_hash]),
+                (
+                    "ephemeralPubkeySalt".to_string(),
+                    vec![ephemeral_salt.to_string()],
+                ),
+            ]),
+        })
+    }
+
+    /// Check a membership proof against the group and ephemeral key it claims.
+    /// The DKIM key is the current key of `proofArgs.dkimSelector` for the
+    /// group's domain in the global [`DkimKeyRegistry`]; the proof must have
+    /// been made with it, whatever `proofArgs.dkimKeyHash` says.
+    pub fn verify_membership(
+        proof: Vec<u8>,
+        anon_group_id: &str,
+        ephemeral_pubkey: &BigUint,
+        ephemeral_pubkey_expiry: &str,
+        proof_args: &HashMap<String, Vec<String>>,
+    ) -> Result<bool, StealthnoteError> {
+        Self::verify_membership_at(
+            proof,
+            anon_group_id,
+            ephemeral_pubkey,
+            ephemeral_pubkey_expiry,
+            proof_args,
+            &DkimKeyRegistry::global(),
+            ProviderRegistry::global().srs_path().as_deref(),
+            Utc::now().timestamp(),
+        )
+    }
+
+    fn verify_membership_at(
+        proof: Vec<u8>,
+        anon_group_id: &str,
+        ephemeral_pubkey: &BigUint,
+        ephemeral_pubkey_expiry: &str,
+        proof_args: &HashMap<String, Vec<String>>,
+        registry: &DkimKeyRegistry,
+        srs_path: Option<&str>,
+        now: i64,
+    ) -> Result<bool, StealthnoteError> {
+        let expiry = parse_expiry(ephemeral_pubkey_expiry)?;
+        if i64::from(expiry) <= now {
+            return Err(StealthnoteError::InvalidInput(format!(
+                "ephemeral key expired at {}",
+                ephemeral_pubkey_expiry
+            )));
+        }
+        let selector = proof_arg(proof_args, "dkimSelector")?;
+        let salt = BigUint::from_str(proof_arg(proof_args, "ephemeralPubkeySalt")?)
+            .map_err(|e| StealthnoteError::InvalidInput(format!("ephemeralPubkeySalt: {}", e)))?;
+        let Some(pubkey_hash) = ephemeral_pubkey_hash(ephemeral_pubkey, &salt, expiry) else {
+            return Ok(false);
+        };
+        let dkim_key = registry
+            .lookup(anon_group_id.to_string(), selector.to_string(), None)?
+            .key()?;
+        let key_hash = format!("{:x}", dkim_pubkey_hash(&dkim_key));
+
+        // Cheap checks on the public outputs first
+        let claims = ZkEmailClaims::from_bb_proof(&proof)?;
+        claims.check(Some(&key_hash), Some(anon_group_id))?;
+        if !subject_binds(&claims.subject, &pubkey_hash) {
+            return Ok(false);
+        }
+        CircuitHandle::zkemail()?.verify(srs_path, proof)
+    }
+}
+
+impl AnonGroupProvider for EmailProvider {
+    fn name(&self) -> String {
+        Self::NAME.to_string()
+    }
+
+    fn slug(&self) -> String {
+        "domain".to_string()
+    }
+
+    /// `inputs`: `eml` (the raw email), `dkimPublicKey` (TXT record or base64
+    /// key) and optionally `srsPath`.
+    fn generate_proof(
+        &self,
+        ephemeral_key: &EphemeralKey,
+        inputs: &HashMap<String, Vec<String>>,
+    ) -> Result<MembershipProof, StealthnoteError> {
+        let eml = proof_arg(inputs, "eml")?;
+        let dkim_key = parse_dkim_key(proof_arg(inputs, "dkimPublicKey")?)?;
+        let salt = BigUint::from_str(&ephemeral_key.salt)
+            .map_err(|e| StealthnoteError::InvalidKey(format!("ephemeral salt: {}", e)))?;
+        Self::prove_membership(
+            srs_path_arg(inputs),
+            eml.as_bytes(),
+            &dkim_key,
+            &BigUint::from_bytes_be(ephemeral_key.public_key.as_bytes()),
+            &salt,
+            parse_expiry(&ephemeral_key.expiry)?,
+        )
+    }
+
+    fn verify_proof(
+        &self,
+        proof: Vec<u8>,
+        anon_group_id: &str,
+        ephemeral_pubkey: &BigUint,
+        ephemeral_pubkey_expiry: &str,
+        proof_args: &HashMap<String, Vec<String>>,
+    ) -> Result<bool, StealthnoteError> {
+        Self::verify_membership(
+            proof,
+            anon_group_id,
+            ephemeral_pubkey,
+            ephemeral_pubkey_expiry,
+            proof_args,
+        )
+    }
+}
+
+/// Seconds since the epoch of an RFC 3339 ephemeral key expiry.
+pub(crate) fn parse_expiry(expiry: &str) -> Result<u32, StealthnoteError> {
+    let parsed: DateTime<Utc> = expiry.parse().map_err(|e| {
+        StealthnoteError::InvalidInput(format!("ephemeral expiry {}: {}", expiry, e))
+    })?;
+    u32::try_from(parsed.timestamp()).map_err(|_| {
+        StealthnoteError::InvalidInput(format!("ephemeral expiry {} is out of range", expiry))
+    })
+}
+
+/// Whether the ephemeral pubkey hash appears as a word of the subject.
+fn subject_binds(subject: &str, pubkey_hash: &BigUint) -> bool {
+    let hash = pubkey_hash.to_string();
+    subject.split_whitespace().any(|word| word == hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subject_binds_pubkey_hash() {
+        let hash = BigUint::from(622618718926420486u64);
+        assert!(subject_binds("Stealthnote 622618718926420486", &hash));
+        assert!(subject_binds("622618718926420486", &hash));
+        assert!(!subject_binds("Stealthnote 6226187189264204860", &hash));
+        assert!(!subject_binds("This is a test email", &hash));
+    }
+
+    const EXPIRY: &str = "2025-05-07T09:07:57.379Z";
+    const BEFORE_EXPIRY: i64 = 1_746_000_000;
+
+    fn verify(
+        proof: Vec<u8>,
+        expiry: &str,
+        args: &HashMap<String, Vec<String>>,
+    ) -> Result<bool, StealthnoteError> {
+        let registry = DkimKeyRegistry::from_snapshot("public/dkim_keys.zone".to_string())?;
+        EmailProvider::verify_membership_at(
+            proof,
+            "gmail.com",
+            &BigUint::from(12345u64),
+            expiry,
+            args,
+            &registry,
+            None,
+            BEFORE_EXPIRY,
+        )
+    }
+
+    #[test]
+    fn test_verify_requires_proof_args() {
+        let args = HashMap::from([("dkimKeyHash".to_string(), vec!["0x01".to_string()])]);
+        assert!(matches!(
+            verify(vec![], EXPIRY, &args),
+            Err(StealthnoteError::InvalidInput(_))
+        ));
+        assert!(EmailProvider
+            .verify_proof(vec![], "gmail.com", &BigUint::from(12345u64), EXPIRY, &args)
+            .is_err());
+
+        let args = HashMap::from([
+            ("dkimSelector".to_string(), vec!["20230601".to_string()]),
+            ("ephemeralPubkeySalt".to_string(), vec!["1".to_string()]),
+        ]);
+        assert!(matches!(
+            verify(vec![], "2025-04-30T00:00:00.000Z", &args),
+            Err(StealthnoteError::InvalidInput(_))
+        ));
+        let mut unknown_selector = args.clone();
+        unknown_selector.insert("dkimSelector".to_string(), vec!["other".to_string()]);
+        assert!(matches!(
+            verify(vec![], EXPIRY, &unknown_selector),
+            Err(StealthnoteError::InvalidKey(_))
+        ));
+    }
+
+    /// A proof made with the prover's own DKIM key claims that key's hash,
+    /// which is not the registered key of the domain.
+    #[test]
+    fn test_verify_rejects_unregistered_dkim_key() {
+        let own_key_hash = format!("{:0>64}", "0a1b2c3d");
+        let field = |byte: u8| format!("{:0>64x}", byte);
+        let mut fields = vec![own_key_hash.clone(), field(42)];
+        for (value, capacity) in [("alice@gmail.com", 320), ("", 37), ("Stealthnote 1", 101)] {
+            fields.extend(value.bytes().map(field));
+            fields.extend((value.len()..capacity).map(|_| field(0)));
+            fields.push(field(value.len() as u8));
+        }
+        let mut proof = ((fields.len() + 2) as u32).to_be_bytes().to_vec();
+        for f in &fields {
+            proof.extend(hex::decode(f).unwrap());
+        }
+        proof.extend([7u8; 64]);
+
+        let args = HashMap::from([
+            ("dkimSelector".to_string(), vec!["20230601".to_string()]),
+            ("dkimKeyHash".to_string(), vec![own_key_hash]),
+            ("ephemeralPubkeySalt".to_string(), vec!["1".to_string()]),
+        ]);
+        let result = verify(proof, EXPIRY, &args);
+        assert!(result.is_err_and(|e| e.to_string().contains("DKIM key hash")));
+    }
+}
@@ -1,63 +1,327 @@
 use num_bigint::BigUint;
 use serde::{Deserialize, Serialize};
 
-use super::{AnonGroup, AnonGroupProvider, EphemeralKey};
-use crate::{proof::jwt_proof, prove_jwt};
+use super::{
+    jwt_proof_inputs, proof_arg, prove_jwt_membership, verify_jwt_membership, AnonGroupProvider,
+    MembershipProof,
+};
+use crate::error::StealthnoteError;
+use crate::proof::{
+    ephemeral_key::EphemeralKey,
+    jwks::{JwksStore, GOOGLE_ISSUER},
+    jwt::DecodedJwt,
+    jwt_input::JwtCircuitInput,
+    jwt_proof::JsonWebKey,
+};
 use std::collections::HashMap;
 
+/// Google signs ID tokens with either form of its issuer.
+const GOOGLE_ISSUERS: [&str; 2] = [GOOGLE_ISSUER, "accounts.google.com"];
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct GoogleOAuthProvider;
 
+impl GoogleOAuthProvider {
+    pub const NAME: &'static str = "google-oauth";
+
+    /// Check the Google claims of `jwt` and return the Workspace domain to
+    /// prove. Personal accounts have no `hd` and belong to no group.
+    pub fn check_claims(jwt: &DecodedJwt) -> Result<String, StealthnoteError> {
+        if !GOOGLE_ISSUERS.contains(&jwt.claims.iss.as_str()) {
+            return Err(StealthnoteError::InvalidJwt(format!(
+                "issuer {} is not Google",
+                jwt.claims.iss
+            )));
+        }
+        let Some(hd) = &jwt.claims.hd else {
+            return Err(StealthnoteError::Unsupported(
+                "personal Google accounts do not belong to a Workspace domain".to_string(),
+            ));
+        };
+        let email_domain = jwt
+            .claims
+            .email
+            .as_deref()
+            .and_then(|email| email.rsplit_once('@'))
+            .map(|(_, domain)| domain);
+        if !email_domain.is_some_and(|domain| domain.eq_ignore_ascii_case(hd)) {
+            return Err(StealthnoteError::InvalidJwt(format!(
+                "email is not in the hosted domain {}",
+                hd
+            )));
+        }
+        if jwt.claims.email_verified != Some(true) {
+            return Err(StealthnoteError::InvalidJwt(
+                "email is not verified".to_string(),
+            ));
+        }
+        Ok(hd.to_ascii_lowercase())
+    }
+
+    /// Prove membership of the token's hosted domain. Returns the stripped
+    /// proof with `keyId` and `jwtCircuitVersion` proof args.
+    pub fn prove_membership(
+        srs_path: Option<&str>,
+        id_token: &str,
+        jwk: &JsonWebKey,
+        ephemeral_pubkey: &str,
+        ephemeral_salt: &str,
+        ephemeral_expiry: &str,
+    ) -> Result<MembershipProof, StealthnoteError> {
+        let domain = Self::check_claims(&DecodedJwt::decode(id_token)?)?;
+        let input = JwtCircuitInput::from_parts(
+            id_token,
+            jwk,
+            ephemeral_pubkey,
+            ephemeral_salt,
+            ephemeral_expiry,
+            &domain,
+        )?;
+        prove_jwt_membership(srs_path, &input, jwk, domain)
+    }
+
+    /// Verify a membership proof with the Google key `proofArgs.keyId`,
+    /// resolved through the global [`JwksStore`].
+    pub fn verify_membership(
+        proof: Vec<u8>,
+        anon_group_id: &str,
+        ephemeral_pubkey: &BigUint,
+        ephemeral_pubkey_expiry: &str,
+        proof_args: &HashMap<String, Vec<String>>,
+    ) -> Result<bool, StealthnoteError> {
+        verify_jwt_membership(
+            proof,
+            anon_group_id,
+            ephemeral_pubkey,
+            ephemeral_pubkey_expiry,
+            proof_args,
+            &JwksStore::global(),
+            GOOGLE_ISSUER,
+        )
+    }
+}
+
 impl AnonGroupProvider for GoogleOAuthProvider {
-    fn name() -> String {
-        "google-oauth".to_string()
+    fn name(&self) -> String {
+        Self::NAME.to_string()
     }
 
-    /** Slug is a key that represents the type of the AnonGroup identifier (to be used in URLs). Example: "domain" */
-    fn get_slug() -> String {
+    fn slug(&self) -> String {
         "domain".to_string()
     }
 
-    /**
-     * Generate a ZK proof that the current user is a member of an AnonGroup
-     * @param ephemeralPubkeyHash - Hash of the ephemeral pubkey, expiry and salt
-     * @returns Returns the AnonGroup and membership proof, along with additional args that may be needed for verification
-     */
+    /// `inputs`: `idToken`, `jwk` (the signing JsonWebKey as JSON) and
+    /// optionally `srsPath`.
     fn generate_proof(
-        ephemeral_key: EphemeralKey,
-        inputs: HashMap<String, Vec<String>>,
-    ) -> Vec<u8> {
-        // const JWT_SRS: &str = "../../../public/jwt-srs.local";
-        // prove_jwt(JWT_SRS.to_string(), inputs)
-        vec![]
-    }
-
-    /**
-     * Verify a ZK proof of group membership
-     * @param proof - The ZK proof to verify
-     * @param ephemeralPubkey - Pubkey modulus of the ephemeral key that was used when generating the proof
-     * @param anonGroup - AnonGroup that the proof claims membership in
-     * @param proofArgs - Additional args that was returned when the proof was generated
-     * @returns Promise resolving to true if the proof is valid
-     */
+        &self,
+        ephemeral_key: &EphemeralKey,
+        inputs: &HashMap<String, Vec<String>>,
+    ) -> Result<MembershipProof, StealthnoteError> {
+        let (jwk, srs_path) = jwt_proof_inputs(inputs)?;
+        Self::prove_membership(
+            srs_path,
+            proof_arg(inputs, "idToken")?,
+            &jwk,
+            &ephemeral_key.get_ephemeral_public_key(),
+            &ephemeral_key.salt,
+            &ephemeral_key.expiry,
+        )
+    }
+
     fn verify_proof(
+        &self,
         proof: Vec<u8>,
-        anon_group_id: String,
-        ephemeral_pubkey: BigUint,
-        ephemeral_pubkey_expiry: String,
-        proof_args: HashMap<String, Vec<String>>,
-    ) -> bool {
-        // const JWT_SRS: &str = "../../../public/jwt-srs.local";
-        // jwt_proof::verify_jwt(JWT_SRS.to_string(), proof)
-        true
-    }
-
-    /**
-     * Get the AnonGroup by its unique identifier
-     * @param groupId - Unique identifier for the AnonGroup
-     * @returns Promise resolving to the AnonGroup
-     */
-    fn get_anon_group(group_id: String) -> AnonGroup {
-        unimplemented!()
+        anon_group_id: &str,
+        ephemeral_pubkey: &BigUint,
+        ephemeral_pubkey_expiry: &str,
+        proof_args: &HashMap<String, Vec<String>>,
+    ) -> Result<bool, StealthnoteError> {
+        Self::verify_membership(
+            proof,
+            anon_group_id,
+            ephemeral_pubkey,
+            ephemeral_pubkey_expiry,
+            proof_args,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_server::provider::verify_jwt_membership_at;
+    use crate::proof::{jwks::FetchedJwks, jwt_input::JwtCircuitInput, rsa::RSA_LIMB_BITS};
+    use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+    use std::str::FromStr;
+
+    const SRS_PATH: &str = "public/jwt-srs.local";
+    /// `ephemeral_pubkey_expiry` of public/jwt_input.json
+    const EXPIRY: &str = "2025-05-06T05:44:54.000Z";
+    const BEFORE_EXPIRY: i64 = 1_746_000_000;
+
+    fn token(claims: serde_json::Value) -> DecodedJwt {
+        let encode = |value: serde_json::Value| BASE64_URL_SAFE_NO_PAD.encode(value.to_string());
+        let header = serde_json::json!({"alg": "RS256", "kid": "test", "typ": "JWT"});
+        DecodedJwt::decode(&format!("{}.{}.c2ln", encode(header), encode(claims))).unwrap()
+    }
+
+    /// The key public/jwt_input.json was signed with, as kid `test`.
+    fn input_key(input: &JwtCircuitInput) -> JsonWebKey {
+        let modulus = input
+            .jwt_pubkey_modulus_limbs
+            .iter()
+            .rev()
+            .fold(BigUint::default(), |acc, limb| {
+                (acc << RSA_LIMB_BITS) + BigUint::from_str(limb).unwrap()
+            });
+        JsonWebKey {
+            kid: "test".to_string(),
+            n: BASE64_URL_SAFE_NO_PAD.encode(modulus.to_bytes_be()),
+            use_: "sig".to_string(),
+            alg: "RS256".to_string(),
+            kty: "RSA".to_string(),
+            e: "AQAB".to_string(),
+        }
+    }
+
+    fn store_serving(keys: Vec<JsonWebKey>) -> JwksStore {
+        JwksStore::with_fetcher(
+            None,
+            Box::new(move |_| {
+                Ok(FetchedJwks {
+                    keys: keys.clone(),
+                    max_age: None,
+                })
+            }),
+        )
+    }
+
+    #[test]
+    fn test_check_claims() {
+        let claims = serde_json::json!({
+            "iss": "accounts.google.com",
+            "aud": "stealthnote",
+            "email": "alice@PSE.dev",
+            "email_verified": true,
+            "hd": "pse.dev",
+            "nonce": "123",
+            "iat": 1746004080,
+            "exp": 1746007680
+        });
+        assert_eq!(
+            GoogleOAuthProvider::check_claims(&token(claims.clone())).unwrap(),
+            "pse.dev"
+        );
+
+        let mut personal = claims.clone();
+        personal.as_object_mut().unwrap().remove("hd");
+        assert!(matches!(
+            GoogleOAuthProvider::check_claims(&token(personal)),
+            Err(StealthnoteError::Unsupported(_))
+        ));
+        let mut other_domain = claims.clone();
+        other_domain["email"] = "alice@evil.dev".into();
+        assert!(GoogleOAuthProvider::check_claims(&token(other_domain)).is_err());
+        let mut other_issuer = claims;
+        other_issuer["iss"] = "https://login.microsoftonline.com/common/v2.0".into();
+        assert!(GoogleOAuthProvider::check_claims(&token(other_issuer)).is_err());
+    }
+
+    #[test]
+    fn test_verify_rejects_bad_proof_args() {
+        let store = store_serving(vec![]);
+        let pubkey = BigUint::from(12345u64);
+        let args = |key_id: &str, version: &str| {
+            HashMap::from([
+                ("keyId".to_string(), vec![key_id.to_string()]),
+                ("jwtCircuitVersion".to_string(), vec![version.to_string()]),
+            ])
+        };
+        let verify = |expiry: &str, args: &HashMap<String, Vec<String>>| {
+            verify_jwt_membership_at(
+                vec![],
+                "pse.dev",
+                &pubkey,
+                expiry,
+                args,
+                &store,
+                GOOGLE_ISSUER,
+                None,
+                BEFORE_EXPIRY,
+            )
+        };
+
+        assert!(matches!(
+            verify("2025-04-30T00:00:00.000Z", &args("rotated", "0.3.1")),
+            Err(StealthnoteError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            verify(EXPIRY, &args("rotated", "0.0.1")),
+            Err(StealthnoteError::Unsupported(_))
+        ));
+        assert!(matches!(
+            verify(EXPIRY, &args("rotated", "0.3.1")),
+            Err(StealthnoteError::InvalidKey(_))
+        ));
+        assert!(matches!(
+            verify(EXPIRY, &HashMap::new()),
+            Err(StealthnoteError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_membership() {
+        let input = JwtCircuitInput::load("public/jwt_input.json").unwrap();
+        let jwk = input_key(&input);
+        let membership =
+            prove_jwt_membership(Some(SRS_PATH), &input, &jwk, "pse.dev".to_string()).unwrap();
+        let store = store_serving(vec![jwk]);
+        // The circuit input holds the pubkey shifted right by 3, as the nonce hashes it
+        let pubkey = BigUint::from_str(&input.ephemeral_pubkey).unwrap() << 3u8;
+        let verify = |proof: Vec<u8>, domain: &str, pubkey: &BigUint, now: i64| {
+            verify_jwt_membership_at(
+                proof,
+                domain,
+                pubkey,
+                EXPIRY,
+                &membership.proof_args,
+                &store,
+                GOOGLE_ISSUER,
+                Some(SRS_PATH),
+                now,
+            )
+        };
+
+        assert!(verify(membership.proof.clone(), "pse.dev", &pubkey, BEFORE_EXPIRY).unwrap());
+
+        // A proof only verifies for its own domain and ephemeral key
+        assert!(!matches!(
+            verify(membership.proof.clone(), "evil.dev", &pubkey, BEFORE_EXPIRY),
+            Ok(true)
+        ));
+        let other_pubkey = &pubkey + 1u8;
+        assert!(!matches!(
+            verify(
+                membership.proof.clone(),
+                "pse.dev",
+                &other_pubkey,
+                BEFORE_EXPIRY
+            ),
+            Ok(true)
+        ));
+        let mut tampered = membership.proof.clone();
+        tampered[100] ^= 1;
+        assert!(!matches!(
+            verify(tampered, "pse.dev", &pubkey, BEFORE_EXPIRY),
+            Ok(true)
+        ));
+
+        let expired = verify(
+            membership.proof,
+            "pse.dev",
+            &pubkey,
+            BEFORE_EXPIRY + 600_000,
+        );
+        assert!(matches!(expired, Err(StealthnoteError::InvalidInput(_))));
     }
 }
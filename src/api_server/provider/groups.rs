@@ -0,0 +1,279 @@
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    sync::{Arc, RwLock},
+};
+
+use super::AnonGroup;
+use crate::error::StealthnoteError;
+
+/// Groups shipped with the library, loaded by [`AnonGroupRegistry::bundled`].
+const BUNDLED_GROUPS: &str = include_str!("../../../public/anon_groups.json");
+
+static GLOBAL_REGISTRY: RwLock<Option<Arc<AnonGroupRegistry>>> = RwLock::new(None);
+
+/// Looks up groups the registry has no entry for, e.g. an organization
+/// directory service.
+#[uniffi::export(with_foreign)]
+pub trait AnonGroupSource: Send + Sync {
+    /// Metadata of `group_id` under `slug`, or `None` if the source doesn't
+    /// know it.
+    fn anon_group(&self, slug: String, group_id: String) -> Option<AnonGroup>;
+}
+
+/// One entry of a groups file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GroupEntry {
+    #[serde(default = "domain_slug")]
+    slug: String,
+    #[serde(flatten)]
+    group: AnonGroup,
+}
+
+#[derive(Debug, Deserialize)]
+struct GroupsFile {
+    groups: Vec<GroupEntry>,
+}
+
+fn domain_slug() -> String {
+    "domain".to_string()
+}
+
+/// Display metadata of AnonGroups by (slug, group id), so the same id can
+/// mean different groups to providers with different slugs.
+///
+/// Lookups try admin-curated entries, then entries loaded from JSON (bundled
+/// or files, later loads win), then the sources in the order they were added.
+/// Unknown groups fall back to their id as title and no logo.
+#[derive(uniffi::Object, Default)]
+pub struct AnonGroupRegistry {
+    curated: RwLock<BTreeMap<(String, String), AnonGroup>>,
+    loaded: RwLock<BTreeMap<(String, String), AnonGroup>>,
+    sources: RwLock<Vec<Arc<dyn AnonGroupSource>>>,
+}
+
+#[uniffi::export]
+impl AnonGroupRegistry {
+    #[uniffi::constructor]
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Registry holding the groups bundled with the library.
+    #[uniffi::constructor]
+    pub fn bundled() -> Arc<Self> {
+        let registry = Self::default();
+        registry
+            .load_json(BUNDLED_GROUPS)
+            .expect("bundled anon_groups.json is valid");
+        Arc::new(registry)
+    }
+
+    /// Add the groups of a JSON file (`{"groups": [{"slug", "id", "title",
+    /// "logoUrl"}]}`; `slug` defaults to `domain`). Returns how many were added.
+    pub fn load_file(&self, path: String) -> Result<u32, StealthnoteError> {
+        self.load_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn add_source(&self, source: Arc<dyn AnonGroupSource>) {
+        self.sources
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(source);
+    }
+
+    /// Add or replace an admin-curated group, which takes precedence over
+    /// every other source.
+    pub fn curate(&self, slug: String, group: AnonGroup) -> Result<(), StealthnoteError> {
+        if group.id.is_empty() || group.title.is_empty() {
+            return Err(StealthnoteError::InvalidInput(format!(
+                "AnonGroup {:?} needs an id and a title",
+                group.id
+            )));
+        }
+        let key = group_key(&slug, &group.id);
+        self.curated
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key, group);
+        Ok(())
+    }
+
+    /// Drop an admin-curated group. Returns whether there was one.
+    pub fn remove_curated(&self, slug: String, group_id: String) -> bool {
+        self.curated
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&group_key(&slug, &group_id))
+            .is_some()
+    }
+
+    /// The group `group_id` under `slug`, if any source knows it.
+    pub fn find(&self, slug: String, group_id: String) -> Option<AnonGroup> {
+        let key = group_key(&slug, &group_id);
+        let known = |groups: &RwLock<BTreeMap<(String, String), AnonGroup>>| {
+            groups
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .get(&key)
+                .cloned()
+        };
+        known(&self.curated)
+            .or_else(|| known(&self.loaded))
+            .or_else(|| {
+                let sources = self.sources.read().unwrap_or_else(|e| e.into_inner());
+                sources
+                    .iter()
+                    .find_map(|source| source.anon_group(key.0.clone(), key.1.clone()))
+            })
+    }
+
+    /// [`Self::find`], or a group titled by its (normalized) id, without a
+    /// logo.
+    pub fn get(&self, slug: String, group_id: String) -> AnonGroup {
+        let (_, id) = group_key(&slug, &group_id);
+        self.find(slug, group_id).unwrap_or_else(|| AnonGroup {
+            title: id.clone(),
+            logo_url: String::new(),
+            id,
+        })
+    }
+}
+
+impl AnonGroupRegistry {
+    /// The registry providers resolve groups with; [`Self::bundled`] unless
+    /// replaced with [`Self::set_global`].
+    pub fn global() -> Arc<AnonGroupRegistry> {
+        if let Some(registry) = &*GLOBAL_REGISTRY.read().unwrap_or_else(|e| e.into_inner()) {
+            return registry.clone();
+        }
+        GLOBAL_REGISTRY
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_or_insert_with(AnonGroupRegistry::bundled)
+            .clone()
+    }
+
+    pub fn set_global(registry: Arc<AnonGroupRegistry>) {
+        *GLOBAL_REGISTRY.write().unwrap_or_else(|e| e.into_inner()) = Some(registry);
+    }
+
+    fn load_json(&self, json: &str) -> Result<u32, StealthnoteError> {
+        let file: GroupsFile = serde_json::from_str(json)
+            .map_err(|e| StealthnoteError::InvalidInput(format!("AnonGroups file: {}", e)))?;
+        let count = file.groups.len() as u32;
+        let mut loaded = self.loaded.write().unwrap_or_else(|e| e.into_inner());
+        for entry in file.groups {
+            loaded.insert(group_key(&entry.slug, &entry.group.id), entry.group);
+        }
+        Ok(count)
+    }
+}
+
+/// Domains are case-insensitive; other ids are kept as they are.
+fn group_key(slug: &str, group_id: &str) -> (String, String) {
+    let id = if slug == "domain" {
+        group_id.to_ascii_lowercase()
+    } else {
+        group_id.to_string()
+    };
+    (slug.to_string(), id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Directory;
+
+    impl AnonGroupSource for Directory {
+        fn anon_group(&self, slug: String, group_id: String) -> Option<AnonGroup> {
+            (slug == "domain" && group_id == "acme.org").then(|| AnonGroup {
+                id: group_id,
+                title: "Acme Corp".to_string(),
+                logo_url: "https://directory.example/acme.png".to_string(),
+            })
+        }
+    }
+
+    #[test]
+    fn test_lookup_order() {
+        let registry = AnonGroupRegistry::bundled();
+        let pse = registry.get("domain".to_string(), "PSE.dev".to_string());
+        assert_eq!(pse.title, "Privacy & Scaling Explorations");
+        assert_eq!(pse.id, "pse.dev");
+
+        // Unknown domains fall back to the domain itself
+        let unknown = registry.get("domain".to_string(), "acme.org".to_string());
+        assert_eq!(unknown.title, "acme.org");
+        assert_eq!(unknown.logo_url, "");
+        let upper = registry.get("domain".to_string(), "Acme.ORG".to_string());
+        assert_eq!(upper, unknown);
+        assert!(registry
+            .find("tenant".to_string(), "pse.dev".to_string())
+            .is_none());
+
+        registry.add_source(Arc::new(Directory));
+        assert_eq!(
+            registry
+                .get("domain".to_string(), "acme.org".to_string())
+                .title,
+            "Acme Corp"
+        );
+
+        let curated = AnonGroup {
+            id: "pse.dev".to_string(),
+            title: "PSE".to_string(),
+            logo_url: "https://pse.dev/logo.svg".to_string(),
+        };
+        registry
+            .curate("domain".to_string(), curated.clone())
+            .unwrap();
+        assert_eq!(
+            registry.get("domain".to_string(), "pse.dev".to_string()),
+            curated
+        );
+        assert!(registry.remove_curated("domain".to_string(), "pse.dev".to_string()));
+        assert_eq!(
+            registry.get("domain".to_string(), "pse.dev".to_string()),
+            pse
+        );
+    }
+
+    #[test]
+    fn test_load_file() {
+        let path = std::env::temp_dir().join("stealthnote_anon_groups_test.json");
+        fs::write(
+            &path,
+            r#"{"groups": [
+                {"id": "acme.org", "title": "Acme", "logoUrl": ""},
+                {"slug": "tenant", "id": "72f988bf", "title": "Contoso", "logoUrl": ""}
+            ]}"#,
+        )
+        .unwrap();
+        let registry = AnonGroupRegistry::new();
+        assert_eq!(registry.load_file(path.display().to_string()).unwrap(), 2);
+        assert_eq!(
+            registry
+                .get("tenant".to_string(), "72f988bf".to_string())
+                .title,
+            "Contoso"
+        );
+        assert_eq!(
+            registry
+                .get("domain".to_string(), "acme.org".to_string())
+                .title,
+            "Acme"
+        );
+        let _ = fs::remove_file(path);
+
+        let nameless = AnonGroup {
+            id: "acme.org".to_string(),
+            title: String::new(),
+            logo_url: String::new(),
+        };
+        assert!(registry.curate("domain".to_string(), nameless).is_err());
+    }
+}
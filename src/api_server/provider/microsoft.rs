@@ -0,0 +1,355 @@
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::{
+    jwt_proof_inputs, proof_arg, prove_jwt_membership, verify_jwt_membership, AnonGroupProvider,
+    MembershipProof,
+};
+use crate::error::StealthnoteError;
+use crate::proof::{
+    ephemeral_key::EphemeralKey,
+    jwks::{JwksStore, MICROSOFT_ISSUER},
+    jwt::DecodedJwt,
+    jwt_input::JwtCircuitInput,
+    jwt_proof::JsonWebKey,
+};
+
+/// Tenant of personal Microsoft accounts (outlook.com, hotmail.com, ...).
+pub const CONSUMER_TENANT_ID: &str = "9188040d-6c67-4c5b-b112-36a304b66dad";
+
+/// Proves membership of a Microsoft 365 organization with an Entra ID (Azure
+/// AD) v2.0 ID token, using the same JWT circuit as Google sign-in.
+///
+/// The circuit discloses the email domain, so groups are domains. Entra lets
+/// tenant admins set `email` to any address, so only emails the tenant has
+/// verified are accepted. `tid` is checked before proving and returned as
+/// `proofArgs.tenantId`, but is not part of the proof, so verifiers can't
+/// rely on it.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct MicrosoftOAuthProvider {
+    /// Only prove membership with tokens from this tenant.
+    pub tenant_id: Option<String>,
+}
+
+/// The Entra-specific claims of an ID token.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct EntraClaims {
+    pub iss: String,
+    /// Directory (tenant) id of the organization that signed the user in.
+    pub tid: String,
+    /// Sign-in name, usually the UPN. Not necessarily a mailbox.
+    pub preferred_username: Option<String>,
+    pub email: Option<String>,
+    /// Whether the tenant verified the domain of `email`.
+    #[serde(default)]
+    pub xms_edov: Option<bool>,
+}
+
+impl MicrosoftOAuthProvider {
+    pub const NAME: &'static str = "microsoft-oauth";
+
+    /// Provider proving for the members of one tenant only, to register in
+    /// place of the default one.
+    pub fn for_tenant(tenant_id: String) -> Self {
+        MicrosoftOAuthProvider {
+            tenant_id: Some(tenant_id),
+        }
+    }
+
+    /// Check the Entra claims of `jwt` and return the email domain to prove,
+    /// optionally pinning the tenant.
+    pub fn check_claims(
+        jwt: &DecodedJwt,
+        expected_tenant_id: Option<&str>,
+    ) -> Result<(EntraClaims, String), StealthnoteError> {
+        let claims: EntraClaims = jwt.claims_as()?;
+        if claims.tid.eq_ignore_ascii_case(CONSUMER_TENANT_ID) {
+            return Err(StealthnoteError::Unsupported(
+                "personal Microsoft accounts do not belong to an organization".to_string(),
+            ));
+        }
+        if let Some(expected) = expected_tenant_id {
+            if !claims.tid.eq_ignore_ascii_case(expected) {
+                return Err(StealthnoteError::InvalidJwt(format!(
+                    "tenant {} does not match {}",
+                    claims.tid, expected
+                )));
+            }
+        }
+        // v1.0 tokens (sts.windows.net) lay out claims differently
+        let issuer = format!("https://login.microsoftonline.com/{}/v2.0", claims.tid);
+        if claims.iss != issuer {
+            return Err(StealthnoteError::InvalidJwt(format!(
+                "issuer {} is not {}",
+                claims.iss, issuer
+            )));
+        }
+
+        // The circuit reads `email` and `email_verified`; `preferred_username`
+        // can't stand in for them.
+        let Some(email) = &claims.email else {
+            return Err(StealthnoteError::Unsupported(format!(
+                "ID token for {} has no email claim; add it as an optional claim of the app registration",
+                claims.preferred_username.as_deref().unwrap_or("this account")
+            )));
+        };
+        if jwt.claims.email_verified != Some(true) && claims.xms_edov != Some(true) {
+            return Err(StealthnoteError::InvalidJwt(format!(
+                "email {} is not verified by the tenant",
+                email
+            )));
+        }
+        if jwt.claims.email_verified.is_none() {
+            return Err(StealthnoteError::Unsupported(
+                "ID token has no email_verified claim, which the JWT circuit requires".to_string(),
+            ));
+        }
+        let domain = match email.rsplit_once('@') {
+            Some((_, domain)) if !domain.is_empty() => domain.to_ascii_lowercase(),
+            _ => {
+                return Err(StealthnoteError::InvalidJwt(format!(
+                    "email {} has no domain",
+                    email
+                )))
+            }
+        };
+        Ok((claims, domain))
+    }
+
+    /// Prove membership of the token's email domain. Returns the stripped
+    /// proof with `keyId`, `jwtCircuitVersion` and `tenantId` proof args.
+    #[allow(clippy::too_many_arguments)]
+    pub fn prove_membership(
+        srs_path: Option<&str>,
+        id_token: &str,
+        jwk: &JsonWebKey,
+        ephemeral_pubkey: &str,
+        ephemeral_salt: &str,
+        ephemeral_expiry: &str,
+        expected_tenant_id: Option<&str>,
+    ) -> Result<MembershipProof, StealthnoteError> {
+        let jwt = DecodedJwt::decode(id_token)?;
+        let (claims, domain) = Self::check_claims(&jwt, expected_tenant_id)?;
+        let input = JwtCircuitInput::from_parts(
+            id_token,
+            jwk,
+            ephemeral_pubkey,
+            ephemeral_salt,
+            ephemeral_expiry,
+            &domain,
+        )?;
+        let mut membership = prove_jwt_membership(srs_path, &input, jwk, domain)?;
+        membership
+            .proof_args
+            .insert("tenantId".to_string(), vec![claims.tid]);
+        Ok(membership)
+    }
+
+    /// Check a membership proof against Microsoft's current signing keys.
+    pub fn verify_membership(
+        proof: Vec<u8>,
+        anon_group_id: &str,
+        ephemeral_pubkey: &BigUint,
+        ephemeral_pubkey_expiry: &str,
+        proof_args: &HashMap<String, Vec<String>>,
+    ) -> Result<bool, StealthnoteError> {
+        Self::verify_with_store(
+            proof,
+            anon_group_id,
+            ephemeral_pubkey,
+            ephemeral_pubkey_expiry,
+            proof_args,
+            &JwksStore::global(),
+        )
+    }
+
+    /// [`Self::verify_membership`] with the signing key looked up in `store`.
+    pub fn verify_with_store(
+        proof: Vec<u8>,
+        anon_group_id: &str,
+        ephemeral_pubkey: &BigUint,
+        ephemeral_pubkey_expiry: &str,
+        proof_args: &HashMap<String, Vec<String>>,
+        store: &JwksStore,
+    ) -> Result<bool, StealthnoteError> {
+        verify_jwt_membership(
+            proof,
+            anon_group_id,
+            ephemeral_pubkey,
+            ephemeral_pubkey_expiry,
+            proof_args,
+            store,
+            MICROSOFT_ISSUER,
+        )
+    }
+}
+
+impl AnonGroupProvider for MicrosoftOAuthProvider {
+    fn name(&self) -> String {
+        Self::NAME.to_string()
+    }
+
+    fn slug(&self) -> String {
+        "domain".to_string()
+    }
+
+    /// `inputs`: `idToken`, `jwk` (the signing JsonWebKey as JSON) and
+    /// optionally `srsPath`. Tokens must come from the pinned tenant, if any.
+    fn generate_proof(
+        &self,
+        ephemeral_key: &EphemeralKey,
+        inputs: &HashMap<String, Vec<String>>,
+    ) -> Result<MembershipProof, StealthnoteError> {
+        let (jwk, srs_path) = jwt_proof_inputs(inputs)?;
+        Self::prove_membership(
+            srs_path,
+            proof_arg(inputs, "idToken")?,
+            &jwk,
+            &ephemeral_key.get_ephemeral_public_key(),
+            &ephemeral_key.salt,
+            &ephemeral_key.expiry,
+            self.tenant_id.as_deref(),
+        )
+    }
+
+    fn verify_proof(
+        &self,
+        proof: Vec<u8>,
+        anon_group_id: &str,
+        ephemeral_pubkey: &BigUint,
+        ephemeral_pubkey_expiry: &str,
+        proof_args: &HashMap<String, Vec<String>>,
+    ) -> Result<bool, StealthnoteError> {
+        Self::verify_membership(
+            proof,
+            anon_group_id,
+            ephemeral_pubkey,
+            ephemeral_pubkey_expiry,
+            proof_args,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::jwks::FetchedJwks;
+    use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+
+    const TENANT_ID: &str = "72f988bf-86f1-41af-91ab-2d7cd011db47";
+
+    fn entra_token(claims: serde_json::Value) -> DecodedJwt {
+        let encode = |value: serde_json::Value| BASE64_URL_SAFE_NO_PAD.encode(value.to_string());
+        let header =
+            serde_json::json!({"alg": "RS256", "kid": "CNv0OI3RwqlHFEVnaoMAshCH2XE", "typ": "JWT"});
+        let token = format!("{}.{}.c2ln", encode(header), encode(claims));
+        DecodedJwt::decode(&token).unwrap()
+    }
+
+    fn claims(tid: &str) -> serde_json::Value {
+        serde_json::json!({
+            "iss": format!("https://login.microsoftonline.com/{}/v2.0", tid),
+            "aud": "6731de76-14a6-49ae-97bc-6eba6914391e",
+            "tid": tid,
+            "preferred_username": "alice@contoso.onmicrosoft.com",
+            "email": "alice@Contoso.com",
+            "email_verified": true,
+            "nonce": "123",
+            "iat": 1746004080,
+            "exp": 1746007680
+        })
+    }
+
+    #[test]
+    fn test_entra_claims() {
+        let jwt = entra_token(claims(TENANT_ID));
+        let (entra, domain) = MicrosoftOAuthProvider::check_claims(&jwt, None).unwrap();
+        assert_eq!(entra.tid, TENANT_ID);
+        assert_eq!(
+            entra.preferred_username.as_deref(),
+            Some("alice@contoso.onmicrosoft.com")
+        );
+        assert_eq!(domain, "contoso.com");
+        assert!(
+            MicrosoftOAuthProvider::check_claims(&jwt, Some(&TENANT_ID.to_uppercase())).is_ok()
+        );
+
+        let other_tenant = MicrosoftOAuthProvider::check_claims(
+            &jwt,
+            Some("00000000-0000-0000-0000-000000000000"),
+        );
+        assert!(matches!(other_tenant, Err(StealthnoteError::InvalidJwt(_))));
+
+        let mut v1 = claims(TENANT_ID);
+        v1["iss"] = format!("https://sts.windows.net/{}/", TENANT_ID).into();
+        let issuer = MicrosoftOAuthProvider::check_claims(&entra_token(v1), None).unwrap_err();
+        assert!(issuer.to_string().contains("sts.windows.net"));
+
+        let consumer = entra_token(claims(CONSUMER_TENANT_ID));
+        assert!(matches!(
+            MicrosoftOAuthProvider::check_claims(&consumer, None),
+            Err(StealthnoteError::Unsupported(_))
+        ));
+
+        let mut no_email = claims(TENANT_ID);
+        no_email.as_object_mut().unwrap().remove("email");
+        let err = MicrosoftOAuthProvider::check_claims(&entra_token(no_email), None).unwrap_err();
+        assert!(err.to_string().contains("alice@contoso.onmicrosoft.com"));
+
+        let mut unverified = claims(TENANT_ID);
+        unverified["email_verified"] = false.into();
+        assert!(matches!(
+            MicrosoftOAuthProvider::check_claims(&entra_token(unverified.clone()), None),
+            Err(StealthnoteError::InvalidJwt(_))
+        ));
+        unverified.as_object_mut().unwrap().remove("email_verified");
+        assert!(matches!(
+            MicrosoftOAuthProvider::check_claims(&entra_token(unverified.clone()), None),
+            Err(StealthnoteError::InvalidJwt(_))
+        ));
+        // A verified domain without `email_verified` still can't be proven
+        unverified["xms_edov"] = true.into();
+        assert!(matches!(
+            MicrosoftOAuthProvider::check_claims(&entra_token(unverified), None),
+            Err(StealthnoteError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_requires_known_key() {
+        let store = JwksStore::with_fetcher(
+            None,
+            Box::new(|_| {
+                Ok(FetchedJwks {
+                    keys: vec![],
+                    max_age: None,
+                })
+            }),
+        );
+        let pubkey = BigUint::from(12345u64);
+        let args = HashMap::from([
+            ("keyId".to_string(), vec!["rotated".to_string()]),
+            ("jwtCircuitVersion".to_string(), vec!["0.3.1".to_string()]),
+        ]);
+        let result = MicrosoftOAuthProvider::verify_with_store(
+            vec![],
+            "contoso.com",
+            &pubkey,
+            "2099-01-01T00:00:00.000Z",
+            &args,
+            &store,
+        );
+        assert!(matches!(result, Err(StealthnoteError::InvalidKey(_))));
+
+        let result = MicrosoftOAuthProvider::verify_with_store(
+            vec![],
+            "contoso.com",
+            &pubkey,
+            "2099-01-01T00:00:00.000Z",
+            &HashMap::new(),
+            &store,
+        );
+        assert!(matches!(result, Err(StealthnoteError::InvalidInput(_))));
+    }
+}
@@ -0,0 +1,465 @@
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::Path,
+    sync::Arc,
+};
+
+use super::{
+    jwt_proof_inputs, proof_arg, prove_jwt_membership, verify_jwt_membership, AnonGroupProvider,
+    MembershipProof, ProviderRegistry,
+};
+use crate::error::StealthnoteError;
+use crate::proof::{
+    ephemeral_key::EphemeralKey,
+    jwks::JwksStore,
+    jwt::DecodedJwt,
+    jwt_input::{JwtCircuitInput, JwtInputOptions},
+    jwt_proof::JsonWebKey,
+};
+
+/// Names taken by the built-in providers.
+const RESERVED_NAMES: [&str; 3] = ["google-oauth", "microsoft-oauth", "email"];
+
+/// Which claim of the ID token names the AnonGroup.
+///
+/// The JWT circuit only discloses the email domain, so the group id is always
+/// that domain; `Hd` and `Claim` additionally require the claim to agree.
+#[derive(uniffi::Enum, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GroupClaim {
+    /// Google-style hosted domain
+    Hd,
+    /// Domain part of `email`
+    EmailDomain,
+    /// A string claim of the issuer's own, e.g. a Keycloak `org_domain` mapper
+    Claim { name: String },
+}
+
+/// One OpenID Connect issuer, as listed in an issuers config file.
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OidcIssuerConfig {
+    /// Provider name, e.g. `okta-acme`. Members name it as `Provider::Registered`.
+    pub name: String,
+    /// Expected `iss`, exactly as the issuer sends it.
+    pub issuer: String,
+    /// JWKS endpoint, or a local JWKS file.
+    pub jwks_uri: String,
+    pub group_claim: GroupClaim,
+    /// First claim of the signed data the circuit hashes; `None` keeps the
+    /// circuit's default (email, email_verified, nonce).
+    #[serde(default)]
+    pub precompute_claims: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IssuersFile {
+    issuers: Vec<OidcIssuerConfig>,
+}
+
+/// Proves membership with an ID token from any configured OIDC issuer
+/// (Okta, Auth0, Keycloak, GitLab, ...) through the JWT circuit.
+#[derive(Debug, Clone)]
+pub struct GenericOidcProvider {
+    pub config: OidcIssuerConfig,
+}
+
+impl GenericOidcProvider {
+    pub fn new(config: OidcIssuerConfig) -> Result<Self, StealthnoteError> {
+        let valid_name = !config.name.is_empty()
+            && config
+                .name
+                .bytes()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-');
+        if !valid_name || RESERVED_NAMES.contains(&config.name.as_str()) {
+            return Err(StealthnoteError::InvalidInput(format!(
+                "OIDC provider name {:?} must be lowercase letters, digits and dashes, and not a built-in provider",
+                config.name
+            )));
+        }
+        if config.issuer.is_empty() || config.jwks_uri.is_empty() {
+            return Err(StealthnoteError::InvalidInput(format!(
+                "OIDC provider {} needs an issuer and a JWKS location",
+                config.name
+            )));
+        }
+        Ok(GenericOidcProvider { config })
+    }
+
+    /// Check the issuer and group claim of `jwt`; returns the group id.
+    pub fn group_id(&self, jwt: &DecodedJwt) -> Result<String, StealthnoteError> {
+        if jwt.claims.iss != self.config.issuer {
+            return Err(StealthnoteError::InvalidJwt(format!(
+                "issuer {} is not {}",
+                jwt.claims.iss, self.config.issuer
+            )));
+        }
+        let email_domain = match jwt.claims.email.as_deref().and_then(|e| e.rsplit_once('@')) {
+            Some((_, domain)) if !domain.is_empty() => domain.to_ascii_lowercase(),
+            _ => {
+                return Err(StealthnoteError::InvalidJwt(
+                    "missing email claim".to_string(),
+                ))
+            }
+        };
+        let (name, group) = match &self.config.group_claim {
+            GroupClaim::EmailDomain => return Ok(email_domain),
+            GroupClaim::Hd => ("hd", jwt.claims.hd.clone()),
+            GroupClaim::Claim { name } => {
+                let claims: serde_json::Map<String, serde_json::Value> = jwt.claims_as()?;
+                let value = claims
+                    .get(name)
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string);
+                (name.as_str(), value)
+            }
+        };
+        match group {
+            Some(group) if group.eq_ignore_ascii_case(&email_domain) => Ok(email_domain),
+            Some(group) => Err(StealthnoteError::InvalidJwt(format!(
+                "{} {} does not match the email domain {}",
+                name, group, email_domain
+            ))),
+            None => Err(StealthnoteError::InvalidJwt(format!(
+                "missing {} claim",
+                name
+            ))),
+        }
+    }
+
+    /// Prove membership of the token's group. Returns the stripped proof with
+    /// `keyId`, `jwtCircuitVersion` and `issuer` proof args.
+    pub fn prove_membership(
+        &self,
+        srs_path: Option<&str>,
+        id_token: &str,
+        jwk: &JsonWebKey,
+        ephemeral_pubkey: &str,
+        ephemeral_salt: &str,
+        ephemeral_expiry: &str,
+    ) -> Result<MembershipProof, StealthnoteError> {
+        let group_id = self.group_id(&DecodedJwt::decode(id_token)?)?;
+        let options = JwtInputOptions {
+            precompute_claims: self.config.precompute_claims.clone(),
+            max_signed_data_length: None,
+        };
+        let input = JwtCircuitInput::from_parts_with_options(
+            id_token,
+            jwk,
+            ephemeral_pubkey,
+            ephemeral_salt,
+            ephemeral_expiry,
+            &group_id,
+            &options,
+        )?;
+        let mut membership = prove_jwt_membership(srs_path, &input, jwk, group_id)?;
+        membership
+            .proof_args
+            .insert("issuer".to_string(), vec![self.config.issuer.clone()]);
+        Ok(membership)
+    }
+
+    /// Check a membership proof against the issuer's signing keys, as cached
+    /// by the global [`JwksStore`].
+    pub fn verify_membership(
+        &self,
+        proof: Vec<u8>,
+        anon_group_id: &str,
+        ephemeral_pubkey: &BigUint,
+        ephemeral_pubkey_expiry: &str,
+        proof_args: &HashMap<String, Vec<String>>,
+    ) -> Result<bool, StealthnoteError> {
+        self.verify_with_store(
+            proof,
+            anon_group_id,
+            ephemeral_pubkey,
+            ephemeral_pubkey_expiry,
+            proof_args,
+            &JwksStore::global(),
+        )
+    }
+
+    /// [`Self::verify_membership`] with the signing key looked up in `store`,
+    /// which learns the issuer's JWKS location if it doesn't have its keys.
+    pub fn verify_with_store(
+        &self,
+        proof: Vec<u8>,
+        anon_group_id: &str,
+        ephemeral_pubkey: &BigUint,
+        ephemeral_pubkey_expiry: &str,
+        proof_args: &HashMap<String, Vec<String>>,
+        store: &JwksStore,
+    ) -> Result<bool, StealthnoteError> {
+        let config = &self.config;
+        // Fixture keys stay offline; other stores may not know the issuer yet
+        if store.keys(config.issuer.clone()).is_empty() {
+            store.add_issuer(config.issuer.clone(), config.jwks_uri.clone());
+        }
+        verify_jwt_membership(
+            proof,
+            anon_group_id,
+            ephemeral_pubkey,
+            ephemeral_pubkey_expiry,
+            proof_args,
+            store,
+            &config.issuer,
+        )
+    }
+}
+
+impl AnonGroupProvider for GenericOidcProvider {
+    fn name(&self) -> String {
+        self.config.name.clone()
+    }
+
+    fn slug(&self) -> String {
+        "domain".to_string()
+    }
+
+    /// `inputs`: `idToken`, `jwk` (the signing JsonWebKey as JSON) and
+    /// optionally `srsPath`.
+    fn generate_proof(
+        &self,
+        ephemeral_key: &EphemeralKey,
+        inputs: &HashMap<String, Vec<String>>,
+    ) -> Result<MembershipProof, StealthnoteError> {
+        let (jwk, srs_path) = jwt_proof_inputs(inputs)?;
+        self.prove_membership(
+            srs_path,
+            proof_arg(inputs, "idToken")?,
+            &jwk,
+            &ephemeral_key.get_ephemeral_public_key(),
+            &ephemeral_key.salt,
+            &ephemeral_key.expiry,
+        )
+    }
+
+    fn verify_proof(
+        &self,
+        proof: Vec<u8>,
+        anon_group_id: &str,
+        ephemeral_pubkey: &BigUint,
+        ephemeral_pubkey_expiry: &str,
+        proof_args: &HashMap<String, Vec<String>>,
+    ) -> Result<bool, StealthnoteError> {
+        self.verify_membership(
+            proof,
+            anon_group_id,
+            ephemeral_pubkey,
+            ephemeral_pubkey_expiry,
+            proof_args,
+        )
+    }
+
+    fn oidc_config(&self) -> Option<OidcIssuerConfig> {
+        Some(self.config.clone())
+    }
+}
+
+/// Register an issuer, replacing any with the same name.
+pub fn register_issuer(config: OidcIssuerConfig) -> Result<(), StealthnoteError> {
+    add(GenericOidcProvider::new(config)?);
+    Ok(())
+}
+
+/// Register every issuer of a config file (`{"issuers": [...]}`) and return
+/// their names. Nothing is registered if any entry is invalid.
+pub fn load_issuers(path: impl AsRef<Path>) -> Result<Vec<String>, StealthnoteError> {
+    let file: IssuersFile = serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| StealthnoteError::InvalidInput(format!("OIDC issuers config: {}", e)))?;
+    let providers = file
+        .issuers
+        .into_iter()
+        .map(GenericOidcProvider::new)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut names = BTreeSet::new();
+    if let Some(duplicate) = providers
+        .iter()
+        .find(|provider| !names.insert(&provider.config.name))
+    {
+        return Err(StealthnoteError::InvalidInput(format!(
+            "OIDC issuers config lists {} twice",
+            duplicate.config.name
+        )));
+    }
+    Ok(providers
+        .into_iter()
+        .map(|provider| {
+            let name = provider.config.name.clone();
+            add(provider);
+            name
+        })
+        .collect())
+}
+
+/// Make `provider`'s keys and memberships verifiable. Can't fail once
+/// [`GenericOidcProvider::new`] accepted the config.
+fn add(provider: GenericOidcProvider) {
+    let config = &provider.config;
+    JwksStore::global().add_issuer(config.issuer.clone(), config.jwks_uri.clone());
+    ProviderRegistry::global()
+        .register(Arc::new(provider))
+        .expect("OIDC provider names are not empty");
+}
+
+/// The OIDC issuer registered as `name` in the global [`ProviderRegistry`].
+pub fn issuer(name: &str) -> Result<GenericOidcProvider, StealthnoteError> {
+    ProviderRegistry::global()
+        .get(name)?
+        .oidc_config()
+        .map(|config| GenericOidcProvider { config })
+        .ok_or_else(|| StealthnoteError::Unsupported(format!("{} is not an OIDC provider", name)))
+}
+
+/// Every OIDC issuer in the global [`ProviderRegistry`].
+pub fn issuers() -> Vec<OidcIssuerConfig> {
+    let registry = ProviderRegistry::global();
+    registry
+        .names()
+        .iter()
+        .filter_map(|name| registry.get(name).ok()?.oidc_config())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+
+    fn token(claims: serde_json::Value) -> DecodedJwt {
+        let encode = |value: serde_json::Value| BASE64_URL_SAFE_NO_PAD.encode(value.to_string());
+        let header = serde_json::json!({"alg": "RS256", "kid": "test", "typ": "JWT"});
+        DecodedJwt::decode(&format!("{}.{}.c2ln", encode(header), encode(claims))).unwrap()
+    }
+
+    fn provider(group_claim: GroupClaim) -> GenericOidcProvider {
+        GenericOidcProvider::new(OidcIssuerConfig {
+            name: "keycloak-acme".to_string(),
+            issuer: "https://sso.acme.org/realms/staff".to_string(),
+            jwks_uri: "https://sso.acme.org/realms/staff/protocol/openid-connect/certs".to_string(),
+            group_claim,
+            precompute_claims: None,
+        })
+        .unwrap()
+    }
+
+    fn claims() -> serde_json::Value {
+        serde_json::json!({
+            "iss": "https://sso.acme.org/realms/staff",
+            "aud": "stealthnote",
+            "email": "bob@Acme.org",
+            "email_verified": true,
+            "hd": "acme.org",
+            "org_domain": "acme.org",
+            "nonce": "123",
+            "iat": 1746004080,
+            "exp": 1746007680
+        })
+    }
+
+    #[test]
+    fn test_group_claim() {
+        let jwt = token(claims());
+        assert_eq!(
+            provider(GroupClaim::EmailDomain).group_id(&jwt).unwrap(),
+            "acme.org"
+        );
+        assert_eq!(provider(GroupClaim::Hd).group_id(&jwt).unwrap(), "acme.org");
+        let custom = provider(GroupClaim::Claim {
+            name: "org_domain".to_string(),
+        });
+        assert_eq!(custom.group_id(&jwt).unwrap(), "acme.org");
+
+        let mut other_org = claims();
+        other_org["org_domain"] = "evil.org".into();
+        let err = custom.group_id(&token(other_org)).unwrap_err();
+        assert!(err.to_string().contains("org_domain evil.org"));
+
+        let missing = provider(GroupClaim::Claim {
+            name: "tenant".to_string(),
+        });
+        assert!(missing.group_id(&jwt).is_err());
+
+        let mut other_issuer = claims();
+        other_issuer["iss"] = "https://accounts.google.com".into();
+        assert!(matches!(
+            provider(GroupClaim::Hd).group_id(&token(other_issuer)),
+            Err(StealthnoteError::InvalidJwt(_))
+        ));
+    }
+
+    #[test]
+    fn test_load_issuers() {
+        let names = load_issuers("public/oidc_issuers.json").unwrap();
+        assert_eq!(
+            names,
+            ["okta-acme", "auth0-acme", "keycloak-acme", "gitlab"]
+        );
+        let gitlab = issuer("gitlab").unwrap();
+        assert_eq!(gitlab.config.issuer, "https://gitlab.com");
+        assert_eq!(gitlab.config.group_claim, GroupClaim::EmailDomain);
+        assert_eq!(
+            issuer("keycloak-acme").unwrap().config.group_claim,
+            GroupClaim::Claim {
+                name: "org_domain".to_string()
+            }
+        );
+        assert!(issuers().iter().any(|config| config.name == "okta-acme"));
+        assert_eq!(
+            ProviderRegistry::global().get("gitlab").unwrap().slug(),
+            "domain"
+        );
+        assert!(matches!(
+            issuer("ping"),
+            Err(StealthnoteError::Unsupported(_))
+        ));
+        assert!(matches!(
+            issuer("google-oauth"),
+            Err(StealthnoteError::Unsupported(_))
+        ));
+
+        let mut reserved = provider(GroupClaim::Hd).config;
+        reserved.name = "google-oauth".to_string();
+        assert!(register_issuer(reserved).is_err());
+
+        // A bad entry leaves every issuer of the file unregistered
+        let path = std::env::temp_dir().join("stealthnote_oidc_issuers_test.json");
+        let mut config = provider(GroupClaim::Hd).config;
+        config.name = "keycloak-staging".to_string();
+        let file = serde_json::json!({"issuers": [config.clone(), config]});
+        fs::write(&path, file.to_string()).unwrap();
+        assert!(load_issuers(&path).is_err());
+        assert!(issuer("keycloak-staging").is_err());
+        assert!(ProviderRegistry::global().get("keycloak-staging").is_err());
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_verify_with_another_store() {
+        let store = JwksStore::with_fetcher(
+            None,
+            Box::new(|_| {
+                Ok(crate::proof::jwks::FetchedJwks {
+                    keys: vec![],
+                    max_age: None,
+                })
+            }),
+        );
+        let args = HashMap::from([
+            ("keyId".to_string(), vec!["rotated".to_string()]),
+            ("jwtCircuitVersion".to_string(), vec!["0.3.1".to_string()]),
+        ]);
+        let result = provider(GroupClaim::Hd).verify_with_store(
+            vec![],
+            "acme.org",
+            &BigUint::from(12345u64),
+            "2099-01-01T00:00:00.000Z",
+            &args,
+            &store,
+        );
+        assert!(matches!(result, Err(StealthnoteError::InvalidKey(_))));
+    }
+}
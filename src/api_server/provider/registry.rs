@@ -0,0 +1,194 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, OnceLock, RwLock},
+};
+
+use super::{AnonGroupProvider, EmailProvider, GoogleOAuthProvider, MicrosoftOAuthProvider};
+use crate::error::StealthnoteError;
+
+/// Membership providers by name. Registering a provider under a taken name
+/// replaces it, e.g. to pin Microsoft sign-in to one tenant.
+///
+/// The registry also holds the SRS file memberships are verified with. It is
+/// verifier configuration: proof args never choose it.
+#[derive(Default)]
+pub struct ProviderRegistry {
+    providers: RwLock<BTreeMap<String, Arc<dyn AnonGroupProvider>>>,
+    srs_path: RwLock<Option<String>>,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry holding Google, Microsoft and email sign-in.
+    pub fn with_builtins() -> Self {
+        let registry = Self::default();
+        let builtins: [Arc<dyn AnonGroupProvider>; 3] = [
+            Arc::new(GoogleOAuthProvider),
+            Arc::new(MicrosoftOAuthProvider::default()),
+            Arc::new(EmailProvider),
+        ];
+        for provider in builtins {
+            registry
+                .register(provider)
+                .expect("built-in providers are named");
+        }
+        registry
+    }
+
+    /// The registry memberships and messages are verified with. Starts with
+    /// the built-in providers; OIDC issuers add themselves when registered.
+    pub fn global() -> &'static ProviderRegistry {
+        static REGISTRY: OnceLock<ProviderRegistry> = OnceLock::new();
+        REGISTRY.get_or_init(ProviderRegistry::with_builtins)
+    }
+
+    pub fn register(&self, provider: Arc<dyn AnonGroupProvider>) -> Result<(), StealthnoteError> {
+        let name = provider.name();
+        if name.is_empty() || provider.slug().is_empty() {
+            return Err(StealthnoteError::InvalidInput(format!(
+                "provider {:?} needs a name and a slug",
+                name
+            )));
+        }
+        self.providers
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name, provider);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<Arc<dyn AnonGroupProvider>, StealthnoteError> {
+        self.providers
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name)
+            .cloned()
+            .ok_or_else(|| StealthnoteError::Unsupported(format!("no provider named {}", name)))
+    }
+
+    /// Every provider whose AnonGroups are identified by `slug`.
+    pub fn with_slug(&self, slug: &str) -> Vec<Arc<dyn AnonGroupProvider>> {
+        self.providers
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .filter(|provider| provider.slug() == slug)
+            .cloned()
+            .collect()
+    }
+
+    /// Verify memberships with the SRS file at `srs_path`, or with bb's
+    /// default SRS for `None`.
+    pub fn set_srs_path(&self, srs_path: Option<String>) {
+        *self.srs_path.write().unwrap_or_else(|e| e.into_inner()) = srs_path;
+    }
+
+    pub fn srs_path(&self) -> Option<String> {
+        self.srs_path
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.providers
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .keys()
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_server::{AnonGroup, MembershipProof};
+    use crate::proof::ephemeral_key::EphemeralKey;
+    use num_bigint::BigUint;
+    use std::collections::HashMap;
+
+    /// A provider for people who can name a secret handle.
+    struct Handle {
+        secret: String,
+    }
+
+    impl AnonGroupProvider for Handle {
+        fn name(&self) -> String {
+            "handle".to_string()
+        }
+
+        fn slug(&self) -> String {
+            "handle".to_string()
+        }
+
+        fn generate_proof(
+            &self,
+            _ephemeral_key: &EphemeralKey,
+            inputs: &HashMap<String, Vec<String>>,
+        ) -> Result<MembershipProof, StealthnoteError> {
+            Ok(MembershipProof {
+                proof: self.secret.as_bytes().to_vec(),
+                group_id: inputs["handle"][0].clone(),
+                proof_args: HashMap::new(),
+            })
+        }
+
+        fn verify_proof(
+            &self,
+            proof: Vec<u8>,
+            _anon_group_id: &str,
+            _ephemeral_pubkey: &BigUint,
+            _ephemeral_pubkey_expiry: &str,
+            _proof_args: &HashMap<String, Vec<String>>,
+        ) -> Result<bool, StealthnoteError> {
+            Ok(proof == self.secret.as_bytes())
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let registry = ProviderRegistry::with_builtins();
+        assert_eq!(
+            registry.names(),
+            ["email", "google-oauth", "microsoft-oauth"]
+        );
+        assert_eq!(registry.with_slug("domain").len(), 3);
+        assert_eq!(registry.srs_path(), None);
+        registry.set_srs_path(Some("public/jwt-srs.local".to_string()));
+        assert_eq!(registry.srs_path().as_deref(), Some("public/jwt-srs.local"));
+        assert!(matches!(
+            registry.get("handle"),
+            Err(StealthnoteError::Unsupported(_))
+        ));
+
+        registry
+            .register(Arc::new(Handle {
+                secret: "hunter2".to_string(),
+            }))
+            .unwrap();
+        let handle = registry.get("handle").unwrap();
+        let key = EphemeralKey::generate_ephemeral_key().unwrap();
+        let inputs = HashMap::from([("handle".to_string(), vec!["@alice".to_string()])]);
+        let membership = handle.generate_proof(&key, &inputs).unwrap();
+        let pubkey = BigUint::from(1u8);
+        let args = HashMap::new();
+        assert!(handle
+            .verify_proof(membership.proof, "@alice", &pubkey, "", &args)
+            .unwrap());
+        assert!(!handle
+            .verify_proof(b"letmein".to_vec(), "@alice", &pubkey, "", &args)
+            .unwrap());
+        assert_eq!(
+            handle.get_anon_group("@alice"),
+            AnonGroup {
+                id: "@alice".to_string(),
+                title: "@alice".to_string(),
+                logo_url: String::new(),
+            }
+        );
+    }
+}
@@ -0,0 +1,40 @@
+use thiserror::Error;
+
+/// Error returned by every exported function, surfaced as a catchable
+/// exception in the generated Swift/Kotlin/Dart bindings.
+#[derive(Debug, Error, uniffi::Error)]
+#[uniffi(flat_error)]
+pub enum StealthnoteError {
+    #[error("Invalid JWT: {0}")]
+    InvalidJwt(String),
+    #[error("Invalid key material: {0}")]
+    InvalidKey(String),
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+    #[error("SRS file not found: {0}")]
+    SrsMissing(String),
+    #[error("Circuit mismatch: {0}")]
+    CircuitMismatch(String),
+    #[error("Proof failed: {0}")]
+    ProofFailed(String),
+    #[error("Unsupported: {0}")]
+    Unsupported(String),
+    #[error("Storage error: {0}")]
+    Storage(String),
+    #[error("Network error: {0}")]
+    Network(String),
+    #[error("Cancelled")]
+    Cancelled,
+}
+
+impl From<reqwest::Error> for StealthnoteError {
+    fn from(e: reqwest::Error) -> Self {
+        StealthnoteError::Network(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for StealthnoteError {
+    fn from(e: std::io::Error) -> Self {
+        StealthnoteError::Storage(e.to_string())
+    }
+}
@@ -2,8 +2,8 @@
 // write some functions and bind them to FFI type
 mopro_ffi::app!();
 
-use api_server::Member;
-use chrono::{DateTime, Utc};
+use api_server::{EmailProvider, Member, MicrosoftOAuthProvider, SignedMessage};
+pub use error::StealthnoteError;
 use noir::{
     barretenberg::{
         prove::prove_ultra_honk,
@@ -14,114 +14,305 @@ use noir::{
     witness::from_vec_str_to_witness_map,
 };
 use num_bigint::BigUint;
+use proof::cache::CircuitHandle;
+use proof::circuit::CircuitArtifact;
+use proof::dkim::parse_dkim_key;
 use proof::ephemeral_key::EphemeralKey;
-use proof::jwt_proof::{generate_inputs, generate_jwt_proof, JsonWebKey, StorageBlock};
-use std::{collections::HashMap, str::FromStr};
+use proof::jwt::DecodedJwt;
+use proof::jwt_input::JwtCircuitInput;
+use proof::jwt_proof::{generate_jwt_proof, generate_jwt_proof_with_progress, JsonWebKey};
+use proof::progress::{run_blocking, ProofProgress, ProofStage};
+use proof::versions::JwtCircuitVersion;
+use proof::zkemail_input::ZkEmailCircuitInput;
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+
+pub use api_server::{
+    AnonGroup, AnonGroupProvider, AnonGroupRegistry, AnonGroupSource, GroupClaim, MembershipProof,
+    OidcIssuerConfig, ProviderRegistry,
+};
+pub use proof::batch::{BatchVerdict, JwtProofItem};
+pub use proof::cache::BuiltinCircuit;
+pub use proof::circuit::ArtifactPin;
+pub use proof::dkim_registry::{DkimKeyRecord, DkimKeyRegistry, DkimKeyResolver};
+pub use proof::envelope::ProofEnvelope;
+pub use proof::jwks::JwksStore;
+pub use proof::jwks_archive::{ArchivedJwk, JwksArchive};
+pub use proof::jwt::{JwtClaims, JwtHeader};
+pub use proof::jwt_input::JwtInputOptions;
+pub use proof::progress::{CancellationToken, ProofProgressListener};
+pub use proof::public_inputs::{JwtProofParts, JwtPublicInputs};
+pub use proof::vk::VerificationKey;
+pub use proof::zkemail_proof::ZkEmailClaims;
 
 mod api_server;
+mod error;
 mod proof;
 
 #[uniffi::export]
-pub fn prove() -> bool {
+pub fn prove() -> Result<bool, StealthnoteError> {
     const BYTECODE: &str = "H4sIAAAAAAAA/62QQQqAMAwErfigpEna5OZXLLb/f4KKLZbiTQdCQg7Dsm66mc9x00O717rhG9ico5cgMOfoMxJu4C2pAEsKioqisnslysoaLVkEQ6aMRYxKFc//ZYQr29L10XfhXv4jB52E+OpMAQAA";
 
     // Setup the SRS
     // You can provide a path to the SRS transcript file as second argument
     // Otherwise it will be downloaded automatically from Aztec's servers
-    setup_srs_from_bytecode(BYTECODE, None, false).unwrap();
+    setup_srs_from_bytecode(BYTECODE, None, false)
+        .map_err(|e| StealthnoteError::SrsMissing(e.to_string()))?;
     // Alternatively, if you know the circuit size, you can use the following function
     // Assuming the circuit size is 40 here
-    setup_srs(40, None).unwrap();
+    setup_srs(40, None).map_err(|e| StealthnoteError::SrsMissing(e.to_string()))?;
 
     // Set up your witness
     // a = 5, b = 6, res = a * b = 30
-    let initial_witness = from_vec_str_to_witness_map(vec!["5", "6", "0x1e"]).unwrap();
+    let initial_witness = from_vec_str_to_witness_map(vec!["5", "6", "0x1e"])
+        .map_err(|e| StealthnoteError::CircuitMismatch(e.to_string()))?;
 
     // Start timing the proof generation
     let start = std::time::Instant::now();
     // Generate the proof
     // It returns the proof
-    let proof = prove_ultra_honk(BYTECODE, initial_witness, false).unwrap();
+    let proof = prove_ultra_honk(BYTECODE, initial_witness, false)
+        .map_err(|e| StealthnoteError::ProofFailed(e.to_string()))?;
     // Print the time it took to generate the proof
     println!("Proof generation time: {:?}", start.elapsed());
 
     // Get the verification key
-    let vk = get_honk_verification_key(BYTECODE, false).unwrap();
+    let vk = get_honk_verification_key(BYTECODE, false)
+        .map_err(|e| StealthnoteError::ProofFailed(e.to_string()))?;
 
     // Verify the proof
-    let verdict = verify_ultra_honk(proof, vk).unwrap();
+    let verdict =
+        verify_ultra_honk(proof, vk).map_err(|e| StealthnoteError::ProofFailed(e.to_string()))?;
     // Print the verdict
     println!("Proof verification verdict: {}", verdict);
-    return verdict;
-}
-
-#[uniffi::export]
-pub fn prove_zkemail(srs_path: String, inputs: HashMap<String, Vec<String>>) -> Vec<u8> {
-    const ZKEMAIL_JSON: &str = include_str!("../circuit/zkemail_test.json");
-    let bytecode_json: serde_json::Value = serde_json::from_str(&ZKEMAIL_JSON).unwrap();
-    let bytecode = bytecode_json["bytecode"].as_str().unwrap();
-
-    // Setup SRS
-    setup_srs_from_bytecode(bytecode, Some(&srs_path), false).unwrap();
-
-    // Define the expected order of witness values based on the ZkEmailInput struct
-    let witness_key_order = [
-        "header_storage",
-        "header_len",
-        "pubkey_modulus",
-        "pubkey_redc",
-        "signature",
-        "date_index",
-        "subject_index",
-        "subject_length",
-        "from_header_index",
-        "from_header_length",
-        "from_address_index",
-        "from_address_length",
-    ];
-
-    let mut witness_vec_string: Vec<String> = Vec::new();
-    for key in witness_key_order {
-        match inputs.get(key) {
-            Some(values) => witness_vec_string.extend(values.iter().cloned()),
-            None => panic!("Missing required input key in HashMap: {}", key),
+    Ok(verdict)
+}
+
+pub const ZKEMAIL_CIRCUIT_JSON: &str = include_str!("../circuit/zkemail_test.json");
+
+// `prove_zkemail` callers name the `Sequence` params without the `_sequence` suffix
+const ZKEMAIL_LEGACY_KEYS: [(&str, &str); 6] = [
+    ("subject_index", "subject_sequence_index"),
+    ("subject_length", "subject_sequence_length"),
+    ("from_header_index", "from_header_sequence_index"),
+    ("from_header_length", "from_header_sequence_length"),
+    ("from_address_index", "from_address_sequence_index"),
+    ("from_address_length", "from_address_sequence_length"),
+];
+
+#[uniffi::export]
+pub fn prove_zkemail(
+    srs_path: String,
+    inputs: HashMap<String, Vec<String>>,
+) -> Result<Vec<u8>, StealthnoteError> {
+    let circuit = CircuitHandle::zkemail()?;
+
+    let mut inputs = inputs;
+    for (legacy, key) in ZKEMAIL_LEGACY_KEYS {
+        if let Some(values) = inputs.remove(legacy) {
+            inputs.insert(key.to_string(), values);
         }
     }
 
-    // Convert Vec<String> to Vec<&str> for the function call
-    let witness_vec_str: Vec<&str> = witness_vec_string.iter().map(AsRef::as_ref).collect();
+    let witness = circuit.artifact.witness_from_map(&inputs)?;
+    circuit.prove_witness(Some(&srs_path), witness)
+}
 
-    let initial_witness = from_vec_str_to_witness_map(witness_vec_str).unwrap();
+/// zkEmail circuit inputs (JSON) for a raw `.eml`, checked against the
+/// sender's DKIM key: a `v=DKIM1; ... p=...` TXT record or its base64 key.
+#[uniffi::export]
+pub fn zkemail_input_from_eml(
+    eml: Vec<u8>,
+    dkim_public_key: String,
+) -> Result<String, StealthnoteError> {
+    let key = parse_dkim_key(&dkim_public_key)?;
+    ZkEmailCircuitInput::from_eml(&eml, &key)?.to_json()
+}
 
-    // Start timing the proof generation
-    let start = std::time::Instant::now();
-    let proof = prove_ultra_honk(bytecode, initial_witness, false).unwrap();
+/// [`zkemail_input_from_eml`] with the DKIM key looked up in `registry`.
+#[uniffi::export]
+pub fn zkemail_input_from_eml_with_registry(
+    eml: Vec<u8>,
+    registry: Arc<DkimKeyRegistry>,
+) -> Result<String, StealthnoteError> {
+    ZkEmailCircuitInput::from_eml_with_registry(&eml, &registry)?.to_json()
+}
 
-    println!("Proof generation time: {:?}", start.elapsed());
+#[uniffi::export]
+pub fn prove_zkemail_from_eml(
+    srs_path: String,
+    eml: Vec<u8>,
+    dkim_public_key: String,
+) -> Result<Vec<u8>, StealthnoteError> {
+    let key = parse_dkim_key(&dkim_public_key)?;
+    let input = ZkEmailCircuitInput::from_eml(&eml, &key)?;
+    let circuit = CircuitHandle::zkemail()?;
+    circuit.prove_witness(Some(&srs_path), input.to_witness()?)
+}
 
-    proof
+#[uniffi::export]
+pub fn verify_zkemail(srs_path: String, proof: Vec<u8>) -> Result<bool, StealthnoteError> {
+    let circuit = CircuitHandle::zkemail()?;
+    circuit.verify(Some(&srs_path), proof)
 }
 
+/// Prove membership of the sender's domain with a DKIM-signed email whose
+/// subject contains the ephemeral pubkey hash. The result holds what
+/// `create_membership` needs for a `Provider::Email` member.
 #[uniffi::export]
-pub fn verify_zkemail(srs_path: String, proof: Vec<u8>) -> bool {
-    const ZKEMAIL_JSON: &str = include_str!("../circuit/zkemail_test.json");
-    let bytecode_json: serde_json::Value = serde_json::from_str(&ZKEMAIL_JSON).unwrap();
-    let bytecode = bytecode_json["bytecode"].as_str().unwrap();
+pub fn prove_email_membership(
+    srs_path: String,
+    eml: Vec<u8>,
+    dkim_public_key: String,
+    ephemeral_pubkey: String,
+    ephemeral_salt: String,
+    ephemeral_expiry: String,
+) -> Result<MembershipProof, StealthnoteError> {
+    let parse = |value: &str, what: &str| {
+        BigUint::from_str(value)
+            .map_err(|e| StealthnoteError::InvalidKey(format!("{} {}: {}", what, value, e)))
+    };
+    EmailProvider::prove_membership(
+        Some(&srs_path),
+        &eml,
+        &parse_dkim_key(&dkim_public_key)?,
+        &parse(&ephemeral_pubkey, "ephemeral pubkey")?,
+        &parse(&ephemeral_salt, "ephemeral salt")?,
+        api_server::parse_expiry(&ephemeral_expiry)?,
+    )
+}
 
-    // Setup SRS
-    setup_srs_from_bytecode(bytecode, Some(&srs_path), false).unwrap();
+/// Verify a zkEmail proof and decode what it attests to, optionally pinning
+/// the DKIM key hash and sender domain (mismatches are errors).
+#[uniffi::export]
+pub fn verify_zkemail_proof(
+    srs_path: String,
+    proof: Vec<u8>,
+    expected_dkim_pubkey_hash: Option<String>,
+    expected_from_domain: Option<String>,
+) -> Result<ZkEmailClaims, StealthnoteError> {
+    proof::zkemail_proof::verify_zkemail_proof(
+        Some(&srs_path),
+        proof,
+        expected_dkim_pubkey_hash.as_deref(),
+        expected_from_domain.as_deref(),
+    )
+}
 
-    // Get the verification key
-    let vk = get_honk_verification_key(bytecode, false).unwrap();
+/// Decode what a zkEmail proof attests to, without verifying it.
+#[uniffi::export]
+pub fn decode_zkemail_proof(proof: Vec<u8>) -> Result<ZkEmailClaims, StealthnoteError> {
+    ZkEmailClaims::from_bb_proof(&proof)
+}
 
-    // Start timing the proof verification
-    let start = std::time::Instant::now();
-    let verdict = verify_ultra_honk(proof, vk).unwrap();
+#[uniffi::export]
+pub fn verify_zkemail_with_vk(
+    srs_path: String,
+    vk: Vec<u8>,
+    expected_vk_hash: Option<String>,
+    proof: Vec<u8>,
+) -> Result<bool, StealthnoteError> {
+    proof::vk::verify_with_vk(Some(&srs_path), vk, expected_vk_hash.as_deref(), proof)
+}
 
-    println!("Proof verification time: {:?}", start.elapsed());
-    println!("Proof verification verdict: {}", verdict);
+//
+// Verification keys
+//
+
+#[uniffi::export]
+pub fn export_jwt_vk(srs_path: String) -> Result<VerificationKey, StealthnoteError> {
+    VerificationKey::jwt(Some(&srs_path))
+}
+
+#[uniffi::export]
+pub fn export_zkemail_vk(srs_path: String) -> Result<VerificationKey, StealthnoteError> {
+    VerificationKey::zkemail(Some(&srs_path))
+}
+
+#[uniffi::export]
+pub fn save_verification_key(vk: VerificationKey, path: String) -> Result<(), StealthnoteError> {
+    vk.save(path)
+}
+
+/// Load a key written by [`save_verification_key`]. Fails if the file was
+/// altered or its hash differs from `expected_vk_hash`.
+#[uniffi::export]
+pub fn load_verification_key(
+    path: String,
+    expected_vk_hash: Option<String>,
+) -> Result<VerificationKey, StealthnoteError> {
+    VerificationKey::load(path, expected_vk_hash.as_deref())
+}
 
-    verdict
+#[uniffi::export]
+pub fn verification_key_hash(vk: Vec<u8>) -> String {
+    proof::vk::vk_hash(&vk)
+}
+
+/// Load the SRS and derive the verification keys of the built-in circuits
+/// ahead of time, e.g. at app start, so the first prove/verify is not slowed down.
+#[uniffi::export]
+pub fn prewarm_circuits(srs_path: String) -> Result<(), StealthnoteError> {
+    proof::cache::prewarm(Some(&srs_path))
+}
+
+/// Replace a built-in circuit with an artifact file (`nargo compile` JSON),
+/// checked against `pin`. Returns the loaded artifact's hash.
+#[uniffi::export]
+pub fn load_circuit_artifact(
+    circuit: BuiltinCircuit,
+    path: String,
+    pin: ArtifactPin,
+) -> Result<u64, StealthnoteError> {
+    let artifact = CircuitArtifact::load_pinned(path, &pin)?;
+    let hash = artifact.hash;
+    circuit.set_override(Some(artifact));
+    Ok(hash)
+}
+
+#[uniffi::export]
+pub fn load_circuit_artifact_bytes(
+    circuit: BuiltinCircuit,
+    artifact: Vec<u8>,
+    pin: ArtifactPin,
+) -> Result<u64, StealthnoteError> {
+    let artifact = CircuitArtifact::from_bytes_pinned(&artifact, &pin)?;
+    let hash = artifact.hash;
+    circuit.set_override(Some(artifact));
+    Ok(hash)
+}
+
+/// Go back to the circuit compiled into the library.
+#[uniffi::export]
+pub fn reset_circuit_artifact(circuit: BuiltinCircuit) {
+    circuit.set_override(None);
+}
+
+//
+// Generic circuit functions
+//
+
+/// Prove any compiled Noir circuit. `artifact` is the `nargo compile` JSON and
+/// `inputs` a JSON object keyed by the circuit's parameter names.
+#[uniffi::export]
+pub fn prove_circuit(
+    srs_path: String,
+    artifact: String,
+    inputs: String,
+) -> Result<Vec<u8>, StealthnoteError> {
+    let circuit = CircuitHandle::from_json(&artifact)?;
+    let inputs: serde_json::Value = serde_json::from_str(&inputs)
+        .map_err(|e| StealthnoteError::InvalidInput(format!("circuit inputs: {}", e)))?;
+    circuit.prove(Some(&srs_path), &inputs)
+}
+
+#[uniffi::export]
+pub fn verify_circuit(
+    srs_path: String,
+    artifact: String,
+    proof: Vec<u8>,
+) -> Result<bool, StealthnoteError> {
+    let circuit = CircuitHandle::from_json(&artifact)?;
+    circuit.verify(Some(&srs_path), proof)
 }
 
 //
@@ -136,119 +327,251 @@ pub fn prove_jwt(
     token_id: String,
     jwt: String, // jwt is a stringified JsonWebKey
     domain: String,
-) -> Vec<u8> {
-    let circuit_input = generate_inputs(
+) -> Result<Vec<u8>, StealthnoteError> {
+    let jwk: JsonWebKey = serde_json::from_str(&jwt)
+        .map_err(|e| StealthnoteError::InvalidKey(format!("JsonWebKey: {}", e)))?;
+    let input = JwtCircuitInput::from_parts(
+        &token_id,
+        &jwk,
+        &ephemeral_pubkey,
+        &ephemeral_salt,
+        &ephemeral_expiry,
+        &domain,
+    )?;
+
+    generate_jwt_proof(srs_path, &input)
+}
+
+/// [`prove_jwt`] with a custom disclosed claim set or signed data limit, or
+/// the full signed data for circuits that take it (empty `precompute_claims`).
+#[uniffi::export]
+#[allow(clippy::too_many_arguments)]
+pub fn prove_jwt_with_options(
+    srs_path: String,
+    ephemeral_pubkey: String,
+    ephemeral_salt: String,
+    ephemeral_expiry: String,
+    token_id: String,
+    jwt: String,
+    domain: String,
+    options: JwtInputOptions,
+) -> Result<Vec<u8>, StealthnoteError> {
+    let jwk: JsonWebKey = serde_json::from_str(&jwt)
+        .map_err(|e| StealthnoteError::InvalidKey(format!("JsonWebKey: {}", e)))?;
+    let input = JwtCircuitInput::from_parts_with_options(
         &token_id,
-        &serde_json::from_str(&jwt).unwrap(),
-        Some(vec!["email", "email_verified", "nonce"]),
-        640,
+        &jwk,
+        &ephemeral_pubkey,
+        &ephemeral_salt,
+        &ephemeral_expiry,
+        &domain,
+        &options,
+    )?;
+
+    generate_jwt_proof(srs_path, &input)
+}
+
+/// Decode an ID token's claims without checking its signature.
+#[uniffi::export]
+pub fn decode_jwt(token_id: String) -> Result<JwtClaims, StealthnoteError> {
+    Ok(DecodedJwt::decode(&token_id)?.claims)
+}
+
+/// Run the checks [`prove_jwt`] makes before proving (RS256 signature, key id,
+/// nonce binding to the ephemeral key, email domain) and return the claims.
+#[uniffi::export]
+pub fn preflight_jwt(
+    ephemeral_pubkey: String,
+    ephemeral_salt: String,
+    ephemeral_expiry: String,
+    token_id: String,
+    jwt: String,
+    domain: String,
+) -> Result<JwtClaims, StealthnoteError> {
+    let jwk: JsonWebKey = serde_json::from_str(&jwt)
+        .map_err(|e| StealthnoteError::InvalidKey(format!("JsonWebKey: {}", e)))?;
+    JwtCircuitInput::from_parts(
+        &token_id,
+        &jwk,
+        &ephemeral_pubkey,
+        &ephemeral_salt,
+        &ephemeral_expiry,
+        &domain,
+    )?;
+    decode_jwt(token_id)
+}
+
+/// Prove membership of a Microsoft 365 organization's email domain with an
+/// Entra ID token, optionally pinned to `tenant_id`. The result holds what
+/// `create_membership` needs for a `Provider::Microsoft` member.
+#[uniffi::export]
+#[allow(clippy::too_many_arguments)]
+pub fn prove_microsoft_membership(
+    srs_path: String,
+    ephemeral_pubkey: String,
+    ephemeral_salt: String,
+    ephemeral_expiry: String,
+    token_id: String,
+    jwt: String,
+    tenant_id: Option<String>,
+) -> Result<MembershipProof, StealthnoteError> {
+    let jwk: JsonWebKey = serde_json::from_str(&jwt)
+        .map_err(|e| StealthnoteError::InvalidKey(format!("JsonWebKey: {}", e)))?;
+    MicrosoftOAuthProvider::prove_membership(
+        Some(&srs_path),
+        &token_id,
+        &jwk,
+        &ephemeral_pubkey,
+        &ephemeral_salt,
+        &ephemeral_expiry,
+        tenant_id.as_deref(),
     )
-    .unwrap();
-
-    let mut inputs: HashMap<String, Vec<String>> = HashMap::new();
-    inputs.insert(
-        "partial_data_storage".to_string(),
-        circuit_input
-            .partial_data
-            .as_ref()
-            .unwrap()
-            .storage
-            .iter()
-            .map(|b| b.to_string())
-            .collect(),
-    );
-    inputs.insert(
-        "partial_data_len".to_string(),
-        vec![circuit_input.partial_data.unwrap().len.to_string()],
-    );
-
-    // let partial_hash = partial_hash_to_u32_words(circuit_input.partial_hash.unwrap().as_ref());
-    inputs.insert(
-        "partial_hash".to_string(),
-        circuit_input
-            .partial_hash
-            .unwrap()
-            .iter()
-            .map(|i| i.to_string())
-            .collect(),
-    );
-    inputs.insert(
-        "full_data_length".to_string(),
-        vec![circuit_input.full_data_length.unwrap().to_string()],
-    );
-    inputs.insert(
-        "base64_decode_offset".to_string(),
-        vec![circuit_input.base64_decode_offset.to_string()],
-    );
-    inputs.insert(
-        "jwt_pubkey_modulus_limbs".to_string(),
-        circuit_input
-            .pubkey_modulus_limbs
-            .iter()
-            .map(|i| i.to_string())
-            .collect(),
-    );
-    inputs.insert(
-        "jwt_pubkey_redc_params_limbs".to_string(),
-        circuit_input
-            .redc_params_limbs
-            .iter()
-            .map(|i| i.to_string())
-            .collect(),
-    );
-    inputs.insert(
-        "jwt_signature_limbs".to_string(),
-        circuit_input
-            .signature_limbs
-            .iter()
-            .map(|i| i.to_string())
-            .collect(),
-    );
-
-    let bignuint_public_key = BigUint::from_str(&ephemeral_pubkey).unwrap();
-    // Equivalent to JavaScript: publicKey >> 3n
-    let shifted_public_key = &bignuint_public_key >> 3u32;
-    inputs.insert(
-        "ephemeral_pubkey".to_string(),
-        vec![shifted_public_key.to_string()],
-    );
-
-    inputs.insert(
-        "ephemeral_pubkey_salt".to_string(),
-        vec![ephemeral_salt.to_string()],
-    );
-
-    // Example: parse an ISO8601 datetime string
-    let expiry: DateTime<Utc> = ephemeral_expiry.parse().unwrap();
-
-    // Get UNIX timestamp in seconds (already floored)
-    let timestamp_secs = expiry.timestamp();
-    inputs.insert(
-        "ephemeral_pubkey_expiry".to_string(),
-        vec![timestamp_secs.to_string()],
-    );
-
-    let field = encode_domain_field(domain.as_str(), 64);
-    inputs.insert(
-        "domain_storage".to_string(),
-        field.storage.iter().map(|b| b.to_string()).collect(),
-    );
-    inputs.insert("domain_len".to_string(), vec![field.len.to_string()]);
-
-    generate_jwt_proof(srs_path, inputs)
-}
-
-fn encode_domain_field(domain: &str, fixed_len: usize) -> StorageBlock {
-    let mut bytes = domain.as_bytes().to_vec();
-    let original_len = bytes.len();
-
-    // padding 0 to fixed length
-    bytes.resize(fixed_len, 0);
-
-    StorageBlock {
-        storage: bytes,
-        len: original_len,
-    }
+}
+
+/// Register the OIDC issuers listed in a config file (`{"issuers": [...]}`)
+/// and return their provider names.
+#[uniffi::export]
+pub fn register_oidc_issuers(config_path: String) -> Result<Vec<String>, StealthnoteError> {
+    api_server::oidc::load_issuers(config_path)
+}
+
+#[uniffi::export]
+pub fn register_oidc_issuer(config: OidcIssuerConfig) -> Result<(), StealthnoteError> {
+    api_server::oidc::register_issuer(config)
+}
+
+#[uniffi::export]
+pub fn oidc_issuers() -> Vec<OidcIssuerConfig> {
+    api_server::oidc::issuers()
+}
+
+/// Prove membership with an ID token from the OIDC issuer registered as
+/// `provider_name` in the default [`ProviderRegistry`], for a
+/// `Provider::Registered` member.
+#[uniffi::export]
+#[allow(clippy::too_many_arguments)]
+pub fn prove_oidc_membership(
+    srs_path: String,
+    provider_name: String,
+    ephemeral_pubkey: String,
+    ephemeral_salt: String,
+    ephemeral_expiry: String,
+    token_id: String,
+    jwt: String,
+) -> Result<MembershipProof, StealthnoteError> {
+    let jwk: JsonWebKey = serde_json::from_str(&jwt)
+        .map_err(|e| StealthnoteError::InvalidKey(format!("JsonWebKey: {}", e)))?;
+    api_server::oidc::issuer(&provider_name)?.prove_membership(
+        Some(&srs_path),
+        &token_id,
+        &jwk,
+        &ephemeral_pubkey,
+        &ephemeral_salt,
+        &ephemeral_expiry,
+    )
+}
+
+/// Display name and logo of the AnonGroup `group_id` of `provider`, from the
+/// default [`AnonGroupRegistry`] unless the provider knows better.
+#[uniffi::export]
+pub fn get_anon_group(
+    provider: api_server::Provider,
+    group_id: String,
+) -> Result<AnonGroup, StealthnoteError> {
+    Ok(provider.resolve()?.get_anon_group(&group_id))
+}
+
+/// Only prove Microsoft membership for tenant `tenant_id` from now on.
+/// Proofs don't bind the tenant, so verification can't enforce this.
+#[uniffi::export]
+pub fn pin_microsoft_tenant(tenant_id: String) -> Result<(), StealthnoteError> {
+    ProviderRegistry::global().register(Arc::new(MicrosoftOAuthProvider::for_tenant(tenant_id)))
+}
+
+/// Verify memberships with the SRS file at `srs_path` from now on, or with
+/// bb's default SRS for `None`.
+#[uniffi::export]
+pub fn set_membership_srs_path(srs_path: Option<String>) {
+    ProviderRegistry::global().set_srs_path(srs_path)
+}
+
+/// Names of the providers memberships can be created with.
+#[uniffi::export]
+pub fn anon_group_providers() -> Vec<String> {
+    ProviderRegistry::global().names()
+}
+
+#[uniffi::export]
+pub fn default_anon_group_registry() -> Arc<AnonGroupRegistry> {
+    AnonGroupRegistry::global()
+}
+
+/// Resolve groups with `registry` from now on, e.g. one with admin-curated
+/// entries or a directory source.
+#[uniffi::export]
+pub fn set_default_anon_group_registry(registry: Arc<AnonGroupRegistry>) {
+    AnonGroupRegistry::set_global(registry)
+}
+
+/// [`prove_jwt`] reporting each stage to `listener`; stops with
+/// `StealthnoteError::Cancelled` once `cancel` is triggered.
+#[uniffi::export]
+#[allow(clippy::too_many_arguments)]
+pub fn prove_jwt_with_progress(
+    srs_path: String,
+    ephemeral_pubkey: String,
+    ephemeral_salt: String,
+    ephemeral_expiry: String,
+    token_id: String,
+    jwt: String,
+    domain: String,
+    listener: Option<Arc<dyn ProofProgressListener>>,
+    cancel: Option<Arc<CancellationToken>>,
+) -> Result<Vec<u8>, StealthnoteError> {
+    let progress = ProofProgress::new(listener, cancel);
+    progress.stage(ProofStage::InputGeneration)?;
+    let jwk: JsonWebKey = serde_json::from_str(&jwt)
+        .map_err(|e| StealthnoteError::InvalidKey(format!("JsonWebKey: {}", e)))?;
+    let input = JwtCircuitInput::from_parts(
+        &token_id,
+        &jwk,
+        &ephemeral_pubkey,
+        &ephemeral_salt,
+        &ephemeral_expiry,
+        &domain,
+    )?;
+
+    generate_jwt_proof_with_progress(srs_path, &input, &progress)
+}
+
+/// Non-blocking [`prove_jwt_with_progress`].
+#[uniffi::export]
+#[allow(clippy::too_many_arguments)]
+pub async fn prove_jwt_async(
+    srs_path: String,
+    ephemeral_pubkey: String,
+    ephemeral_salt: String,
+    ephemeral_expiry: String,
+    token_id: String,
+    jwt: String,
+    domain: String,
+    listener: Option<Arc<dyn ProofProgressListener>>,
+    cancel: Option<Arc<CancellationToken>>,
+) -> Result<Vec<u8>, StealthnoteError> {
+    run_blocking(move || {
+        prove_jwt_with_progress(
+            srs_path,
+            ephemeral_pubkey,
+            ephemeral_salt,
+            ephemeral_expiry,
+            token_id,
+            jwt,
+            domain,
+            listener,
+            cancel,
+        )
+    })
+    .await
 }
 
 #[uniffi::export]
@@ -259,7 +582,7 @@ fn verify_jwt_proof(
     google_jwt_pubkey_modulus: String,
     ephemeral_pubkey: String,
     ephemeral_pubkey_expiry: String,
-) -> bool {
+) -> Result<bool, StealthnoteError> {
     proof::jwt_proof::verify_jwt_proof(
         srs_path,
         proof,
@@ -270,8 +593,190 @@ fn verify_jwt_proof(
     )
 }
 
+/// Non-blocking [`verify_jwt_proof`].
+#[uniffi::export]
+pub async fn verify_jwt_proof_async(
+    srs_path: String,
+    proof: Vec<u8>,
+    domain: String,
+    google_jwt_pubkey_modulus: String,
+    ephemeral_pubkey: String,
+    ephemeral_pubkey_expiry: String,
+) -> Result<bool, StealthnoteError> {
+    run_blocking(move || {
+        proof::jwt_proof::verify_jwt_proof(
+            srs_path,
+            proof,
+            domain,
+            google_jwt_pubkey_modulus,
+            ephemeral_pubkey,
+            ephemeral_pubkey_expiry,
+        )
+    })
+    .await
+}
+
+/// Verify many membership proofs concurrently on at most `max_threads`
+/// threads (default: up to 4), one verdict per item, in order.
+#[uniffi::export]
+pub fn verify_jwt_proofs_batch(
+    srs_path: String,
+    items: Vec<JwtProofItem>,
+    max_threads: Option<u32>,
+) -> Vec<BatchVerdict> {
+    proof::batch::verify_jwt_proofs_batch(&srs_path, items, max_threads.map(|n| n as usize))
+}
+
+/// Verify a stored membership proof with the circuit named by its
+/// `proofArgs.jwtCircuitVersion`, so proofs from older circuits stay verifiable.
 #[uniffi::export]
-pub fn generate_ephemeral_key() -> String {
+pub fn verify_jwt_proof_versioned(
+    srs_path: String,
+    jwt_circuit_version: String,
+    proof: Vec<u8>,
+    domain: String,
+    google_jwt_pubkey_modulus: String,
+    ephemeral_pubkey: String,
+    ephemeral_pubkey_expiry: String,
+) -> Result<bool, StealthnoteError> {
+    JwtCircuitVersion::get(&jwt_circuit_version)?.verify(
+        Some(&srs_path),
+        proof,
+        domain,
+        google_jwt_pubkey_modulus,
+        ephemeral_pubkey,
+        ephemeral_pubkey_expiry,
+    )
+}
+
+/// [`verify_jwt_proof_versioned`] with the issuer's signing key `key_id`
+/// resolved through the default [`JwksStore`] instead of passed in.
+#[uniffi::export]
+#[allow(clippy::too_many_arguments)]
+pub fn verify_jwt_proof_with_key_id(
+    srs_path: String,
+    issuer: String,
+    key_id: String,
+    jwt_circuit_version: String,
+    proof: Vec<u8>,
+    domain: String,
+    ephemeral_pubkey: String,
+    ephemeral_pubkey_expiry: String,
+) -> Result<bool, StealthnoteError> {
+    let expiry = api_server::parse_expiry(&ephemeral_pubkey_expiry)?;
+    let jwk = JwksStore::global().key_for_proof(issuer, key_id, i64::from(expiry))?;
+    JwtCircuitVersion::get(&jwt_circuit_version)?.verify(
+        Some(&srs_path),
+        proof,
+        domain,
+        jwk.n,
+        ephemeral_pubkey,
+        ephemeral_pubkey_expiry,
+    )
+}
+
+/// The DKIM keys email memberships are verified against.
+#[uniffi::export]
+pub fn default_dkim_registry() -> Arc<DkimKeyRegistry> {
+    DkimKeyRegistry::global()
+}
+
+/// Verify email memberships against `registry` from now on, e.g. one loaded
+/// from a snapshot or with a DNS resolver set.
+#[uniffi::export]
+pub fn set_default_dkim_registry(registry: Arc<DkimKeyRegistry>) {
+    DkimKeyRegistry::set_global(registry)
+}
+
+/// The key store membership verification resolves `proofArgs.keyId` with.
+#[uniffi::export]
+pub fn default_jwks_store() -> Arc<JwksStore> {
+    JwksStore::global()
+}
+
+/// Verify with `store` from now on, e.g. one opened on a cache file or
+/// loaded with offline fixtures.
+#[uniffi::export]
+pub fn set_default_jwks_store(store: Arc<JwksStore>) {
+    JwksStore::set_global(store)
+}
+
+#[uniffi::export]
+#[allow(clippy::too_many_arguments)]
+pub fn verify_jwt_proof_with_vk(
+    srs_path: String,
+    vk: Vec<u8>,
+    expected_vk_hash: Option<String>,
+    proof: Vec<u8>,
+    domain: String,
+    google_jwt_pubkey_modulus: String,
+    ephemeral_pubkey: String,
+    ephemeral_pubkey_expiry: String,
+) -> Result<bool, StealthnoteError> {
+    proof::jwt_proof::verify_jwt_proof_with_vk(
+        srs_path,
+        vk,
+        expected_vk_hash,
+        proof,
+        domain,
+        google_jwt_pubkey_modulus,
+        ephemeral_pubkey,
+        ephemeral_pubkey_expiry,
+    )
+}
+
+/// Decode what a proof returned by [`prove_jwt`] attests to.
+#[uniffi::export]
+pub fn decode_jwt_public_inputs(proof: Vec<u8>) -> Result<JwtPublicInputs, StealthnoteError> {
+    Ok(JwtProofParts::from_bb_proof(&proof)?.public_inputs)
+}
+
+#[uniffi::export]
+pub fn split_jwt_proof(proof: Vec<u8>) -> Result<JwtProofParts, StealthnoteError> {
+    JwtProofParts::from_bb_proof(&proof)
+}
+
+//
+// Proof envelopes
+//
+
+/// Wrap a proof returned by [`prove_jwt`]; `jwt_circuit_version` defaults to the compiled-in circuit's.
+#[uniffi::export]
+pub fn jwt_proof_envelope(
+    proof: Vec<u8>,
+    jwt_circuit_version: Option<String>,
+) -> Result<ProofEnvelope, StealthnoteError> {
+    ProofEnvelope::from_jwt_proof(&proof, jwt_circuit_version)
+}
+
+#[uniffi::export]
+pub fn proof_envelope_to_bytes(envelope: ProofEnvelope) -> Result<Vec<u8>, StealthnoteError> {
+    envelope.to_bytes()
+}
+
+#[uniffi::export]
+pub fn proof_envelope_from_bytes(bytes: Vec<u8>) -> Result<ProofEnvelope, StealthnoteError> {
+    ProofEnvelope::from_bytes(&bytes)
+}
+
+#[uniffi::export]
+pub fn proof_envelope_to_json(envelope: ProofEnvelope) -> Result<String, StealthnoteError> {
+    envelope.to_json()
+}
+
+#[uniffi::export]
+pub fn proof_envelope_from_json(json: String) -> Result<ProofEnvelope, StealthnoteError> {
+    ProofEnvelope::from_json(&json)
+}
+
+/// The proof body to post to stealthnote.xyz (public inputs stripped).
+#[uniffi::export]
+pub fn proof_envelope_to_wire(envelope: ProofEnvelope) -> Vec<u8> {
+    envelope.to_wire_proof()
+}
+
+#[uniffi::export]
+pub fn generate_ephemeral_key() -> Result<String, StealthnoteError> {
     let ephemeral_key = loop {
         if let Some(key) = EphemeralKey::generate_ephemeral_key() {
             break key;
@@ -295,7 +800,7 @@ pub fn generate_ephemeral_key() -> String {
             ephemeral_key.get_ephemeral_pubkey_hash(),
         ),
     ]);
-    serde_json::to_string(&json_obj).unwrap()
+    serde_json::to_string(&json_obj).map_err(|e| StealthnoteError::InvalidKey(e.to_string()))
 }
 
 //
@@ -303,13 +808,29 @@ pub fn generate_ephemeral_key() -> String {
 //
 
 #[uniffi::export]
-pub fn create_membership(member: Member, path: String) -> bool {
-    api_server::membership::create_membership(member, path).unwrap()
+pub fn create_membership(member: Member, path: String) -> Result<bool, StealthnoteError> {
+    api_server::membership::create_membership(member, path)
 }
 
+/// Whether `signed_message` was signed by `member`'s ephemeral key for the
+/// group and provider it names.
 #[uniffi::export]
-pub fn post_likes(pub_key: String, msg_id: u32, like: bool, path: String) -> u32 {
-    api_server::likes::post_likes(pub_key, msg_id, like, path).unwrap()
+pub fn verify_message(
+    signed_message: SignedMessage,
+    member: Member,
+) -> Result<bool, StealthnoteError> {
+    api_server::message::verify_message(&signed_message, &member)
+}
+
+#[uniffi::export]
+pub fn post_likes(
+    pub_key: String,
+    msg_id: u32,
+    like: bool,
+    path: String,
+) -> Result<u32, StealthnoteError> {
+    api_server::likes::post_likes(pub_key, msg_id, like, path)
+        .map_err(|e| StealthnoteError::Storage(e.to_string()))
 }
 
 #[cfg(test)]
@@ -317,13 +838,11 @@ mod tests {
     use crate::proof::jwt_proof::{verify_jwt, JsonWebKey};
 
     use super::*;
-    use serde::Deserialize;
-    use std::fs;
 
     #[test]
     #[serial_test::serial]
     fn test_generate_ephemeral_key() {
-        let ephemeral_key = generate_ephemeral_key();
+        let ephemeral_key = generate_ephemeral_key().unwrap();
         println!("ephemeral_key: {}", ephemeral_key);
     }
 
@@ -350,28 +869,111 @@ mod tests {
 
         let domain = "pse.dev".to_string();
 
-        // Now produce the proof as usual
-        let pubkey_str = serde_json::to_string(&pubkey).unwrap();
-        let proof = prove_jwt(
-            srs_path.clone(),
-            ephemeral_pubkey.to_string(),
-            ephemeral_salt.to_string(),
-            ephemeral_expiry.to_string(),
-            id_token.to_string(),
-            pubkey_str,
-            domain,
-        );
+        // Prove as `prove_jwt` does, at a time the token was still valid
+        let input = JwtCircuitInput::from_parts_at(
+            id_token,
+            &pubkey,
+            ephemeral_pubkey,
+            ephemeral_salt,
+            ephemeral_expiry,
+            &domain,
+            &JwtInputOptions::default(),
+            1746004080,
+        )
+        .unwrap();
+        let proof = generate_jwt_proof(srs_path.clone(), &input).unwrap();
         assert!(!proof.is_empty(), "Proof should not be empty");
 
         // Call verify_jwt as before
-        let verified = verify_jwt(srs_path, proof);
+        let verified = verify_jwt(srs_path, proof).unwrap();
         assert!(verified, "JWT proof should verify correctly");
     }
 
+    #[test]
+    fn test_prove_jwt_errors() {
+        let pubkey = JsonWebKey {
+            kid: "07b80a365428525f8bf7cd0846d74a8ee4ef3625".to_string(),
+            n: "03Cww27F2O7JxB5Ji9iT9szfKZ4MK-iPzVpQkdLjCuGKfpjaCVAz9zIQ0-7gbZ-8cJRaSLfByWTGMIHRYiX2efdjz1Z9jck0DK9W3mapFrBPvM7AlRni4lPlwUigDd8zxAMDCheqyK3vCOLFW-1xYHt_YGwv8b0dP7rjujarEYlWjeppO_QMNtXdKdT9eZtBEcj_9ms9W0aLdCFNR5AAR3y0kLkKR1H4DW7vncB46rqCJLenhlCbcW0MZ3asqcjqBQ2t9QMRnY83Zf_pNEsCcXlKp4uOQqEvzjAc9ZSr2sOmd_ESZ_3jMlNkCZ4J41TuG-My5illFcW5LajSKvxD3w".to_string(),
+            use_: "sig".to_string(),
+            alg: "RS256".to_string(),
+            kty: "RSA".to_string(),
+            e: "AQAB".to_string(),
+        };
+        let pubkey_str = serde_json::to_string(&pubkey).unwrap();
+        let prove = |jwt: &str, key: &str, expiry: &str| {
+            prove_jwt(
+                "public/jwt-srs.local".to_string(),
+                "17302102366996071265028731047581517700208166805377449770193522591062772282670"
+                    .to_string(),
+                "646645587996092179008704451306999156519169540151959619716525865713892520"
+                    .to_string(),
+                expiry.to_string(),
+                jwt.to_string(),
+                key.to_string(),
+                "pse.dev".to_string(),
+            )
+        };
+
+        assert!(matches!(
+            prove("not-a-jwt", &pubkey_str, "2025-05-07T09:07:57.379Z"),
+            Err(StealthnoteError::InvalidJwt(_))
+        ));
+        assert!(matches!(
+            prove("a.b.c", "{}", "2025-05-07T09:07:57.379Z"),
+            Err(StealthnoteError::InvalidKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_prove_jwt_cancelled() {
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let result = prove_jwt_with_progress(
+            "public/jwt-srs.local".to_string(),
+            "17302102366996071265028731047581517700208166805377449770193522591062772282670"
+                .to_string(),
+            "646645587996092179008704451306999156519169540151959619716525865713892520".to_string(),
+            "2025-05-07T09:07:57.379Z".to_string(),
+            "a.b.c".to_string(),
+            "{}".to_string(),
+            "pse.dev".to_string(),
+            None,
+            Some(cancel),
+        );
+        assert!(matches!(result, Err(StealthnoteError::Cancelled)));
+    }
+
+    #[test]
+    fn test_verify_jwt_proof_errors() {
+        let verify = |modulus: &str, pubkey: &str, expiry: &str| {
+            verify_jwt_proof(
+                "public/jwt-srs.local".to_string(),
+                vec![],
+                "pse.dev".to_string(),
+                modulus.to_string(),
+                pubkey.to_string(),
+                expiry.to_string(),
+            )
+        };
+
+        assert!(matches!(
+            verify("AQAB", "not-a-number", "2025-05-07T09:07:57.379Z"),
+            Err(StealthnoteError::InvalidKey(_))
+        ));
+        assert!(matches!(
+            verify("AQAB", "12345", "next tuesday"),
+            Err(StealthnoteError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            verify_zkemail("does/not/exist.srs".to_string(), vec![]),
+            Err(StealthnoteError::SrsMissing(_))
+        ));
+    }
+
     #[test]
     #[serial_test::serial]
     fn test_prove() {
-        assert!(prove());
+        assert!(prove().unwrap());
     }
 
     #[test]
@@ -380,131 +982,18 @@ mod tests {
         // Define a path for the SRS file for testing
         let srs_path = "public/jwt-srs.local".to_string();
 
-        // Load input data from the JSON file for the test case
-        let json_str = fs::read_to_string("public/jwt_input.json")
-            .expect("Failed to read jwt_input.json for test");
-
-        #[derive(Deserialize, Debug)]
-        struct JwtInputTest {
-            partial_data: PartialDataTest,
-            partial_hash: Vec<u32>,
-            full_data_length: u32,
-            base64_decode_offset: u32,
-            jwt_pubkey_modulus_limbs: Vec<String>,
-            jwt_pubkey_redc_params_limbs: Vec<String>,
-            jwt_signature_limbs: Vec<String>,
-            ephemeral_pubkey: String,
-            ephemeral_pubkey_salt: String,
-            ephemeral_pubkey_expiry: String,
-            domain: DomainTest,
-        }
-        #[derive(Deserialize, Debug)]
-        struct PartialDataTest {
-            storage: Vec<u8>,
-            len: u32,
-        }
-        #[derive(Deserialize, Debug)]
-        struct DomainTest {
-            storage: Vec<u8>,
-            len: u32,
-        }
-
-        let input_data: JwtInputTest =
-            serde_json::from_str(&json_str).expect("Failed to parse jwt_input.json for test");
-
-        // Convert loaded data into the HashMap format required by prove_zkemail
-        let mut inputs: HashMap<String, Vec<String>> = HashMap::new();
-        inputs.insert(
-            "partial_data_storage".to_string(),
-            input_data
-                .partial_data
-                .storage
-                .iter()
-                .map(|b| b.to_string())
-                .collect(),
-        );
-        inputs.insert(
-            "partial_data_len".to_string(),
-            vec![input_data.partial_data.len.to_string()],
-        );
-        inputs.insert(
-            "partial_hash".to_string(),
-            input_data
-                .partial_hash
-                .iter()
-                .map(|i| i.to_string())
-                .collect(),
-        );
-        inputs.insert(
-            "full_data_length".to_string(),
-            vec![input_data.full_data_length.to_string()],
-        );
-        inputs.insert(
-            "base64_decode_offset".to_string(),
-            vec![input_data.base64_decode_offset.to_string()],
-        );
-        inputs.insert(
-            "jwt_pubkey_modulus_limbs".to_string(),
-            input_data
-                .jwt_pubkey_modulus_limbs
-                .iter()
-                .map(|i| i.to_string())
-                .collect(),
-        );
-        inputs.insert(
-            "jwt_pubkey_redc_params_limbs".to_string(),
-            input_data
-                .jwt_pubkey_redc_params_limbs
-                .iter()
-                .map(|i| i.to_string())
-                .collect(),
-        );
-        inputs.insert(
-            "jwt_signature_limbs".to_string(),
-            input_data
-                .jwt_signature_limbs
-                .iter()
-                .map(|i| i.to_string())
-                .collect(),
-        );
-        inputs.insert(
-            "ephemeral_pubkey".to_string(),
-            vec![input_data.ephemeral_pubkey.to_string()],
-        );
-        inputs.insert(
-            "ephemeral_pubkey_salt".to_string(),
-            vec![input_data.ephemeral_pubkey_salt.to_string()],
-        );
-        inputs.insert(
-            "ephemeral_pubkey_expiry".to_string(),
-            vec![input_data.ephemeral_pubkey_expiry.to_string()],
-        );
-        inputs.insert(
-            "domain_storage".to_string(),
-            input_data
-                .domain
-                .storage
-                .iter()
-                .map(|b| b.to_string())
-                .collect(),
-        );
-        inputs.insert(
-            "domain_len".to_string(),
-            vec![input_data.domain.len.to_string()],
-        );
-
-        // Write inputs HashMap to JSON snapshot file
-        let json_snapshot = serde_json::to_string_pretty(&inputs).unwrap();
-        std::fs::write("public/jwt_input_snapshot_real.json", json_snapshot).unwrap();
+        // Load input data from the JSON snapshot for the test case
+        let input = JwtCircuitInput::load("public/jwt_input.json")
+            .expect("Failed to load jwt_input.json for test");
 
         // Call prove_jwt
-        let proof = generate_jwt_proof(srs_path.clone(), inputs);
+        let proof = generate_jwt_proof(srs_path.clone(), &input).unwrap();
 
         // Ensure proof is not empty (basic check)
         assert!(!proof.is_empty(), "Generated proof is empty");
 
         // Call verify_jwt
-        let is_valid = verify_jwt(srs_path, proof);
+        let is_valid = verify_jwt(srs_path, proof).unwrap();
 
         // Assert that verification returns true
         assert!(is_valid, "Proof verification failed");
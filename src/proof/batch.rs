@@ -0,0 +1,158 @@
+use super::jwt_proof::JWT_CIRCUIT_VERSION;
+use super::versions::JwtCircuitVersion;
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+/// Upper bound on verifier threads when the caller doesn't pick one; each bb
+/// verification already uses a fair amount of memory on phones.
+const DEFAULT_MAX_THREADS: usize = 4;
+
+/// One stored membership proof (public inputs stripped) and the metadata it claims.
+#[derive(uniffi::Record, Debug, Clone)]
+pub struct JwtProofItem {
+    pub proof: Vec<u8>,
+    pub domain: String,
+    pub google_jwt_pubkey_modulus: String,
+    pub ephemeral_pubkey: String,
+    pub ephemeral_pubkey_expiry: String,
+    /// Defaults to the compiled-in circuit version.
+    pub jwt_circuit_version: Option<String>,
+}
+
+#[derive(uniffi::Record, Debug, Clone, PartialEq)]
+pub struct BatchVerdict {
+    pub verified: bool,
+    /// Why the proof couldn't be checked; `None` when `verified` is a real verdict.
+    pub error: Option<String>,
+}
+
+/// Verify `items` on up to `max_threads` threads, returning one verdict per
+/// item in the same order. Each circuit version's VK is derived once up front.
+pub fn verify_jwt_proofs_batch(
+    srs_path: &str,
+    items: Vec<JwtProofItem>,
+    max_threads: Option<usize>,
+) -> Vec<BatchVerdict> {
+    let versions: HashSet<&str> = items
+        .iter()
+        .map(|item| {
+            item.jwt_circuit_version
+                .as_deref()
+                .unwrap_or(JWT_CIRCUIT_VERSION)
+        })
+        .collect();
+    for version in versions {
+        // Failures surface again, per item, below
+        if let Ok(circuit) = JwtCircuitVersion::get(version).and_then(|v| v.circuit()) {
+            let _ = circuit.verification_key(Some(srs_path));
+        }
+    }
+
+    let threads = max_threads
+        .unwrap_or_else(|| {
+            thread::available_parallelism()
+                .map_or(1, |n| n.get())
+                .min(DEFAULT_MAX_THREADS)
+        })
+        .clamp(1, items.len().max(1));
+    let next = AtomicUsize::new(0);
+    let verdicts: Mutex<Vec<Option<BatchVerdict>>> = Mutex::new(vec![None; items.len()]);
+
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                let verdict = verify_item(srs_path, item);
+                verdicts.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(verdict);
+            });
+        }
+    });
+
+    verdicts
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .map(|verdict| {
+            verdict.unwrap_or(BatchVerdict {
+                verified: false,
+                error: Some("verifier thread panicked".to_string()),
+            })
+        })
+        .collect()
+}
+
+fn verify_item(srs_path: &str, item: &JwtProofItem) -> BatchVerdict {
+    let version = item
+        .jwt_circuit_version
+        .as_deref()
+        .unwrap_or(JWT_CIRCUIT_VERSION);
+    let result = JwtCircuitVersion::get(version).and_then(|circuit| {
+        circuit.verify(
+            Some(srs_path),
+            item.proof.clone(),
+            item.domain.clone(),
+            item.google_jwt_pubkey_modulus.clone(),
+            item.ephemeral_pubkey.clone(),
+            item.ephemeral_pubkey_expiry.clone(),
+        )
+    });
+    match result {
+        Ok(verified) => BatchVerdict {
+            verified,
+            error: None,
+        },
+        Err(e) => BatchVerdict {
+            verified: false,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(ephemeral_pubkey: &str, version: Option<&str>) -> JwtProofItem {
+        JwtProofItem {
+            proof: vec![0u8; 64],
+            domain: "pse.dev".to_string(),
+            google_jwt_pubkey_modulus: "AQAB".to_string(),
+            ephemeral_pubkey: ephemeral_pubkey.to_string(),
+            ephemeral_pubkey_expiry: "2025-05-07T09:07:57.379Z".to_string(),
+            jwt_circuit_version: version.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_batch_reports_per_item_errors_in_order() {
+        let items = vec![
+            item("not-a-number", None),
+            item("123", Some("0.0.1")),
+            item("123", None),
+            item("also-bad", Some("0.3.1")),
+        ];
+        let verdicts = verify_jwt_proofs_batch("does/not/exist.srs", items, Some(3));
+        assert_eq!(verdicts.len(), 4);
+        assert!(verdicts.iter().all(|v| !v.verified));
+
+        let errors: Vec<&str> = verdicts
+            .iter()
+            .map(|v| v.error.as_deref().unwrap())
+            .collect();
+        assert!(errors[0].starts_with("Invalid key material"));
+        assert!(errors[1].contains("unknown JWT circuit version 0.0.1"));
+        assert!(errors[2].starts_with("SRS file not found"));
+        assert!(errors[3].starts_with("Invalid key material"));
+
+        assert!(verify_jwt_proofs_batch("does/not/exist.srs", vec![], None).is_empty());
+    }
+}
@@ -0,0 +1,345 @@
+use super::circuit::CircuitArtifact;
+use super::jwt_proof::JWT_CIRCUIT_JSON;
+use super::progress::{ProofProgress, ProofStage};
+use crate::error::StealthnoteError;
+use noir::{
+    barretenberg::{
+        prove::prove_ultra_honk,
+        srs::{setup_srs, setup_srs_from_bytecode},
+        utils::get_honk_verification_key,
+        verify::verify_ultra_honk,
+    },
+    witness::from_vec_str_to_witness_map,
+};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard, OnceLock},
+};
+
+/// A parsed circuit together with the setup derived from it (SRS size and
+/// verification keys), shared process-wide and keyed by the SHA-256 of its
+/// bytecode so repeated prove/verify calls skip re-parsing and re-deriving
+/// the VK. The artifact's own `hash` field is not trusted for this.
+pub struct CircuitHandle {
+    pub artifact: CircuitArtifact,
+    srs_points: OnceLock<u32>,
+    /// Verification keys by the SRS file they were derived with
+    vks: Mutex<HashMap<Option<String>, Vec<u8>>>,
+}
+
+/// SRS currently loaded into barretenberg. bb keeps a single global CRS, so
+/// it only has to be reloaded when a larger circuit or a different file is used.
+struct SrsState {
+    path: Option<String>,
+    points: u32,
+}
+
+static CIRCUITS: OnceLock<Mutex<HashMap<[u8; 32], Arc<CircuitHandle>>>> = OnceLock::new();
+static SRS_STATE: Mutex<Option<SrsState>> = Mutex::new(None);
+
+/// Circuits compiled into the library, which a runtime-loaded artifact can replace.
+#[derive(uniffi::Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinCircuit {
+    Jwt,
+    Zkemail,
+}
+
+static OVERRIDES: Mutex<[Option<Arc<CircuitHandle>>; 2]> = Mutex::new([None, None]);
+
+impl BuiltinCircuit {
+    fn slot(self) -> usize {
+        match self {
+            BuiltinCircuit::Jwt => 0,
+            BuiltinCircuit::Zkemail => 1,
+        }
+    }
+
+    /// Use `artifact` instead of the compiled-in circuit, or go back to it with `None`.
+    /// Overrides get their own handle, kept out of the shared cache.
+    pub fn set_override(self, artifact: Option<CircuitArtifact>) -> Option<Arc<CircuitHandle>> {
+        let handle = artifact.map(|artifact| Arc::new(CircuitHandle::new(artifact)));
+        let mut overrides = OVERRIDES.lock().unwrap_or_else(|e| e.into_inner());
+        overrides[self.slot()] = handle.clone();
+        handle
+    }
+
+    fn loaded(self) -> Option<Arc<CircuitHandle>> {
+        OVERRIDES.lock().unwrap_or_else(|e| e.into_inner())[self.slot()].clone()
+    }
+}
+
+impl CircuitHandle {
+    /// The JWT circuit: a runtime-loaded artifact if one is set, else the built-in one.
+    pub fn jwt() -> Result<Arc<Self>, StealthnoteError> {
+        static JWT: OnceLock<Arc<CircuitHandle>> = OnceLock::new();
+        match BuiltinCircuit::Jwt.loaded() {
+            Some(handle) => Ok(handle),
+            None => builtin(&JWT, JWT_CIRCUIT_JSON),
+        }
+    }
+
+    /// The zkEmail circuit: a runtime-loaded artifact if one is set, else the built-in one.
+    pub fn zkemail() -> Result<Arc<Self>, StealthnoteError> {
+        static ZKEMAIL: OnceLock<Arc<CircuitHandle>> = OnceLock::new();
+        match BuiltinCircuit::Zkemail.loaded() {
+            Some(handle) => Ok(handle),
+            None => builtin(&ZKEMAIL, crate::ZKEMAIL_CIRCUIT_JSON),
+        }
+    }
+
+    /// Parse an artifact JSON, returning the cached handle if the same bytecode was seen.
+    pub fn from_json(json: &str) -> Result<Arc<Self>, StealthnoteError> {
+        Ok(Self::from_artifact(CircuitArtifact::from_json(json)?))
+    }
+
+    pub fn from_artifact(artifact: CircuitArtifact) -> Arc<Self> {
+        let key: [u8; 32] = Sha256::digest(artifact.bytecode.as_bytes()).into();
+        let mut circuits = CIRCUITS
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        circuits
+            .entry(key)
+            .or_insert_with(|| Arc::new(CircuitHandle::new(artifact)))
+            .clone()
+    }
+
+    fn new(artifact: CircuitArtifact) -> Self {
+        CircuitHandle {
+            artifact,
+            srs_points: OnceLock::new(),
+            vks: Mutex::default(),
+        }
+    }
+
+    pub fn prove(
+        &self,
+        srs_path: Option<&str>,
+        inputs: &Value,
+    ) -> Result<Vec<u8>, StealthnoteError> {
+        let witness = self.artifact.witness_from_value(inputs)?;
+        self.prove_witness(srs_path, witness)
+    }
+
+    pub fn prove_witness(
+        &self,
+        srs_path: Option<&str>,
+        witness: Vec<String>,
+    ) -> Result<Vec<u8>, StealthnoteError> {
+        self.prove_witness_with_progress(srs_path, witness, &ProofProgress::default())
+    }
+
+    pub fn prove_witness_with_progress(
+        &self,
+        srs_path: Option<&str>,
+        witness: Vec<String>,
+        progress: &ProofProgress,
+    ) -> Result<Vec<u8>, StealthnoteError> {
+        let expected = self.artifact.witness_len();
+        if witness.len() != expected {
+            return Err(StealthnoteError::CircuitMismatch(format!(
+                "Witness has {} values, circuit expects {}",
+                witness.len(),
+                expected
+            )));
+        }
+
+        progress.stage(ProofStage::SrsSetup)?;
+        self.ensure_srs(srs_path)?;
+
+        progress.stage(ProofStage::WitnessSolving)?;
+        // Convert Vec<String> to Vec<&str> for the function call
+        let witness_vec_str: Vec<&str> = witness.iter().map(AsRef::as_ref).collect();
+        let initial_witness = from_vec_str_to_witness_map(witness_vec_str)
+            .map_err(|e| StealthnoteError::CircuitMismatch(e.to_string()))?;
+
+        progress.stage(ProofStage::Proving)?;
+        let proof = prove_ultra_honk(&self.artifact.bytecode, initial_witness, false)
+            .map_err(|e| StealthnoteError::ProofFailed(e.to_string()))?;
+
+        progress.stage(ProofStage::Done)?;
+        Ok(proof)
+    }
+
+    pub fn verify(&self, srs_path: Option<&str>, proof: Vec<u8>) -> Result<bool, StealthnoteError> {
+        let vk = self.verification_key(srs_path)?;
+        // bb verifies against whichever SRS is loaded, which must be the VK's
+        self.ensure_srs(srs_path)?;
+        verify_ultra_honk(proof, vk).map_err(|e| StealthnoteError::ProofFailed(e.to_string()))
+    }
+
+    /// Verification key for this circuit with the SRS at `srs_path`, derived
+    /// on first use of that SRS.
+    pub fn verification_key(&self, srs_path: Option<&str>) -> Result<Vec<u8>, StealthnoteError> {
+        check_srs_path(srs_path)?;
+        let key = srs_path.map(str::to_string);
+        if let Some(vk) = self.vks().get(&key) {
+            return Ok(vk.clone());
+        }
+        self.ensure_srs(srs_path)?;
+        let vk = get_honk_verification_key(&self.artifact.bytecode, false)
+            .map_err(|e| StealthnoteError::ProofFailed(e.to_string()))?;
+        Ok(self.vks().entry(key).or_insert(vk).clone())
+    }
+
+    fn vks(&self) -> MutexGuard<'_, HashMap<Option<String>, Vec<u8>>> {
+        self.vks.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Load enough SRS points for this circuit, unless they are already loaded from the same file.
+    fn ensure_srs(&self, srs_path: Option<&str>) -> Result<(), StealthnoteError> {
+        check_srs_path(srs_path)?;
+
+        let mut state = SRS_STATE.lock().unwrap_or_else(|e| e.into_inner());
+        if let (Some(loaded), Some(needed)) = (state.as_ref(), self.srs_points.get()) {
+            if loaded.path.as_deref() == srs_path && loaded.points >= *needed {
+                return Ok(());
+            }
+        }
+
+        let points = setup_srs_from_bytecode(&self.artifact.bytecode, srs_path, false)
+            .map_err(|e| StealthnoteError::SrsMissing(e.to_string()))?;
+        let _ = self.srs_points.set(points);
+        *state = Some(SrsState {
+            path: srs_path.map(str::to_string),
+            points,
+        });
+        Ok(())
+    }
+}
+
+/// Make sure an SRS is loaded before verifying against a precomputed VK. The
+/// verifier only needs the first points, so no circuit has to be parsed for it.
+pub fn ensure_verifier_srs(srs_path: Option<&str>) -> Result<(), StealthnoteError> {
+    check_srs_path(srs_path)?;
+
+    let mut state = SRS_STATE.lock().unwrap_or_else(|e| e.into_inner());
+    if state
+        .as_ref()
+        .is_some_and(|loaded| loaded.path.as_deref() == srs_path)
+    {
+        return Ok(());
+    }
+    let points = setup_srs(1, srs_path).map_err(|e| StealthnoteError::SrsMissing(e.to_string()))?;
+    *state = Some(SrsState {
+        path: srs_path.map(str::to_string),
+        points,
+    });
+    Ok(())
+}
+
+/// A given SRS file has to exist, whatever is already loaded.
+fn check_srs_path(srs_path: Option<&str>) -> Result<(), StealthnoteError> {
+    match srs_path {
+        Some(path) if !Path::new(path).exists() => {
+            Err(StealthnoteError::SrsMissing(path.to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn builtin(
+    cell: &'static OnceLock<Arc<CircuitHandle>>,
+    json: &str,
+) -> Result<Arc<CircuitHandle>, StealthnoteError> {
+    if let Some(handle) = cell.get() {
+        return Ok(handle.clone());
+    }
+    let handle = CircuitHandle::from_json(json)?;
+    Ok(cell.get_or_init(|| handle).clone())
+}
+
+/// Parse the built-in circuits, load the SRS and derive their verification
+/// keys up front so the first prove/verify call doesn't pay for it.
+pub fn prewarm(srs_path: Option<&str>) -> Result<(), StealthnoteError> {
+    let circuits = [CircuitHandle::jwt()?, CircuitHandle::zkemail()?];
+    for circuit in &circuits {
+        circuit.verification_key(srs_path)?;
+    }
+    // Leave the SRS sized for the largest circuit so no later call has to reload it.
+    let largest = circuits
+        .iter()
+        .max_by_key(|c| c.srs_points.get().copied().unwrap_or(0))
+        .expect("at least one built-in circuit");
+    largest.ensure_srs(srs_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handles_are_shared_by_bytecode() {
+        let jwt = CircuitHandle::jwt().unwrap();
+        assert!(Arc::ptr_eq(&jwt, &CircuitHandle::jwt().unwrap()));
+        assert!(Arc::ptr_eq(
+            &jwt,
+            &CircuitHandle::from_json(JWT_CIRCUIT_JSON).unwrap()
+        ));
+
+        // A forged `hash` field doesn't alias another circuit
+        let mut forged = CircuitHandle::zkemail().unwrap().artifact.clone();
+        forged.hash = jwt.artifact.hash;
+        assert!(!Arc::ptr_eq(&jwt, &CircuitHandle::from_artifact(forged)));
+
+        let zkemail = CircuitHandle::zkemail().unwrap();
+        assert!(!Arc::ptr_eq(&jwt, &zkemail));
+        assert_eq!(jwt.artifact.num_public_inputs(), 85);
+        assert_eq!(zkemail.artifact.num_public_inputs(), 463);
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn test_runtime_artifact_override() {
+        let builtin = CircuitHandle::zkemail().unwrap();
+
+        // Same circuit, standing in for a newer release
+        let loaded = BuiltinCircuit::Zkemail
+            .set_override(Some(builtin.artifact.clone()))
+            .unwrap();
+        assert!(Arc::ptr_eq(&CircuitHandle::zkemail().unwrap(), &loaded));
+        assert!(!Arc::ptr_eq(&loaded, &builtin));
+        assert!(Arc::ptr_eq(
+            &CircuitHandle::from_json(crate::ZKEMAIL_CIRCUIT_JSON).unwrap(),
+            &builtin
+        ));
+
+        BuiltinCircuit::Zkemail.set_override(None);
+        assert!(Arc::ptr_eq(&CircuitHandle::zkemail().unwrap(), &builtin));
+    }
+
+    #[test]
+    fn test_missing_srs_is_reported() {
+        let jwt = CircuitHandle::jwt().unwrap();
+        let result = jwt.ensure_srs(Some("does/not/exist.srs"));
+        assert!(matches!(result, Err(StealthnoteError::SrsMissing(_))));
+
+        // Also when the verification key is already derived
+        let warm = CircuitHandle::new(jwt.artifact.clone());
+        warm.vks()
+            .insert(Some("does/not/exist.srs".to_string()), vec![]);
+        let result = warm.verification_key(Some("does/not/exist.srs"));
+        assert!(matches!(result, Err(StealthnoteError::SrsMissing(_))));
+        let result = ensure_verifier_srs(Some("does/not/exist.srs"));
+        assert!(matches!(result, Err(StealthnoteError::SrsMissing(_))));
+    }
+
+    #[test]
+    fn test_vk_is_cached_per_srs() {
+        let handle = CircuitHandle::new(CircuitHandle::jwt().unwrap().artifact.clone());
+        handle
+            .vks()
+            .insert(Some("Cargo.toml".to_string()), vec![1, 2, 3]);
+        assert_eq!(
+            handle.verification_key(Some("Cargo.toml")).unwrap(),
+            [1, 2, 3]
+        );
+        // Another SRS doesn't get the key derived with the first one
+        assert!(!handle
+            .verification_key(Some("Cargo.lock"))
+            .is_ok_and(|vk| vk == [1, 2, 3]));
+    }
+}
@@ -0,0 +1,610 @@
+use anyhow::{anyhow, bail, Result};
+use num_bigint::BigUint;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+    sync::LazyLock,
+};
+
+use crate::error::StealthnoteError;
+
+// BN254 scalar field modulus, every witness value must be strictly below it
+const BN254_MODULUS: &str =
+    "21888242871839275222246405745257275088548364400416722356888129093348386718017";
+static BN254_FIELD: LazyLock<BigUint> =
+    LazyLock::new(|| BigUint::parse_bytes(BN254_MODULUS.as_bytes(), 10).unwrap());
+
+/// What an artifact loaded at runtime must match before it is used. At least
+/// one of `hash` (the artifact's own `hash` field) or `sha256` (of the file
+/// bytes) is required; only the SHA-256 also covers edits to the bytecode.
+#[derive(uniffi::Record, Debug, Clone, Default)]
+pub struct ArtifactPin {
+    pub hash: Option<u64>,
+    pub sha256: Option<String>,
+    /// Compared without the `+<commit>` suffix, e.g. `1.0.0-beta.3`.
+    pub noir_version: Option<String>,
+}
+
+/// A compiled Noir circuit as emitted by `nargo compile` (`target/<name>.json`).
+#[derive(Debug, Deserialize, Clone)]
+pub struct CircuitArtifact {
+    pub noir_version: String,
+    pub hash: u64,
+    pub abi: Abi,
+    pub bytecode: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Abi {
+    pub parameters: Vec<AbiParameter>,
+    pub return_type: Option<AbiReturnType>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct AbiParameter {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub typ: AbiType,
+    pub visibility: AbiVisibility,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct AbiReturnType {
+    pub abi_type: AbiType,
+    pub visibility: AbiVisibility,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AbiVisibility {
+    Public,
+    Private,
+    Databus,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Sign {
+    Unsigned,
+    Signed,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+pub struct AbiField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub typ: AbiType,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum AbiType {
+    Field,
+    Boolean,
+    Integer {
+        sign: Sign,
+        width: u32,
+    },
+    Array {
+        length: usize,
+        #[serde(rename = "type")]
+        typ: Box<AbiType>,
+    },
+    String {
+        length: usize,
+    },
+    Struct {
+        path: String,
+        fields: Vec<AbiField>,
+    },
+    Tuple {
+        fields: Vec<AbiType>,
+    },
+}
+
+impl AbiType {
+    /// Number of field elements this type occupies in the witness.
+    pub fn field_count(&self) -> usize {
+        match self {
+            AbiType::Field | AbiType::Boolean | AbiType::Integer { .. } => 1,
+            AbiType::Array { length, typ } => length * typ.field_count(),
+            AbiType::String { length } => *length,
+            AbiType::Struct { fields, .. } => fields.iter().map(|f| f.typ.field_count()).sum(),
+            AbiType::Tuple { fields } => fields.iter().map(|t| t.field_count()).sum(),
+        }
+    }
+
+    /// Capacity of a `BoundedVec` (a struct of a `storage` array and a `len`).
+    pub fn bounded_vec_capacity(&self) -> Option<usize> {
+        let AbiType::Struct { fields, .. } = self else {
+            return None;
+        };
+        match fields.iter().find(|f| f.name == "storage").map(|f| &f.typ) {
+            Some(AbiType::Array { length, .. }) if fields.iter().any(|f| f.name == "len") => {
+                Some(*length)
+            }
+            _ => None,
+        }
+    }
+
+    fn is_primitive(&self) -> bool {
+        matches!(
+            self,
+            AbiType::Field | AbiType::Boolean | AbiType::Integer { .. }
+        )
+    }
+
+    /// Append the witness values for `value` to `out`, checking it against this type.
+    fn flatten(&self, value: &Value, path: &str, out: &mut Vec<String>) -> Result<()> {
+        match self {
+            AbiType::Field => {
+                let n = parse_biguint(value, path)?;
+                if n >= *BN254_FIELD {
+                    bail!("{}: value exceeds the BN254 field modulus", path);
+                }
+                out.push(n.to_string());
+            }
+            AbiType::Boolean => {
+                let b = match value {
+                    Value::Bool(b) => *b,
+                    _ => match parse_biguint(value, path)?.to_string().as_str() {
+                        "0" => false,
+                        "1" => true,
+                        _ => bail!("{}: expected a boolean", path),
+                    },
+                };
+                out.push(if b { "1" } else { "0" }.to_string());
+            }
+            AbiType::Integer {
+                sign: Sign::Unsigned,
+                width,
+            } => {
+                let n = parse_biguint(value, path)?;
+                if n.bits() > *width as u64 {
+                    bail!("{}: {} does not fit in u{}", path, n, width);
+                }
+                out.push(n.to_string());
+            }
+            AbiType::Integer {
+                sign: Sign::Signed,
+                width,
+            } => {
+                let n = parse_i128(value, path)?;
+                let bound = 1i128 << (width - 1);
+                if n < -bound || n >= bound {
+                    bail!("{}: {} does not fit in i{}", path, n, width);
+                }
+                // Negative values are encoded as their two's complement within `width` bits
+                let encoded = if n < 0 {
+                    (BigUint::from(1u8) << *width) - BigUint::from(n.unsigned_abs())
+                } else {
+                    BigUint::from(n as u128)
+                };
+                out.push(encoded.to_string());
+            }
+            AbiType::Array { length, typ } => {
+                let items = expect_array(value, *length, path)?;
+                for (i, item) in items.iter().enumerate() {
+                    typ.flatten(item, &format!("{}[{}]", path, i), out)?;
+                }
+            }
+            AbiType::String { length } => match value {
+                Value::String(s) => {
+                    if s.len() != *length {
+                        bail!(
+                            "{}: expected a string of {} bytes, got {}",
+                            path,
+                            length,
+                            s.len()
+                        );
+                    }
+                    out.extend(s.bytes().map(|b| b.to_string()));
+                }
+                _ => {
+                    let byte = AbiType::Integer {
+                        sign: Sign::Unsigned,
+                        width: 8,
+                    };
+                    let items = expect_array(value, *length, path)?;
+                    for (i, item) in items.iter().enumerate() {
+                        byte.flatten(item, &format!("{}[{}]", path, i), out)?;
+                    }
+                }
+            },
+            AbiType::Struct { fields, .. } => {
+                let object = value
+                    .as_object()
+                    .ok_or_else(|| anyhow!("{}: expected an object", path))?;
+                check_unknown_keys(object, fields.iter().map(|f| f.name.as_str()), path)?;
+                for field in fields {
+                    let field_path = format!("{}.{}", path, field.name);
+                    let field_value = object
+                        .get(&field.name)
+                        .ok_or_else(|| anyhow!("{}: missing field", field_path))?;
+                    field.typ.flatten(field_value, &field_path, out)?;
+                }
+            }
+            AbiType::Tuple { fields } => {
+                let items = expect_array(value, fields.len(), path)?;
+                for (i, (typ, item)) in fields.iter().zip(items).enumerate() {
+                    typ.flatten(item, &format!("{}.{}", path, i), out)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuild a nested value from flat `HashMap` keys, where struct fields and
+    /// tuple items are addressed as `<param>_<field>` (e.g. `partial_data_storage`).
+    fn value_from_flat_map(
+        &self,
+        key: &str,
+        inputs: &HashMap<String, Vec<String>>,
+        used: &mut HashSet<String>,
+    ) -> Result<Value> {
+        match self {
+            AbiType::Struct { fields, .. } => {
+                let mut object = Map::new();
+                for field in fields {
+                    let field_key = format!("{}_{}", key, field.name);
+                    object.insert(
+                        field.name.clone(),
+                        field.typ.value_from_flat_map(&field_key, inputs, used)?,
+                    );
+                }
+                Ok(Value::Object(object))
+            }
+            AbiType::Tuple { fields } => fields
+                .iter()
+                .enumerate()
+                .map(|(i, typ)| typ.value_from_flat_map(&format!("{}_{}", key, i), inputs, used))
+                .collect::<Result<Vec<_>>>()
+                .map(Value::Array),
+            AbiType::Array { typ, .. } if !typ.is_primitive() => bail!(
+                "{}: arrays of composite types can't be passed as a flat map, use nested inputs",
+                key
+            ),
+            _ => {
+                let values = inputs
+                    .get(key)
+                    .ok_or_else(|| anyhow!("Missing required input key: {}", key))?;
+                used.insert(key.to_string());
+                if self.is_primitive() {
+                    match values.as_slice() {
+                        [v] => Ok(Value::String(v.clone())),
+                        _ => bail!("{}: expected a single value, got {}", key, values.len()),
+                    }
+                } else {
+                    Ok(Value::Array(
+                        values.iter().cloned().map(Value::String).collect(),
+                    ))
+                }
+            }
+        }
+    }
+}
+
+impl CircuitArtifact {
+    pub fn from_json(json: &str) -> Result<Self, StealthnoteError> {
+        serde_json::from_str(json).map_err(|e| {
+            StealthnoteError::CircuitMismatch(format!("Invalid circuit artifact: {}", e))
+        })
+    }
+
+    /// Parse an artifact loaded at runtime, rejecting it unless it matches `pin`.
+    pub fn from_bytes_pinned(bytes: &[u8], pin: &ArtifactPin) -> Result<Self, StealthnoteError> {
+        if pin.hash.is_none() && pin.sha256.is_none() {
+            return Err(StealthnoteError::InvalidInput(
+                "Circuit artifact pin needs a hash or a sha256".to_string(),
+            ));
+        }
+        if let Some(expected) = &pin.sha256 {
+            let actual = hex::encode(Sha256::digest(bytes));
+            if actual != expected.trim_start_matches("0x").to_lowercase() {
+                return Err(StealthnoteError::CircuitMismatch(format!(
+                    "Circuit artifact sha256 {} does not match pinned {}",
+                    actual, expected
+                )));
+            }
+        }
+
+        let json = std::str::from_utf8(bytes).map_err(|e| {
+            StealthnoteError::CircuitMismatch(format!("Invalid circuit artifact: {}", e))
+        })?;
+        let artifact = Self::from_json(json)?;
+        if let Some(expected) = pin.hash {
+            if artifact.hash != expected {
+                return Err(StealthnoteError::CircuitMismatch(format!(
+                    "Circuit artifact hash {} does not match pinned {}",
+                    artifact.hash, expected
+                )));
+            }
+        }
+        if let Some(expected) = &pin.noir_version {
+            let version = artifact.noir_version.split('+').next().unwrap_or_default();
+            if version != expected.split('+').next().unwrap_or_default() {
+                return Err(StealthnoteError::CircuitMismatch(format!(
+                    "Circuit artifact compiled with noir {}, expected {}",
+                    artifact.noir_version, expected
+                )));
+            }
+        }
+        Ok(artifact)
+    }
+
+    pub fn load_pinned(
+        path: impl AsRef<Path>,
+        pin: &ArtifactPin,
+    ) -> Result<Self, StealthnoteError> {
+        Self::from_bytes_pinned(&fs::read(path)?, pin)
+    }
+
+    pub fn parameter(&self, name: &str) -> Option<&AbiParameter> {
+        self.abi.parameters.iter().find(|p| p.name == name)
+    }
+
+    /// Total number of witness values expected by the circuit's `main`.
+    pub fn witness_len(&self) -> usize {
+        self.abi
+            .parameters
+            .iter()
+            .map(|p| p.typ.field_count())
+            .sum()
+    }
+
+    /// Number of public inputs bb prepends to a proof: public parameters followed by the return value.
+    pub fn num_public_inputs(&self) -> usize {
+        let params: usize = self
+            .abi
+            .parameters
+            .iter()
+            .filter(|p| p.visibility == AbiVisibility::Public)
+            .map(|p| p.typ.field_count())
+            .sum();
+        let returns = self
+            .abi
+            .return_type
+            .as_ref()
+            .map_or(0, |r| r.abi_type.field_count());
+        params + returns
+    }
+
+    /// Flatten a JSON object keyed by parameter name into the witness, in ABI order.
+    pub fn witness_from_value(&self, inputs: &Value) -> Result<Vec<String>, StealthnoteError> {
+        self.flatten_inputs(inputs)
+            .map_err(|e| StealthnoteError::CircuitMismatch(e.to_string()))
+    }
+
+    /// Flatten a flat `HashMap` input (the shape used by the uniffi prove functions).
+    pub fn witness_from_map(
+        &self,
+        inputs: &HashMap<String, Vec<String>>,
+    ) -> Result<Vec<String>, StealthnoteError> {
+        let mut used = HashSet::new();
+        let mut object = Map::new();
+        for param in &self.abi.parameters {
+            let value = param
+                .typ
+                .value_from_flat_map(&param.name, inputs, &mut used)
+                .map_err(|e| StealthnoteError::CircuitMismatch(e.to_string()))?;
+            object.insert(param.name.clone(), value);
+        }
+        if let Some(key) = inputs.keys().find(|k| !used.contains(*k)) {
+            return Err(StealthnoteError::CircuitMismatch(format!(
+                "Unknown input key: {}",
+                key
+            )));
+        }
+        self.witness_from_value(&Value::Object(object))
+    }
+
+    fn flatten_inputs(&self, inputs: &Value) -> Result<Vec<String>> {
+        let object = inputs
+            .as_object()
+            .ok_or_else(|| anyhow!("Circuit inputs must be an object keyed by parameter name"))?;
+        check_unknown_keys(
+            object,
+            self.abi.parameters.iter().map(|p| p.name.as_str()),
+            "inputs",
+        )?;
+
+        let mut witness = Vec::with_capacity(self.witness_len());
+        for param in &self.abi.parameters {
+            let value = object
+                .get(&param.name)
+                .ok_or_else(|| anyhow!("Missing required input: {}", param.name))?;
+            param.typ.flatten(value, &param.name, &mut witness)?;
+        }
+        Ok(witness)
+    }
+}
+
+//
+// utils
+//
+fn parse_biguint(value: &Value, path: &str) -> Result<BigUint> {
+    let parsed = match value {
+        Value::Number(n) => n.as_u64().map(BigUint::from),
+        Value::String(s) => {
+            let s = s.trim();
+            match s.strip_prefix("0x") {
+                Some(hex) => BigUint::parse_bytes(hex.as_bytes(), 16),
+                None => BigUint::parse_bytes(s.as_bytes(), 10),
+            }
+        }
+        _ => None,
+    };
+    parsed.ok_or_else(|| anyhow!("{}: expected an unsigned integer, got {}", path, value))
+}
+
+fn parse_i128(value: &Value, path: &str) -> Result<i128> {
+    let parsed = match value {
+        Value::Number(n) => n.as_i64().map(i128::from),
+        Value::String(s) => s.trim().parse::<i128>().ok(),
+        _ => None,
+    };
+    parsed.ok_or_else(|| anyhow!("{}: expected a signed integer, got {}", path, value))
+}
+
+fn expect_array<'a>(value: &'a Value, length: usize, path: &str) -> Result<&'a Vec<Value>> {
+    let items = value
+        .as_array()
+        .ok_or_else(|| anyhow!("{}: expected an array of length {}", path, length))?;
+    if items.len() != length {
+        bail!(
+            "{}: expected an array of length {}, got {}",
+            path,
+            length,
+            items.len()
+        );
+    }
+    Ok(items)
+}
+
+fn check_unknown_keys<'a>(
+    object: &Map<String, Value>,
+    expected: impl Iterator<Item = &'a str>,
+    path: &str,
+) -> Result<()> {
+    let expected: HashSet<&str> = expected.collect();
+    if let Some(key) = object.keys().find(|k| !expected.contains(k.as_str())) {
+        bail!("{}: unknown field {}", path, key);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs;
+
+    const JWT_JSON: &str = include_str!("../../circuit/stealthnote_jwt.json");
+    const ZKEMAIL_JSON: &str = include_str!("../../circuit/zkemail_test.json");
+
+    #[test]
+    fn test_parse_artifact_abi() {
+        let jwt = CircuitArtifact::from_json(JWT_JSON).unwrap();
+        assert_eq!(jwt.abi.parameters.len(), 11);
+        assert_eq!(jwt.witness_len(), 641 + 8 + 1 + 1 + 18 * 3 + 65 + 1 + 1 + 1);
+        assert_eq!(jwt.num_public_inputs(), 18 + 65 + 1 + 1);
+
+        let zkemail = CircuitArtifact::from_json(ZKEMAIL_JSON).unwrap();
+        assert_eq!(zkemail.num_public_inputs(), 2 + 321 + 38 + 102);
+    }
+
+    #[test]
+    fn test_pinned_artifact_loading() {
+        let sha256 = hex::encode(Sha256::digest(JWT_JSON.as_bytes()));
+        let pin = ArtifactPin {
+            hash: Some(2226591547107946498),
+            sha256: Some(sha256.clone()),
+            noir_version: Some("1.0.0-beta.3".to_string()),
+        };
+        let jwt = CircuitArtifact::from_bytes_pinned(JWT_JSON.as_bytes(), &pin).unwrap();
+        assert_eq!(jwt.num_public_inputs(), 85);
+        let jwt = CircuitArtifact::load_pinned("circuit/stealthnote_jwt.json", &pin).unwrap();
+        assert_eq!(jwt.hash, 2226591547107946498);
+
+        // Bytecode edited while keeping the `hash` field
+        let tampered = JWT_JSON.replacen("\"bytecode\":\"H4sI", "\"bytecode\":\"H4sJ", 1);
+        assert_ne!(tampered, JWT_JSON);
+        let result = CircuitArtifact::from_bytes_pinned(tampered.as_bytes(), &pin);
+        assert!(matches!(result, Err(StealthnoteError::CircuitMismatch(_))));
+
+        let wrong_hash = ArtifactPin {
+            hash: Some(9042762978155049072),
+            ..Default::default()
+        };
+        assert!(CircuitArtifact::from_bytes_pinned(JWT_JSON.as_bytes(), &wrong_hash).is_err());
+        let wrong_noir = ArtifactPin {
+            sha256: Some(sha256),
+            noir_version: Some("1.0.0-beta.2".to_string()),
+            ..Default::default()
+        };
+        assert!(CircuitArtifact::from_bytes_pinned(JWT_JSON.as_bytes(), &wrong_noir).is_err());
+        let result = CircuitArtifact::from_bytes_pinned(JWT_JSON.as_bytes(), &Default::default());
+        assert!(matches!(result, Err(StealthnoteError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_nested_and_flat_inputs_match() {
+        let jwt = CircuitArtifact::from_json(JWT_JSON).unwrap();
+
+        let nested: Value =
+            serde_json::from_str(&fs::read_to_string("public/jwt_input.json").unwrap()).unwrap();
+        let from_value = jwt.witness_from_value(&nested).unwrap();
+        assert_eq!(from_value.len(), jwt.witness_len());
+
+        let flat: HashMap<String, Vec<String>> = serde_json::from_str(
+            &fs::read_to_string("public/jwt_input_snapshot_real.json").unwrap(),
+        )
+        .unwrap();
+        let from_map = jwt.witness_from_map(&flat).unwrap();
+        assert_eq!(from_map.len(), jwt.witness_len());
+    }
+
+    #[test]
+    fn test_zkemail_inputs_with_hex_fields() {
+        let zkemail = CircuitArtifact::from_json(ZKEMAIL_JSON).unwrap();
+        let inputs: Value =
+            serde_json::from_str(&fs::read_to_string("public/zkemail_input.json").unwrap())
+                .unwrap();
+        let witness = zkemail.witness_from_value(&inputs).unwrap();
+        assert_eq!(witness.len(), zkemail.witness_len());
+        // pubkey.modulus[0] is given as hex
+        assert_eq!(
+            witness[577],
+            BigUint::parse_bytes(b"95d4c106145000c13aeeedd678b05f", 16)
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_type_checks() {
+        let u8_array = AbiType::Array {
+            length: 2,
+            typ: Box::new(AbiType::Integer {
+                sign: Sign::Unsigned,
+                width: 8,
+            }),
+        };
+        let mut out = vec![];
+        assert!(u8_array.flatten(&json!([1, 255]), "a", &mut out).is_ok());
+        assert!(u8_array.flatten(&json!([1, 256]), "a", &mut out).is_err());
+        assert!(u8_array.flatten(&json!([1]), "a", &mut out).is_err());
+        assert!(u8_array.flatten(&json!("0x01"), "a", &mut out).is_err());
+
+        let i8_type = AbiType::Integer {
+            sign: Sign::Signed,
+            width: 8,
+        };
+        out.clear();
+        i8_type.flatten(&json!(-1), "b", &mut out).unwrap();
+        assert_eq!(out, vec!["255"]);
+        assert!(i8_type.flatten(&json!(128), "b", &mut out).is_err());
+
+        assert!(AbiType::Field
+            .flatten(&json!(BN254_MODULUS), "c", &mut out)
+            .is_err());
+    }
+
+    #[test]
+    fn test_missing_and_unknown_inputs() {
+        let jwt = CircuitArtifact::from_json(JWT_JSON).unwrap();
+        let mut nested: Value =
+            serde_json::from_str(&fs::read_to_string("public/jwt_input.json").unwrap()).unwrap();
+
+        nested["nonce"] = json!("1");
+        assert!(jwt.witness_from_value(&nested).is_err());
+
+        nested.as_object_mut().unwrap().remove("nonce");
+        nested.as_object_mut().unwrap().remove("domain");
+        assert!(jwt.witness_from_value(&nested).is_err());
+    }
+}
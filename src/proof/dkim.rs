@@ -0,0 +1,485 @@
+use super::rsa::RsaPublicKey;
+use crate::error::StealthnoteError;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use sha2::{Digest, Sha256};
+
+/// DKIM canonicalization algorithm (RFC 6376, section 3.4).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Canonicalization {
+    Simple,
+    Relaxed,
+}
+
+/// One RFC 5322 header field, kept verbatim (folding included, final CRLF excluded).
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeaderField {
+    pub name: String,
+    pub raw: String,
+}
+
+/// A parsed `.eml`: header fields in order and the body bytes.
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub headers: Vec<HeaderField>,
+    pub body: Vec<u8>,
+}
+
+/// The tags of a `DKIM-Signature` header that matter for verification.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DkimSignature {
+    pub algorithm: String,
+    pub domain: String,
+    pub selector: String,
+    pub header_canonicalization: Canonicalization,
+    pub body_canonicalization: Canonicalization,
+    /// `h=`, lowercased, in signing order.
+    pub signed_headers: Vec<String>,
+    pub body_hash: Vec<u8>,
+    pub signature: Vec<u8>,
+    pub body_length: Option<usize>,
+    /// `t=`, seconds since the Unix epoch.
+    pub timestamp: Option<i64>,
+    raw: String,
+}
+
+/// The canonicalized header data a DKIM signature covers, and where each
+/// signed field landed in it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignedHeaders {
+    pub data: Vec<u8>,
+    fields: Vec<(String, usize, usize)>,
+}
+
+impl Email {
+    /// Parse a raw message. Bare LF line endings (common in exports) are read as CRLF.
+    pub fn parse(eml: &[u8]) -> Result<Self, StealthnoteError> {
+        let eml = normalize_line_endings(eml);
+        let (header, body) = match find(&eml, b"\r\n\r\n") {
+            Some(end) => (&eml[..end + 2], eml[end + 4..].to_vec()),
+            None => (&eml[..], vec![]),
+        };
+        let header =
+            std::str::from_utf8(header).map_err(|_| invalid_email("header is not UTF-8"))?;
+
+        let mut headers: Vec<HeaderField> = vec![];
+        for line in header.split_terminator("\r\n") {
+            if line.starts_with([' ', '\t']) {
+                let field = headers
+                    .last_mut()
+                    .ok_or_else(|| invalid_email("message starts with a continuation line"))?;
+                field.raw.push_str("\r\n");
+                field.raw.push_str(line);
+                continue;
+            }
+            let (name, _) = line
+                .split_once(':')
+                .ok_or_else(|| invalid_email(&format!("malformed header line {:?}", line)))?;
+            headers.push(HeaderField {
+                name: name.trim_end().to_string(),
+                raw: line.to_string(),
+            });
+        }
+        if headers.is_empty() {
+            return Err(invalid_email("no header fields"));
+        }
+        Ok(Email { headers, body })
+    }
+
+    /// The first `DKIM-Signature`, or the first one signed by `domain`.
+    pub fn dkim_signature(&self, domain: Option<&str>) -> Result<DkimSignature, StealthnoteError> {
+        let mut found = None;
+        for field in self.fields("dkim-signature") {
+            let signature = DkimSignature::parse(&field.raw)?;
+            if domain.map_or(true, |d| signature.domain.eq_ignore_ascii_case(d)) {
+                found = Some(signature);
+                break;
+            }
+        }
+        found.ok_or_else(|| match domain {
+            Some(domain) => invalid_email(&format!("no DKIM-Signature from {}", domain)),
+            None => invalid_email("no DKIM-Signature header"),
+        })
+    }
+
+    /// Canonicalize the headers `signature` covers, ending with the signature
+    /// header itself with an empty `b=` and no trailing CRLF.
+    pub fn signed_headers(&self, signature: &DkimSignature) -> SignedHeaders {
+        let canon = signature.header_canonicalization;
+        let mut data = vec![];
+        let mut fields = vec![];
+        // Repeated names in h= select instances from the bottom up
+        let mut used: Vec<(String, usize)> = vec![];
+        for name in &signature.signed_headers {
+            let instances: Vec<&HeaderField> = self.fields(name).collect();
+            let skip = match used.iter_mut().find(|(n, _)| n == name) {
+                Some((_, count)) => {
+                    *count += 1;
+                    *count - 1
+                }
+                None => {
+                    used.push((name.clone(), 1));
+                    0
+                }
+            };
+            let Some(field) = instances.iter().rev().nth(skip) else {
+                continue;
+            };
+            let canonical = canonicalize_header(&field.raw, canon);
+            if !fields.iter().any(|(n, _, _)| n == name) {
+                fields.push((name.clone(), data.len(), canonical.len()));
+            }
+            data.extend(canonical.as_bytes());
+            data.extend(b"\r\n");
+        }
+        let unsigned = strip_signature_value(&signature.raw);
+        data.extend(canonicalize_header(&unsigned, canon).as_bytes());
+        SignedHeaders { data, fields }
+    }
+
+    /// Check `bh=` against the canonicalized body.
+    pub fn check_body_hash(&self, signature: &DkimSignature) -> Result<(), StealthnoteError> {
+        let mut body = canonicalize_body(&self.body, signature.body_canonicalization);
+        if let Some(length) = signature.body_length {
+            body.truncate(length);
+        }
+        if Sha256::digest(&body).as_slice() != signature.body_hash {
+            return Err(invalid_email("body hash does not match the DKIM signature"));
+        }
+        Ok(())
+    }
+
+    fn fields<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a HeaderField> + 'a {
+        self.headers
+            .iter()
+            .filter(move |f| f.name.eq_ignore_ascii_case(name))
+    }
+}
+
+impl DkimSignature {
+    /// Parse a raw `DKIM-Signature:` header field.
+    pub fn parse(raw: &str) -> Result<Self, StealthnoteError> {
+        let (_, value) = raw
+            .split_once(':')
+            .ok_or_else(|| invalid_email("malformed DKIM-Signature"))?;
+        let tags = parse_tags(value);
+        let tag = |name: &str| {
+            tags.iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str())
+        };
+        let required = |name: &str| {
+            tag(name).ok_or_else(|| invalid_email(&format!("DKIM-Signature has no {}= tag", name)))
+        };
+
+        if required("v")? != "1" {
+            return Err(invalid_email("unsupported DKIM-Signature version"));
+        }
+        let (header_canonicalization, body_canonicalization) = match tag("c") {
+            None => (Canonicalization::Simple, Canonicalization::Simple),
+            Some(c) => {
+                let (header, body) = c.split_once('/').unwrap_or((c, "simple"));
+                (
+                    parse_canonicalization(header)?,
+                    parse_canonicalization(body)?,
+                )
+            }
+        };
+        let base64 = |name: &str| {
+            BASE64_STANDARD
+                .decode(required(name)?)
+                .map_err(|e| invalid_email(&format!("DKIM-Signature {}=: {}", name, e)))
+        };
+
+        Ok(DkimSignature {
+            algorithm: required("a")?.to_ascii_lowercase(),
+            domain: required("d")?.to_string(),
+            selector: required("s")?.to_string(),
+            header_canonicalization,
+            body_canonicalization,
+            signed_headers: required("h")?
+                .split(':')
+                .map(|h| h.trim().to_ascii_lowercase())
+                .filter(|h| !h.is_empty())
+                .collect(),
+            body_hash: base64("bh")?,
+            signature: base64("b")?,
+            body_length: tag("l")
+                .map(|l| {
+                    l.parse()
+                        .map_err(|_| invalid_email("DKIM-Signature l= is not a number"))
+                })
+                .transpose()?,
+            timestamp: tag("t")
+                .map(|t| {
+                    t.parse()
+                        .map_err(|_| invalid_email("DKIM-Signature t= is not a number"))
+                })
+                .transpose()?,
+            raw: raw.to_string(),
+        })
+    }
+
+    /// Check the signature over `headers` with the sender's DKIM key.
+    pub fn verify(
+        &self,
+        headers: &SignedHeaders,
+        key: &RsaPublicKey,
+    ) -> Result<(), StealthnoteError> {
+        if self.algorithm != "rsa-sha256" {
+            return Err(StealthnoteError::Unsupported(format!(
+                "DKIM algorithm {}, only rsa-sha256 is supported",
+                self.algorithm
+            )));
+        }
+        if !key.verify_pkcs1_sha256(&headers.data, &self.signature) {
+            return Err(invalid_email(&format!(
+                "DKIM signature does not verify with the {}._domainkey.{} key",
+                self.selector, self.domain
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl SignedHeaders {
+    /// Offset and length of the signed `name` field (name included), as the
+    /// circuit's `Sequence` inputs expect.
+    pub fn field(&self, name: &str) -> Option<(usize, usize)> {
+        self.fields
+            .iter()
+            .find(|(n, _, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, index, length)| (*index, *length))
+    }
+}
+
+/// Parse a DKIM key: the `selector._domainkey` TXT record (`v=DKIM1; k=rsa; p=...`,
+/// quoted chunks allowed) or just the base64 `p=` value.
+pub fn parse_dkim_key(record: &str) -> Result<RsaPublicKey, StealthnoteError> {
+    let record = record.replace('"', "");
+    let tagged = record.contains(';')
+        || ["v=", "k=", "p="]
+            .iter()
+            .any(|t| record.trim_start().starts_with(t));
+    let p = if tagged {
+        let tags = parse_tags(&record);
+        if let Some((_, k)) = tags.iter().find(|(n, _)| n == "k") {
+            if k != "rsa" {
+                return Err(StealthnoteError::Unsupported(format!(
+                    "DKIM key type {}",
+                    k
+                )));
+            }
+        }
+        match tags.into_iter().find(|(n, _)| n == "p") {
+            Some((_, p)) if p.is_empty() => {
+                return Err(StealthnoteError::InvalidKey(
+                    "DKIM key has been revoked".to_string(),
+                ))
+            }
+            Some((_, p)) => p,
+            None => {
+                return Err(StealthnoteError::InvalidKey(
+                    "DKIM record has no p= tag".to_string(),
+                ))
+            }
+        }
+    } else {
+        record.split_whitespace().collect()
+    };
+    let der = BASE64_STANDARD
+        .decode(p)
+        .map_err(|e| StealthnoteError::InvalidKey(format!("DKIM key: {}", e)))?;
+    RsaPublicKey::from_der(&der)
+}
+
+/// RFC 6376, section 3.4.2 (relaxed) or 3.4.1 (simple, unchanged).
+pub fn canonicalize_header(raw: &str, canon: Canonicalization) -> String {
+    if canon == Canonicalization::Simple {
+        return raw.to_string();
+    }
+    let (name, value) = raw.split_once(':').unwrap_or((raw, ""));
+    let value = value.replace("\r\n", "");
+    let value = value
+        .split([' ', '\t'])
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>();
+    format!(
+        "{}:{}",
+        name.trim_end().to_ascii_lowercase(),
+        value.join(" ")
+    )
+}
+
+/// RFC 6376, section 3.4.3 (simple) or 3.4.4 (relaxed).
+pub fn canonicalize_body(body: &[u8], canon: Canonicalization) -> Vec<u8> {
+    let mut lines: Vec<Vec<u8>> = body
+        .split(|b| *b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line).to_vec())
+        .collect();
+    if body.ends_with(b"\n") {
+        lines.pop();
+    }
+    if canon == Canonicalization::Relaxed {
+        for line in lines.iter_mut() {
+            let mut compact = Vec::with_capacity(line.len());
+            for &b in line.iter() {
+                let wsp = b == b' ' || b == b'\t';
+                if !wsp {
+                    compact.push(b);
+                } else if compact.last() != Some(&b' ') {
+                    compact.push(b' ');
+                }
+            }
+            if compact.last() == Some(&b' ') {
+                compact.pop();
+            }
+            *line = compact;
+        }
+    }
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    if lines.is_empty() {
+        return match canon {
+            Canonicalization::Simple => b"\r\n".to_vec(),
+            Canonicalization::Relaxed => vec![],
+        };
+    }
+    lines
+        .into_iter()
+        .flat_map(|line| [line, b"\r\n".to_vec()])
+        .flatten()
+        .collect()
+}
+
+/// `tag=value` pairs of a DKIM tag list, with folding whitespace removed.
+fn parse_tags(list: &str) -> Vec<(String, String)> {
+    list.split(';')
+        .filter_map(|tag| {
+            let (name, value) = tag.split_once('=')?;
+            let value: String = value.split_whitespace().collect();
+            Some((name.trim().to_string(), value))
+        })
+        .collect()
+}
+
+/// Empty the `b=` tag's value, keeping everything else byte for byte.
+fn strip_signature_value(raw: &str) -> String {
+    let (name, value) = raw.split_once(':').unwrap_or((raw, ""));
+    let tags: Vec<String> = value
+        .split(';')
+        .map(|tag| match tag.split_once('=') {
+            Some((tag_name, _)) if tag_name.trim() == "b" => format!("{}=", tag_name),
+            _ => tag.to_string(),
+        })
+        .collect();
+    format!("{}:{}", name, tags.join(";"))
+}
+
+fn parse_canonicalization(name: &str) -> Result<Canonicalization, StealthnoteError> {
+    match name {
+        "simple" => Ok(Canonicalization::Simple),
+        "relaxed" => Ok(Canonicalization::Relaxed),
+        _ => Err(invalid_email(&format!("unknown canonicalization {}", name))),
+    }
+}
+
+fn normalize_line_endings(eml: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(eml.len());
+    for (i, &b) in eml.iter().enumerate() {
+        if b == b'\n' && (i == 0 || eml[i - 1] != b'\r') {
+            out.push(b'\r');
+        }
+        out.push(b);
+    }
+    out
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn invalid_email(reason: &str) -> StealthnoteError {
+    StealthnoteError::InvalidInput(format!("email: {}", reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canonicalization() {
+        let raw = "Subject :  This  is\r\n\t a test \t";
+        assert_eq!(
+            canonicalize_header(raw, Canonicalization::Relaxed),
+            "subject:This is a test"
+        );
+        assert_eq!(canonicalize_header(raw, Canonicalization::Simple), raw);
+
+        // Empty body hashes from RFC 6376, section 3.4.3 and 3.4.4
+        let hash = |body: &[u8], canon| {
+            BASE64_STANDARD.encode(Sha256::digest(canonicalize_body(body, canon)))
+        };
+        assert_eq!(
+            hash(b"", Canonicalization::Simple),
+            "frcCV1k9oG9oKj3dpUqdJg1PxRT2RSN/XKdLCPjaYaY="
+        );
+        assert_eq!(
+            hash(b"\r\n\r\n", Canonicalization::Relaxed),
+            "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+        );
+        assert_eq!(
+            canonicalize_body(b" C \r\nD \t E\r\n\r\n\r\n", Canonicalization::Relaxed),
+            b" C\r\nD E\r\n"
+        );
+        assert_eq!(
+            canonicalize_body(b" C \r\nD \t E\r\n\r\n\r\n", Canonicalization::Simple),
+            b" C \r\nD \t E\r\n"
+        );
+    }
+
+    #[test]
+    fn test_signed_header_selection() {
+        let eml = b"DKIM-Signature: v=1; a=rsa-sha256; d=example.com; s=sel;\n  h=from:subject:subject:to; bh=AAAA; b=AAAA\nSubject: first\nFrom: a@example.com\nSubject: second\n\nbody\n";
+        let email = Email::parse(eml).unwrap();
+        assert_eq!(email.body, b"body\r\n");
+        let signature = email.dkim_signature(Some("EXAMPLE.com")).unwrap();
+        assert_eq!(signature.header_canonicalization, Canonicalization::Simple);
+        assert_eq!(
+            signature.signed_headers,
+            ["from", "subject", "subject", "to"]
+        );
+
+        let signed = email.signed_headers(&signature);
+        assert_eq!(
+            String::from_utf8(signed.data.clone()).unwrap(),
+            "From: a@example.com\r\nSubject: second\r\nSubject: first\r\nDKIM-Signature: v=1; a=rsa-sha256; d=example.com; s=sel;\r\n  h=from:subject:subject:to; bh=AAAA; b="
+        );
+        assert_eq!(signed.field("subject"), Some((21, 15)));
+        assert_eq!(signed.field("to"), None);
+
+        assert!(email.dkim_signature(Some("gmail.com")).is_err());
+        assert!(Email::parse(b" folded\r\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_dkim_key_record() {
+        let record =
+            "\"v=DKIM1; k=rsa; \" \"p=MCgCIQCAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAASNFZwIDAQAB\"";
+        let key = parse_dkim_key(record).unwrap();
+        assert_eq!(key.key_bits(), 256);
+        assert_eq!(key.exponent, 65537u32.into());
+        assert_eq!(
+            parse_dkim_key("MCgCIQCAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAASNFZwIDAQAB").unwrap(),
+            key
+        );
+        assert!(matches!(
+            parse_dkim_key("v=DKIM1; p="),
+            Err(StealthnoteError::InvalidKey(_))
+        ));
+        assert!(matches!(
+            parse_dkim_key("v=DKIM1; k=ed25519; p=AAAA"),
+            Err(StealthnoteError::Unsupported(_))
+        ));
+    }
+}
@@ -0,0 +1,478 @@
+use super::dkim::{parse_dkim_key, DkimSignature};
+use super::rsa::RsaPublicKey;
+use crate::error::StealthnoteError;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+/// A DKIM key published at `selector._domainkey.domain` and when it was in use.
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DkimKeyRecord {
+    pub domain: String,
+    pub selector: String,
+    /// The TXT record (`v=DKIM1; k=rsa; p=...`) or its base64 `p=` value.
+    pub public_key: String,
+    /// Seconds since the Unix epoch; `None` is open-ended.
+    #[serde(default)]
+    pub valid_from: Option<i64>,
+    #[serde(default)]
+    pub valid_until: Option<i64>,
+}
+
+impl DkimKeyRecord {
+    pub fn key(&self) -> Result<RsaPublicKey, StealthnoteError> {
+        parse_dkim_key(&self.public_key)
+    }
+
+    fn is_valid_at(&self, at: Option<i64>) -> bool {
+        match at {
+            Some(at) => {
+                self.valid_from.map_or(true, |from| from <= at)
+                    && self.valid_until.map_or(true, |until| at < until)
+            }
+            None => self.valid_until.is_none(),
+        }
+    }
+}
+
+/// Looks up DKIM keys the registry doesn't know, e.g. a DNS TXT query.
+#[uniffi::export(with_foreign)]
+pub trait DkimKeyResolver: Send + Sync {
+    /// TXT records at `selector._domainkey.domain`; empty when nothing is
+    /// published or the lookup failed.
+    fn resolve(&self, domain: String, selector: String) -> Vec<String>;
+}
+
+static GLOBAL_REGISTRY: RwLock<Option<Arc<DkimKeyRegistry>>> = RwLock::new(None);
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DkimSnapshot {
+    keys: Vec<DkimKeyRecord>,
+}
+
+/// DKIM keys by (domain, selector), with their rotation history. Loaded from
+/// a JSON or zone-file snapshot; misses go to the resolver, if one is set.
+#[derive(uniffi::Object, Default)]
+pub struct DkimKeyRegistry {
+    keys: RwLock<HashMap<(String, String), Vec<DkimKeyRecord>>>,
+    resolver: RwLock<Option<Arc<dyn DkimKeyResolver>>>,
+}
+
+#[uniffi::export]
+impl DkimKeyRegistry {
+    #[uniffi::constructor]
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Registry holding the keys of a snapshot file.
+    #[uniffi::constructor]
+    pub fn from_snapshot(path: String) -> Result<Arc<Self>, StealthnoteError> {
+        let registry = Self::default();
+        registry.load_snapshot(path)?;
+        Ok(Arc::new(registry))
+    }
+
+    /// Add the keys of a JSON (`{"keys": [...]}`) or zone-file snapshot.
+    /// Returns how many were added.
+    pub fn load_snapshot(&self, path: String) -> Result<u32, StealthnoteError> {
+        let contents = fs::read_to_string(Path::new(&path))?;
+        let records = if contents.trim_start().starts_with('{') {
+            serde_json::from_str::<DkimSnapshot>(&contents)
+                .map_err(|e| StealthnoteError::InvalidInput(format!("DKIM snapshot: {}", e)))?
+                .keys
+        } else {
+            parse_zone(&contents)?
+        };
+        let count = records.len() as u32;
+        for record in records {
+            self.insert(record)?;
+        }
+        Ok(count)
+    }
+
+    pub fn set_resolver(&self, resolver: Arc<dyn DkimKeyResolver>) {
+        *self.resolver.write().unwrap_or_else(|e| e.into_inner()) = Some(resolver);
+    }
+
+    /// Add a key, checking that it parses and its window is not empty.
+    pub fn insert(&self, record: DkimKeyRecord) -> Result<(), StealthnoteError> {
+        record.key()?;
+        if let (Some(from), Some(until)) = (record.valid_from, record.valid_until) {
+            if from >= until {
+                return Err(StealthnoteError::InvalidInput(format!(
+                    "DKIM key {}._domainkey.{} is valid from {} until {}",
+                    record.selector, record.domain, from, until
+                )));
+            }
+        }
+        let mut keys = self.keys.write().unwrap_or_else(|e| e.into_inner());
+        let history = keys
+            .entry(registry_key(&record.domain, &record.selector))
+            .or_default();
+        if !history.contains(&record) {
+            history.push(record);
+        }
+        Ok(())
+    }
+
+    /// Replace the current key of (domain, selector) as of `at`: open-ended
+    /// keys are closed at `at` and `public_key` is valid from then on.
+    pub fn rotate(
+        &self,
+        domain: String,
+        selector: String,
+        public_key: String,
+        at: i64,
+    ) -> Result<(), StealthnoteError> {
+        let record = DkimKeyRecord {
+            domain,
+            selector,
+            public_key,
+            valid_from: Some(at),
+            valid_until: None,
+        };
+        record.key()?;
+        {
+            let mut keys = self.keys.write().unwrap_or_else(|e| e.into_inner());
+            let history = keys
+                .entry(registry_key(&record.domain, &record.selector))
+                .or_default();
+            for old in history.iter_mut().filter(|r| r.valid_until.is_none()) {
+                old.valid_until = Some(at);
+            }
+        }
+        self.insert(record)
+    }
+
+    /// The key of (domain, selector) valid at `at` (seconds since the epoch),
+    /// or the current one. Unknown current keys are resolved and remembered;
+    /// DNS can't tell which key was valid at a past `at`, so those must be in
+    /// the history.
+    pub fn lookup(
+        &self,
+        domain: String,
+        selector: String,
+        at: Option<i64>,
+    ) -> Result<DkimKeyRecord, StealthnoteError> {
+        if let Some(record) = self.find(&domain, &selector, at) {
+            return Ok(record);
+        }
+        let resolver = self
+            .resolver
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        if let (Some(resolver), None) = (resolver, at) {
+            let resolved = resolver.resolve(domain.clone(), selector.clone());
+            // Revoked (empty p=) and non-RSA records are skipped
+            for public_key in resolved.into_iter().filter(|r| parse_dkim_key(r).is_ok()) {
+                self.insert(DkimKeyRecord {
+                    domain: domain.clone(),
+                    selector: selector.clone(),
+                    public_key,
+                    valid_from: None,
+                    valid_until: None,
+                })?;
+            }
+            // DNS only knows the current key
+            if let Some(record) = self.find(&domain, &selector, None) {
+                return Ok(record);
+            }
+        }
+        Err(StealthnoteError::InvalidKey(match at {
+            Some(at) => format!(
+                "no DKIM key for {}._domainkey.{} at {}",
+                selector, domain, at
+            ),
+            None => format!("no DKIM key for {}._domainkey.{}", selector, domain),
+        }))
+    }
+
+    /// Every known key of (domain, selector), oldest first.
+    pub fn history(&self, domain: String, selector: String) -> Vec<DkimKeyRecord> {
+        let keys = self.keys.read().unwrap_or_else(|e| e.into_inner());
+        let mut history = keys
+            .get(&registry_key(&domain, &selector))
+            .cloned()
+            .unwrap_or_default();
+        history.sort_by_key(|r| r.valid_from.unwrap_or(i64::MIN));
+        history
+    }
+
+    /// JSON snapshot of every key, loadable with [`Self::load_snapshot`].
+    pub fn to_json(&self) -> Result<String, StealthnoteError> {
+        let keys = self.keys.read().unwrap_or_else(|e| e.into_inner());
+        let mut snapshot = DkimSnapshot {
+            keys: keys.values().flatten().cloned().collect(),
+        };
+        snapshot.keys.sort_by(|a, b| {
+            (&a.domain, &a.selector, a.valid_from).cmp(&(&b.domain, &b.selector, b.valid_from))
+        });
+        serde_json::to_string_pretty(&snapshot)
+            .map_err(|e| StealthnoteError::Storage(e.to_string()))
+    }
+}
+
+impl DkimKeyRegistry {
+    /// The registry email memberships are verified with; empty unless
+    /// replaced with [`Self::set_global`].
+    pub fn global() -> Arc<DkimKeyRegistry> {
+        if let Some(registry) = &*GLOBAL_REGISTRY.read().unwrap_or_else(|e| e.into_inner()) {
+            return registry.clone();
+        }
+        GLOBAL_REGISTRY
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_or_insert_with(DkimKeyRegistry::new)
+            .clone()
+    }
+
+    pub fn set_global(registry: Arc<DkimKeyRegistry>) {
+        *GLOBAL_REGISTRY.write().unwrap_or_else(|e| e.into_inner()) = Some(registry);
+    }
+
+    /// The key an email's DKIM signature was made with, as of its `t=` timestamp.
+    pub fn key_for(&self, signature: &DkimSignature) -> Result<RsaPublicKey, StealthnoteError> {
+        self.lookup(
+            signature.domain.clone(),
+            signature.selector.clone(),
+            signature.timestamp,
+        )?
+        .key()
+    }
+
+    fn find(&self, domain: &str, selector: &str, at: Option<i64>) -> Option<DkimKeyRecord> {
+        let keys = self.keys.read().unwrap_or_else(|e| e.into_inner());
+        keys.get(&registry_key(domain, selector))?
+            .iter()
+            .filter(|r| r.is_valid_at(at))
+            .max_by_key(|r| r.valid_from.unwrap_or(i64::MIN))
+            .cloned()
+    }
+}
+
+fn registry_key(domain: &str, selector: &str) -> (String, String) {
+    (
+        domain.trim_end_matches('.').to_ascii_lowercase(),
+        selector.to_ascii_lowercase(),
+    )
+}
+
+/// `TXT` records named `selector._domainkey.domain` in a zone file. Other
+/// records, `$` directives and comments are skipped.
+fn parse_zone(zone: &str) -> Result<Vec<DkimKeyRecord>, StealthnoteError> {
+    let mut records = vec![];
+    let mut entry = String::new();
+    let mut depth = 0i32;
+    for line in zone.lines() {
+        let line = strip_zone_comment(line);
+        depth += line.matches('(').count() as i32 - line.matches(')').count() as i32;
+        entry.push(' ');
+        entry.push_str(line);
+        if depth > 0 {
+            continue;
+        }
+        if let Some(record) = zone_record(&entry.replace(['(', ')'], " "))? {
+            records.push(record);
+        }
+        entry.clear();
+    }
+    Ok(records)
+}
+
+fn zone_record(entry: &str) -> Result<Option<DkimKeyRecord>, StealthnoteError> {
+    let entry = entry.trim();
+    if entry.is_empty() || entry.starts_with('$') {
+        return Ok(None);
+    }
+    let Some((name, rest)) = entry.split_once(char::is_whitespace) else {
+        return Ok(None);
+    };
+    let Some((selector, domain)) = name.split_once("._domainkey.") else {
+        return Ok(None);
+    };
+    // [ttl] [class] TXT "..." ...
+    let mut rest = rest;
+    let txt = loop {
+        let (token, tail) = rest
+            .trim_start()
+            .split_once(char::is_whitespace)
+            .unwrap_or((rest.trim_start(), ""));
+        if token.eq_ignore_ascii_case("TXT") {
+            break tail;
+        }
+        if token.is_empty() || token.starts_with('"') {
+            return Ok(None);
+        }
+        rest = tail;
+    };
+    // Quoted character-strings are concatenated
+    let public_key = txt.split('"').skip(1).step_by(2).collect::<String>();
+    if public_key.is_empty() {
+        return Err(StealthnoteError::InvalidInput(format!(
+            "zone file: TXT record of {} is not quoted",
+            name
+        )));
+    }
+    Ok(Some(DkimKeyRecord {
+        domain: domain.trim_end_matches('.').to_string(),
+        selector: selector.to_string(),
+        public_key,
+        valid_from: None,
+        valid_until: None,
+    }))
+}
+
+/// Drop a `;` comment, leaving semicolons inside quoted strings alone.
+fn strip_zone_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const TEST_KEY: &str = "MCgCIQCAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAASNFZwIDAQAB";
+
+    struct FixedResolver {
+        records: Vec<String>,
+        calls: AtomicUsize,
+    }
+
+    impl DkimKeyResolver for FixedResolver {
+        fn resolve(&self, _domain: String, _selector: String) -> Vec<String> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.records.clone()
+        }
+    }
+
+    #[test]
+    fn test_json_snapshot_with_rotation() {
+        let registry = DkimKeyRegistry::from_snapshot("public/dkim_keys.json".to_string()).unwrap();
+        let lookup = |at| registry.lookup("Gmail.com".to_string(), "20230601".to_string(), at);
+
+        let current = lookup(None).unwrap();
+        assert_eq!(current.key().unwrap().key_bits(), 2048);
+        assert_eq!(lookup(Some(1736920853)).unwrap(), current);
+        // Before the rotation in the fixture
+        assert_eq!(
+            lookup(Some(1680000000)).unwrap().key().unwrap().key_bits(),
+            256
+        );
+        assert!(matches!(
+            lookup(Some(1500000000)),
+            Err(StealthnoteError::InvalidKey(_))
+        ));
+
+        registry
+            .rotate(
+                "gmail.com".to_string(),
+                "20230601".to_string(),
+                TEST_KEY.to_string(),
+                1800000000,
+            )
+            .unwrap();
+        assert_eq!(
+            lookup(Some(1736920853)).unwrap(),
+            DkimKeyRecord {
+                valid_until: Some(1800000000),
+                ..current.clone()
+            }
+        );
+        assert_eq!(lookup(None).unwrap().public_key, TEST_KEY);
+        assert_eq!(
+            registry
+                .history("gmail.com".to_string(), "20230601".to_string())
+                .len(),
+            3
+        );
+
+        // Round trip through a snapshot
+        let path = std::env::temp_dir().join("stealthnote_dkim_keys.json");
+        fs::write(&path, registry.to_json().unwrap()).unwrap();
+        let reloaded = DkimKeyRegistry::from_snapshot(path.to_string_lossy().to_string()).unwrap();
+        assert_eq!(
+            reloaded.history("gmail.com".to_string(), "20230601".to_string()),
+            registry.history("gmail.com".to_string(), "20230601".to_string())
+        );
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_zone_snapshot() {
+        let registry = DkimKeyRegistry::new();
+        let added = registry
+            .load_snapshot("public/dkim_keys.zone".to_string())
+            .unwrap();
+        assert_eq!(added, 2);
+
+        let gmail = registry
+            .lookup("gmail.com".to_string(), "20230601".to_string(), None)
+            .unwrap();
+        assert!(gmail.public_key.starts_with("v=DKIM1; k=rsa; p=MIIBIjAN"));
+        assert_eq!(gmail.key().unwrap().key_bits(), 2048);
+        assert!(registry
+            .lookup("example.com".to_string(), "sel".to_string(), None)
+            .is_ok());
+        assert!(registry
+            .lookup("example.com".to_string(), "other".to_string(), None)
+            .is_err());
+    }
+
+    #[test]
+    fn test_resolver_fallback() {
+        let registry = DkimKeyRegistry::new();
+        let resolver = Arc::new(FixedResolver {
+            records: vec![
+                "v=DKIM1; p=".to_string(),
+                format!("v=DKIM1; p={}", TEST_KEY),
+            ],
+            calls: AtomicUsize::new(0),
+        });
+        registry.set_resolver(resolver.clone());
+
+        // DNS only answers for now, not for a past signing time
+        assert!(registry
+            .lookup(
+                "example.com".to_string(),
+                "sel".to_string(),
+                Some(1_700_000_000)
+            )
+            .is_err());
+        assert_eq!(resolver.calls.load(Ordering::SeqCst), 0);
+
+        let record = registry
+            .lookup("example.com".to_string(), "sel".to_string(), None)
+            .unwrap();
+        assert_eq!(record.key().unwrap().key_bits(), 256);
+        // Cached after the first lookup
+        registry
+            .lookup("example.com".to_string(), "sel".to_string(), None)
+            .unwrap();
+        assert_eq!(resolver.calls.load(Ordering::SeqCst), 1);
+
+        let empty = Arc::new(FixedResolver {
+            records: vec![],
+            calls: AtomicUsize::new(0),
+        });
+        registry.set_resolver(empty);
+        assert!(registry
+            .lookup("example.com".to_string(), "missing".to_string(), None)
+            .is_err());
+    }
+}
@@ -0,0 +1,390 @@
+use super::cache::CircuitHandle;
+use super::jwt_proof::{
+    flatten_fields_as_array, jwt_public_inputs, reconstruct_honk_proof, JWT_CIRCUIT_VERSION,
+};
+use super::public_inputs::JwtPublicInputLayout;
+use super::vk::hex_bytes;
+use crate::error::StealthnoteError;
+use byteorder::{BigEndian, ByteOrder};
+use serde::{Deserialize, Serialize};
+
+pub const PROOF_ENVELOPE_VERSION: u8 = 1;
+pub const JWT_CIRCUIT_NAME: &str = "stealthnote_jwt";
+
+const ENVELOPE_MAGIC: &[u8; 4] = b"SNPE";
+const FIELD_BYTES: usize = 32;
+
+/// Self-describing proof: which circuit produced it, the public inputs it
+/// commits to and the raw proof body bb verifies them against.
+///
+/// Binary layout (all integers big-endian):
+/// `"SNPE" | version u8 | name len u16 | name | circuit hash u64 |
+///  has jwt version u8 | [jwt version len u16 | jwt version] |
+///  public input count u32 | inputs (32 bytes each) | proof len u32 | proof`
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProofEnvelope {
+    pub version: u8,
+    pub circuit_name: String,
+    #[serde(with = "u64_as_string")]
+    pub circuit_hash: u64,
+    pub jwt_circuit_version: Option<String>,
+    /// 0x-prefixed, 32-byte field elements in circuit order.
+    pub public_inputs: Vec<String>,
+    /// Proof body without the public inputs, i.e. what stealthnote.xyz stores.
+    #[serde(with = "hex_bytes")]
+    pub proof: Vec<u8>,
+}
+
+impl ProofEnvelope {
+    /// Wrap a proof as returned by bb (field count, public inputs, proof body).
+    pub fn from_bb_proof(
+        circuit_name: &str,
+        circuit: &CircuitHandle,
+        jwt_circuit_version: Option<String>,
+        bb_proof: &[u8],
+    ) -> Result<Self, StealthnoteError> {
+        let (public_inputs, proof) =
+            split_bb_proof(bb_proof, circuit.artifact.num_public_inputs())?;
+        Ok(ProofEnvelope {
+            version: PROOF_ENVELOPE_VERSION,
+            circuit_name: circuit_name.to_string(),
+            circuit_hash: circuit.artifact.hash,
+            jwt_circuit_version,
+            public_inputs,
+            proof,
+        })
+    }
+
+    /// Wrap a proof returned by `prove_jwt`.
+    pub fn from_jwt_proof(
+        bb_proof: &[u8],
+        jwt_circuit_version: Option<String>,
+    ) -> Result<Self, StealthnoteError> {
+        Self::from_bb_proof(
+            JWT_CIRCUIT_NAME,
+            &*CircuitHandle::jwt()?,
+            Some(jwt_circuit_version.unwrap_or_else(|| JWT_CIRCUIT_VERSION.to_string())),
+            bb_proof,
+        )
+    }
+
+    /// Rebuild an envelope from a membership as stored by stealthnote.xyz: the
+    /// stripped proof body plus the metadata the public inputs are derived from.
+    pub fn from_jwt_wire_proof(
+        wire_proof: Vec<u8>,
+        jwt_circuit_version: String,
+        domain: String,
+        google_jwt_pubkey_modulus: String,
+        ephemeral_pubkey: String,
+        ephemeral_pubkey_expiry: String,
+    ) -> Result<Self, StealthnoteError> {
+        let circuit = CircuitHandle::jwt()?;
+        let public_inputs = jwt_public_inputs(
+            &JwtPublicInputLayout::V0_3,
+            domain,
+            google_jwt_pubkey_modulus,
+            ephemeral_pubkey,
+            ephemeral_pubkey_expiry,
+        )?;
+        let envelope = ProofEnvelope {
+            version: PROOF_ENVELOPE_VERSION,
+            circuit_name: JWT_CIRCUIT_NAME.to_string(),
+            circuit_hash: circuit.artifact.hash,
+            jwt_circuit_version: Some(jwt_circuit_version),
+            public_inputs,
+            proof: wire_proof,
+        };
+        envelope.validate()?;
+        Ok(envelope)
+    }
+
+    /// Proof body in the layout the stealthnote.xyz API expects (public inputs stripped).
+    pub fn to_wire_proof(&self) -> Vec<u8> {
+        self.proof.clone()
+    }
+
+    /// Full bb proof, ready for `verify_ultra_honk`.
+    pub fn to_bb_proof(&self) -> Result<Vec<u8>, StealthnoteError> {
+        self.validate()?;
+        Ok(reconstruct_honk_proof(
+            &flatten_fields_as_array(&self.public_inputs),
+            &self.proof,
+            FIELD_BYTES,
+        ))
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, StealthnoteError> {
+        self.validate()?;
+        let mut out =
+            Vec::with_capacity(64 + self.public_inputs.len() * FIELD_BYTES + self.proof.len());
+        out.extend_from_slice(ENVELOPE_MAGIC);
+        out.push(self.version);
+        write_str(&mut out, &self.circuit_name)?;
+        out.extend_from_slice(&self.circuit_hash.to_be_bytes());
+        match &self.jwt_circuit_version {
+            Some(version) => {
+                out.push(1);
+                write_str(&mut out, version)?;
+            }
+            None => out.push(0),
+        }
+        out.extend_from_slice(&(self.public_inputs.len() as u32).to_be_bytes());
+        out.extend_from_slice(&flatten_fields_as_array(&self.public_inputs));
+        out.extend_from_slice(&(self.proof.len() as u32).to_be_bytes());
+        out.extend_from_slice(&self.proof);
+        Ok(out)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, StealthnoteError> {
+        let mut reader = Reader { bytes, pos: 0 };
+        if reader.take(4)? != ENVELOPE_MAGIC {
+            return Err(StealthnoteError::InvalidInput(
+                "Not a proof envelope".to_string(),
+            ));
+        }
+        let version = reader.take(1)?[0];
+        check_version(version)?;
+        let circuit_name = reader.read_str()?;
+        let circuit_hash = BigEndian::read_u64(reader.take(8)?);
+        let jwt_circuit_version = match reader.take(1)?[0] {
+            0 => None,
+            1 => Some(reader.read_str()?),
+            flag => {
+                return Err(StealthnoteError::InvalidInput(format!(
+                    "Invalid proof envelope flag: {}",
+                    flag
+                )))
+            }
+        };
+        let count = BigEndian::read_u32(reader.take(4)?) as usize;
+        let public_inputs = reader
+            .take(count.checked_mul(FIELD_BYTES).ok_or_else(truncated)?)?
+            .chunks(FIELD_BYTES)
+            .map(|field| format!("0x{}", hex::encode(field)))
+            .collect();
+        let proof_len = BigEndian::read_u32(reader.take(4)?) as usize;
+        let proof = reader.take(proof_len)?.to_vec();
+        if reader.pos != bytes.len() {
+            return Err(StealthnoteError::InvalidInput(format!(
+                "{} trailing bytes after proof envelope",
+                bytes.len() - reader.pos
+            )));
+        }
+
+        Ok(ProofEnvelope {
+            version,
+            circuit_name,
+            circuit_hash,
+            jwt_circuit_version,
+            public_inputs,
+            proof,
+        })
+    }
+
+    pub fn to_json(&self) -> Result<String, StealthnoteError> {
+        self.validate()?;
+        serde_json::to_string(self).map_err(|e| StealthnoteError::InvalidInput(e.to_string()))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, StealthnoteError> {
+        let envelope: ProofEnvelope = serde_json::from_str(json)
+            .map_err(|e| StealthnoteError::InvalidInput(format!("proof envelope: {}", e)))?;
+        envelope.validate()?;
+        Ok(envelope)
+    }
+
+    fn validate(&self) -> Result<(), StealthnoteError> {
+        check_version(self.version)?;
+        if self.proof.len() % FIELD_BYTES != 0 {
+            return Err(StealthnoteError::InvalidInput(format!(
+                "Proof body of {} bytes is not a whole number of fields",
+                self.proof.len()
+            )));
+        }
+        for input in &self.public_inputs {
+            let digits = input.strip_prefix("0x").unwrap_or(input);
+            if digits.is_empty()
+                || digits.len() > FIELD_BYTES * 2
+                || !digits.chars().all(|c| c.is_ascii_hexdigit())
+            {
+                return Err(StealthnoteError::InvalidInput(format!(
+                    "Invalid public input: {}",
+                    input
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Split a bb proof into its public inputs (as 0x-prefixed fields) and proof body.
+pub fn split_bb_proof(
+    bb_proof: &[u8],
+    num_public_inputs: usize,
+) -> Result<(Vec<String>, Vec<u8>), StealthnoteError> {
+    let inputs_len = num_public_inputs * FIELD_BYTES;
+    if bb_proof.len() < 4 + inputs_len || (bb_proof.len() - 4) % FIELD_BYTES != 0 {
+        return Err(StealthnoteError::InvalidInput(format!(
+            "Proof of {} bytes cannot hold {} public inputs",
+            bb_proof.len(),
+            num_public_inputs
+        )));
+    }
+    let field_count = BigEndian::read_u32(&bb_proof[..4]) as usize;
+    if field_count != (bb_proof.len() - 4) / FIELD_BYTES {
+        return Err(StealthnoteError::InvalidInput(format!(
+            "Proof header claims {} fields, found {}",
+            field_count,
+            (bb_proof.len() - 4) / FIELD_BYTES
+        )));
+    }
+
+    let (inputs, proof) = bb_proof[4..].split_at(inputs_len);
+    let public_inputs = inputs
+        .chunks(FIELD_BYTES)
+        .map(|field| format!("0x{}", hex::encode(field)))
+        .collect();
+    Ok((public_inputs, proof.to_vec()))
+}
+
+fn check_version(version: u8) -> Result<(), StealthnoteError> {
+    if version != PROOF_ENVELOPE_VERSION {
+        return Err(StealthnoteError::Unsupported(format!(
+            "proof envelope version {}",
+            version
+        )));
+    }
+    Ok(())
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) -> Result<(), StealthnoteError> {
+    let len = u16::try_from(s.len())
+        .map_err(|_| StealthnoteError::InvalidInput(format!("{} is too long", s)))?;
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+fn truncated() -> StealthnoteError {
+    StealthnoteError::InvalidInput("Truncated proof envelope".to_string())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StealthnoteError> {
+        let end = self.pos.checked_add(len).ok_or_else(truncated)?;
+        let slice = self.bytes.get(self.pos..end).ok_or_else(truncated)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn read_str(&mut self) -> Result<String, StealthnoteError> {
+        let len = BigEndian::read_u16(self.take(2)?) as usize;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|e| StealthnoteError::InvalidInput(e.to_string()))
+    }
+}
+
+mod u64_as_string {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    // JS numbers can't hold a full u64 circuit hash
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NUM_JWT_PUBLIC_INPUTS: usize = 85;
+
+    // bb proof layout with dummy public inputs and a 3-field proof body
+    fn fake_jwt_bb_proof() -> Vec<u8> {
+        let mut fields: Vec<u8> = Vec::new();
+        for i in 0..NUM_JWT_PUBLIC_INPUTS {
+            let mut field = [0u8; FIELD_BYTES];
+            field[FIELD_BYTES - 1] = i as u8;
+            fields.extend_from_slice(&field);
+        }
+        fields.extend(std::iter::repeat(0xab).take(3 * FIELD_BYTES));
+
+        let mut proof = ((fields.len() / FIELD_BYTES) as u32).to_be_bytes().to_vec();
+        proof.extend(fields);
+        proof
+    }
+
+    #[test]
+    fn test_envelope_roundtrip() {
+        let bb_proof = fake_jwt_bb_proof();
+        let envelope = ProofEnvelope::from_jwt_proof(&bb_proof, None).unwrap();
+        assert_eq!(envelope.jwt_circuit_version.as_deref(), Some("0.3.1"));
+        assert_eq!(envelope.public_inputs.len(), NUM_JWT_PUBLIC_INPUTS);
+        assert_eq!(envelope.public_inputs[2], format!("0x{:0>64x}", 2));
+
+        // Same bytes the Flutter app strips before posting to the server
+        assert_eq!(envelope.to_wire_proof(), bb_proof[4 + 2720..].to_vec());
+        assert_eq!(envelope.to_bb_proof().unwrap(), bb_proof);
+
+        let bytes = envelope.to_bytes().unwrap();
+        assert_eq!(ProofEnvelope::from_bytes(&bytes).unwrap(), envelope);
+        let json = envelope.to_json().unwrap();
+        assert_eq!(ProofEnvelope::from_json(&json).unwrap(), envelope);
+    }
+
+    #[test]
+    fn test_envelope_from_wire_proof() {
+        let bb_proof = fake_jwt_bb_proof();
+        let wire = bb_proof[4 + 2720..].to_vec();
+        let envelope = ProofEnvelope::from_jwt_wire_proof(
+            wire.clone(),
+            "0.3.1".to_string(),
+            "pse.dev".to_string(),
+            "03Cww27F2O7JxB5Ji9iT9szfKZ4MK-iPzVpQkdLjCuGKfpjaCVAz9zIQ0-7gbZ-8cJRaSLfByWTGMIHRYiX2efdjz1Z9jck0DK9W3mapFrBPvM7AlRni4lPlwUigDd8zxAMDCheqyK3vCOLFW-1xYHt_YGwv8b0dP7rjujarEYlWjeppO_QMNtXdKdT9eZtBEcj_9ms9W0aLdCFNR5AAR3y0kLkKR1H4DW7vncB46rqCJLenhlCbcW0MZ3asqcjqBQ2t9QMRnY83Zf_pNEsCcXlKp4uOQqEvzjAc9ZSr2sOmd_ESZ_3jMlNkCZ4J41TuG-My5illFcW5LajSKvxD3w".to_string(),
+            "17302102366996071265028731047581517700208166805377449770193522591062772282670".to_string(),
+            "2025-05-07T09:07:57.379Z".to_string(),
+        )
+        .unwrap();
+        assert_eq!(envelope.public_inputs.len(), NUM_JWT_PUBLIC_INPUTS);
+        assert_eq!(envelope.to_wire_proof(), wire);
+        assert_eq!(envelope.to_bb_proof().unwrap().len(), bb_proof.len());
+    }
+
+    #[test]
+    fn test_envelope_rejects_malformed() {
+        let bb_proof = fake_jwt_bb_proof();
+        assert!(matches!(
+            split_bb_proof(&bb_proof[..100], NUM_JWT_PUBLIC_INPUTS),
+            Err(StealthnoteError::InvalidInput(_))
+        ));
+        let mut bad_header = bb_proof.clone();
+        bad_header[3] ^= 1;
+        assert!(split_bb_proof(&bad_header, NUM_JWT_PUBLIC_INPUTS).is_err());
+
+        let bytes = ProofEnvelope::from_jwt_proof(&bb_proof, None)
+            .unwrap()
+            .to_bytes()
+            .unwrap();
+        assert!(ProofEnvelope::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(ProofEnvelope::from_bytes(&[bytes.as_slice(), &[0]].concat()).is_err());
+
+        let mut future = bytes.clone();
+        future[4] = PROOF_ENVELOPE_VERSION + 1;
+        assert!(matches!(
+            ProofEnvelope::from_bytes(&future),
+            Err(StealthnoteError::Unsupported(_))
+        ));
+    }
+}
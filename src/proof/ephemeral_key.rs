@@ -34,6 +34,23 @@ fn bytes_to_biguint(bytes: &[u8]) -> BigUint {
     result
 }
 
+/// How long an ephemeral key is valid for, in seconds.
+pub const EPHEMERAL_KEY_LIFETIME: i64 = 7 * 24 * 3600;
+
+/// Poseidon2 of (pubkey >> 3, salt, expiry), the value the app sets as the JWT `nonce`.
+pub fn ephemeral_pubkey_hash(pubkey: &BigUint, salt: &BigUint, expiry: u32) -> Option<BigUint> {
+    let inputs = [
+        (pubkey >> 3u8).to_string(),
+        salt.to_string(),
+        expiry.to_string(),
+    ]
+    .iter()
+    .map(|input| FieldElement::try_from_str(input))
+    .collect::<Option<Vec<_>>>()?;
+    let hash = Poseidon2::hash(&inputs, false);
+    BigUint::from_str(&hash.to_string()).ok()
+}
+
 impl EphemeralKey {
     pub fn generate_ephemeral_key() -> Option<Self> {
         for _ in 0..10 {
@@ -44,7 +61,7 @@ impl EphemeralKey {
             let salt: SigningKey = SigningKey::generate(&mut csprng);
 
             let now = Utc::now();
-            let one_week_later = now + Duration::weeks(1);
+            let one_week_later = now + Duration::seconds(EPHEMERAL_KEY_LIFETIME);
             let expiry_iso_string =
                 one_week_later.to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
             let dt: DateTime<Utc> = match expiry_iso_string.parse() {
@@ -55,28 +72,15 @@ impl EphemeralKey {
             let expiry = dt.timestamp() as u32;
 
             let public_key = bytes_to_biguint(&verifying_key.to_bytes());
-            let public_key_shifted = (public_key >> 3u8).to_string();
-
-            let salt_str = bytes_to_biguint(&salt.to_bytes()[0..30]).to_string();
-            let expiry_str = expiry.to_string();
-            let input1_field_element = FieldElement::try_from_str(&public_key_shifted).unwrap();
-            let input2_field_element = FieldElement::try_from_str(&salt_str).unwrap();
-            let input3_field_element = FieldElement::try_from_str(&expiry_str).unwrap();
-
-            let hash = Poseidon2::hash(
-                &[
-                    input1_field_element,
-                    input2_field_element,
-                    input3_field_element,
-                ],
-                false,
-            );
-
-            if let Ok(ephemeral_pubkey_hash) = BigUint::from_str(&hash.to_string()) {
+            let salt_biguint = bytes_to_biguint(&salt.to_bytes()[0..30]);
+
+            if let Some(ephemeral_pubkey_hash) =
+                ephemeral_pubkey_hash(&public_key, &salt_biguint, expiry)
+            {
                 return Some(EphemeralKey {
                     private_key: signing_key,
                     public_key: verifying_key,
-                    salt: salt_str,
+                    salt: salt_biguint.to_string(),
                     expiry: expiry_iso_string,
                     ephemeral_pubkey_hash,
                 });
@@ -0,0 +1,593 @@
+use chrono::{DateTime, Utc};
+use reqwest::header::{CACHE_CONTROL, EXPIRES};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use super::{
+    ephemeral_key::EPHEMERAL_KEY_LIFETIME, jwks_archive::JwksArchive, jwt_proof::JsonWebKey,
+};
+use crate::error::StealthnoteError;
+
+pub const GOOGLE_ISSUER: &str = "https://accounts.google.com";
+pub const GOOGLE_JWKS_URL: &str = "https://www.googleapis.com/oauth2/v3/certs";
+/// Entra ID issuers are per tenant but share one key set, cached under the
+/// `common` authority.
+pub const MICROSOFT_ISSUER: &str = "https://login.microsoftonline.com/common/v2.0";
+pub const MICROSOFT_JWKS_URL: &str = "https://login.microsoftonline.com/common/discovery/v2.0/keys";
+
+/// Lifetime of a key set served without cache headers.
+const DEFAULT_MAX_AGE: i64 = 3600;
+/// An unknown kid refetches at most this often, so bogus kids can't hammer the issuer.
+const MIN_REFRESH_INTERVAL: i64 = 60;
+
+#[derive(Deserialize)]
+struct JwksDocument {
+    keys: Vec<serde_json::Value>,
+}
+
+/// A JWKS document and how long it may be cached.
+#[derive(Debug, Clone)]
+pub struct FetchedJwks {
+    pub keys: Vec<JsonWebKey>,
+    /// Seconds, from `Cache-Control: max-age` or `Expires`.
+    pub max_age: Option<i64>,
+}
+
+/// The RSA keys of a JWKS document. Keys of other types are skipped.
+pub fn parse_jwks(json: &str) -> Result<Vec<JsonWebKey>, StealthnoteError> {
+    let document: JwksDocument = serde_json::from_str(json)
+        .map_err(|e| StealthnoteError::InvalidKey(format!("JWKS: {}", e)))?;
+    Ok(rsa_keys(document.keys))
+}
+
+fn rsa_keys(keys: Vec<serde_json::Value>) -> Vec<JsonWebKey> {
+    keys.into_iter()
+        .filter(|key| key.get("kty").and_then(|kty| kty.as_str()) == Some("RSA"))
+        .filter_map(|key| serde_json::from_value(key).ok())
+        .collect()
+}
+
+pub async fn fetch_jwks(url: &str) -> Result<FetchedJwks, StealthnoteError> {
+    let response = reqwest::get(url).await?.error_for_status()?;
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let max_age = header(CACHE_CONTROL)
+        .and_then(|value| cache_control_max_age(&value))
+        .or_else(|| {
+            let expires = DateTime::parse_from_rfc2822(&header(EXPIRES)?).ok()?;
+            Some(
+                (expires.with_timezone(&Utc) - Utc::now())
+                    .num_seconds()
+                    .max(0),
+            )
+        });
+    Ok(FetchedJwks {
+        keys: parse_jwks(&response.text().await?)?,
+        max_age,
+    })
+}
+
+/// [`fetch_jwks`] for sync callers, on its own thread and runtime so it also
+/// works from inside an async context.
+pub fn fetch_jwks_blocking(url: &str) -> Result<FetchedJwks, StealthnoteError> {
+    let url = url.to_string();
+    std::thread::spawn(move || {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(fetch_jwks(&url))
+    })
+    .join()
+    .map_err(|_| StealthnoteError::Network("JWKS fetch thread panicked".to_string()))?
+}
+
+/// Keys at `location`: an http(s) JWKS endpoint or a local JWKS file.
+pub fn load_jwks(location: &str) -> Result<FetchedJwks, StealthnoteError> {
+    if location.starts_with("https://") || location.starts_with("http://") {
+        fetch_jwks_blocking(location)
+    } else {
+        Ok(FetchedJwks {
+            keys: parse_jwks(&fs::read_to_string(location)?)?,
+            max_age: None,
+        })
+    }
+}
+
+pub fn find_key<'a>(keys: &'a [JsonWebKey], kid: &str) -> Result<&'a JsonWebKey, StealthnoteError> {
+    keys.iter()
+        .find(|key| key.kid == kid)
+        .ok_or_else(|| StealthnoteError::InvalidKey(format!("no key {} in the JWKS", kid)))
+}
+
+fn cache_control_max_age(value: &str) -> Option<i64> {
+    value
+        .split(',')
+        .find_map(|directive| directive.trim().strip_prefix("max-age="))
+        .and_then(|age| age.trim().parse().ok())
+}
+
+/// What the store knows about one issuer.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct IssuerKeys {
+    /// Where to refresh from; `None` for fixtures, which never expire.
+    jwks_uri: Option<String>,
+    keys: Vec<JsonWebKey>,
+    fetched_at: i64,
+    expires_at: i64,
+}
+
+static GLOBAL_STORE: RwLock<Option<Arc<JwksStore>>> = RwLock::new(None);
+
+/// Fixture file: a JWKS document plus the issuer it belongs to.
+#[derive(Deserialize)]
+struct JwksFixture {
+    issuer: String,
+    keys: Vec<serde_json::Value>,
+}
+
+type Fetcher = dyn Fn(&str) -> Result<FetchedJwks, StealthnoteError> + Send + Sync;
+
+/// Signing keys by (issuer, kid). Key sets are refetched once their cache
+/// lifetime runs out or when a proof names a kid the store hasn't seen; a
+/// stale set is still served if the issuer can't be reached. Every key seen is
+/// recorded in a [`JwksArchive`], which answers for keys the issuer has since
+/// rotated out.
+#[derive(uniffi::Object)]
+pub struct JwksStore {
+    issuers: RwLock<BTreeMap<String, IssuerKeys>>,
+    /// Cache file rewritten after every refresh.
+    path: Option<PathBuf>,
+    fetch: Box<Fetcher>,
+    archive: RwLock<Arc<JwksArchive>>,
+}
+
+#[uniffi::export]
+impl JwksStore {
+    /// Store that knows where the Google and Microsoft keys live.
+    #[uniffi::constructor]
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::with_fetcher(None, Box::new(load_jwks)))
+    }
+
+    /// [`Self::new`], persisted to `path`, with its archive kept next to it
+    /// (`jwks.json` archives to `jwks.archive.json`). Existing files are loaded.
+    #[uniffi::constructor]
+    pub fn open(path: String) -> Result<Arc<Self>, StealthnoteError> {
+        let store = Self::with_fetcher(Some(PathBuf::from(&path)), Box::new(load_jwks));
+        let archive_path = Path::new(&path).with_extension("archive.json");
+        store.set_archive(JwksArchive::open(archive_path.display().to_string())?);
+        if Path::new(&path).exists() {
+            let cached: BTreeMap<String, IssuerKeys> =
+                serde_json::from_str(&fs::read_to_string(&path)?).map_err(|e| {
+                    StealthnoteError::Storage(format!("JWKS cache {}: {}", path, e))
+                })?;
+            store.write().extend(cached);
+        }
+        Ok(Arc::new(store))
+    }
+
+    /// Refresh `issuer`'s keys from `jwks_uri` (URL or file) from now on.
+    pub fn add_issuer(&self, issuer: String, jwks_uri: String) {
+        let mut issuers = self.write();
+        let entry = issuers.entry(issuer).or_default();
+        if entry.jwks_uri.as_ref() != Some(&jwks_uri) {
+            entry.jwks_uri = Some(jwks_uri);
+            entry.expires_at = 0;
+        }
+    }
+
+    /// Load every `*.json` fixture in `dir` (`{"issuer": ..., "keys": [...]}`)
+    /// for offline use: fixture issuers are never refetched. Returns the issuers.
+    pub fn load_fixtures(&self, dir: String) -> Result<Vec<String>, StealthnoteError> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(&dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+        let mut loaded = Vec::new();
+        for path in paths {
+            let fixture: JwksFixture =
+                serde_json::from_str(&fs::read_to_string(&path)?).map_err(|e| {
+                    StealthnoteError::InvalidKey(format!("JWKS fixture {}: {}", path.display(), e))
+                })?;
+            let keys = rsa_keys(fixture.keys);
+            let now = Utc::now().timestamp();
+            let _ = self
+                .archive()
+                .record(fixture.issuer.clone(), keys.clone(), now);
+            self.write().insert(
+                fixture.issuer.clone(),
+                IssuerKeys {
+                    jwks_uri: None,
+                    keys,
+                    fetched_at: now,
+                    expires_at: i64::MAX,
+                },
+            );
+            loaded.push(fixture.issuer);
+        }
+        Ok(loaded)
+    }
+
+    /// The current key `kid` of `issuer`, refetching the issuer's keys if needed.
+    pub fn key(&self, issuer: String, kid: String) -> Result<JsonWebKey, StealthnoteError> {
+        self.key_at(&issuer, &kid, Utc::now().timestamp(), None)
+    }
+
+    /// The key `kid` of `issuer` a proof for an ephemeral key expiring at
+    /// `ephemeral_expiry` (seconds since the epoch) was made with. Keys the
+    /// issuer rotated out are taken from the archive, but only if the
+    /// ephemeral key can have been made while the issuer still served them.
+    pub fn key_for_proof(
+        &self,
+        issuer: String,
+        kid: String,
+        ephemeral_expiry: i64,
+    ) -> Result<JsonWebKey, StealthnoteError> {
+        self.key_at(
+            &issuer,
+            &kid,
+            Utc::now().timestamp(),
+            Some(ephemeral_expiry),
+        )
+    }
+
+    /// Every cached key of `issuer`, without refreshing.
+    pub fn keys(&self, issuer: String) -> Vec<JsonWebKey> {
+        self.read()
+            .get(&issuer)
+            .map(|entry| entry.keys.clone())
+            .unwrap_or_default()
+    }
+
+    /// Refetch `issuer`'s keys now.
+    pub fn refresh(&self, issuer: String) -> Result<(), StealthnoteError> {
+        self.refresh_at(&issuer, Utc::now().timestamp())
+    }
+
+    pub fn save(&self, path: String) -> Result<(), StealthnoteError> {
+        let json = serde_json::to_string_pretty(&*self.read())
+            .map_err(|e| StealthnoteError::Storage(e.to_string()))?;
+        Ok(fs::write(path, json)?)
+    }
+
+    pub fn archive(&self) -> Arc<JwksArchive> {
+        self.archive
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Record keys in, and fall back to, `archive` instead of the store's own
+    /// in-memory one.
+    pub fn set_archive(&self, archive: Arc<JwksArchive>) {
+        *self.archive.write().unwrap_or_else(|e| e.into_inner()) = archive;
+    }
+}
+
+impl JwksStore {
+    pub fn with_fetcher(path: Option<PathBuf>, fetch: Box<Fetcher>) -> Self {
+        let issuers = [
+            (GOOGLE_ISSUER, GOOGLE_JWKS_URL),
+            (MICROSOFT_ISSUER, MICROSOFT_JWKS_URL),
+        ]
+        .into_iter()
+        .map(|(issuer, jwks_uri)| {
+            let entry = IssuerKeys {
+                jwks_uri: Some(jwks_uri.to_string()),
+                ..Default::default()
+            };
+            (issuer.to_string(), entry)
+        })
+        .collect();
+        JwksStore {
+            issuers: RwLock::new(issuers),
+            path,
+            fetch,
+            archive: RwLock::new(JwksArchive::new()),
+        }
+    }
+
+    /// The store the providers verify with; an in-memory [`Self::new`]
+    /// unless replaced with [`Self::set_global`].
+    pub fn global() -> Arc<JwksStore> {
+        if let Some(store) = &*GLOBAL_STORE.read().unwrap_or_else(|e| e.into_inner()) {
+            return store.clone();
+        }
+        GLOBAL_STORE
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_or_insert_with(JwksStore::new)
+            .clone()
+    }
+
+    pub fn set_global(store: Arc<JwksStore>) {
+        *GLOBAL_STORE.write().unwrap_or_else(|e| e.into_inner()) = Some(store);
+    }
+
+    pub(crate) fn key_at(
+        &self,
+        issuer: &str,
+        kid: &str,
+        now: i64,
+        ephemeral_expiry: Option<i64>,
+    ) -> Result<JsonWebKey, StealthnoteError> {
+        self.current_key_at(issuer, kid, now).or_else(|e| {
+            let Some(expiry) = ephemeral_expiry else {
+                return Err(e);
+            };
+            self.archive()
+                .lookup(issuer.to_string(), kid.to_string())
+                .filter(|archived| expiry <= archived.last_seen + EPHEMERAL_KEY_LIFETIME)
+                .map(|archived| archived.key)
+                .ok_or(e)
+        })
+    }
+
+    fn current_key_at(
+        &self,
+        issuer: &str,
+        kid: &str,
+        now: i64,
+    ) -> Result<JsonWebKey, StealthnoteError> {
+        let (cached, refresh) = {
+            let issuers = self.read();
+            let entry = issuers.get(issuer).ok_or_else(|| {
+                StealthnoteError::Unsupported(format!("no JWKS known for issuer {}", issuer))
+            })?;
+            let cached = find_key(&entry.keys, kid).ok().cloned();
+            let stale = now >= entry.expires_at;
+            let unknown = cached.is_none() && now - entry.fetched_at >= MIN_REFRESH_INTERVAL;
+            (cached, entry.jwks_uri.is_some() && (stale || unknown))
+        };
+        if refresh {
+            match self.refresh_at(issuer, now) {
+                Ok(()) => {
+                    let keys = self.keys(issuer.to_string());
+                    return find_key(&keys, kid)
+                        .cloned()
+                        .map_err(|_| no_key(issuer, kid));
+                }
+                // Keep serving a stale key while the issuer is unreachable
+                Err(_) if cached.is_some() => {}
+                Err(e) => return Err(e),
+            }
+        }
+        cached.ok_or_else(|| no_key(issuer, kid))
+    }
+
+    fn refresh_at(&self, issuer: &str, now: i64) -> Result<(), StealthnoteError> {
+        let jwks_uri = self
+            .read()
+            .get(issuer)
+            .and_then(|entry| entry.jwks_uri.clone())
+            .ok_or_else(|| {
+                StealthnoteError::Unsupported(format!("no JWKS location for issuer {}", issuer))
+            })?;
+        let fetched = (self.fetch)(&jwks_uri)?;
+        let _ = self
+            .archive()
+            .record(issuer.to_string(), fetched.keys.clone(), now);
+        {
+            let mut issuers = self.write();
+            let entry = issuers.entry(issuer.to_string()).or_default();
+            entry.keys = fetched.keys;
+            entry.fetched_at = now;
+            entry.expires_at = now + fetched.max_age.unwrap_or(DEFAULT_MAX_AGE);
+        }
+        if let Some(path) = &self.path {
+            // A read-only cache file shouldn't fail verification
+            let _ = self.save(path.display().to_string());
+        }
+        Ok(())
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, BTreeMap<String, IssuerKeys>> {
+        self.issuers.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, BTreeMap<String, IssuerKeys>> {
+        self.issuers.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn no_key(issuer: &str, kid: &str) -> StealthnoteError {
+    StealthnoteError::InvalidKey(format!("{} has no signing key {}", issuer, kid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn jwk(kid: &str) -> JsonWebKey {
+        JsonWebKey {
+            kid: kid.to_string(),
+            n: "0fu2".to_string(),
+            use_: "sig".to_string(),
+            alg: "RS256".to_string(),
+            kty: "RSA".to_string(),
+            e: "AQAB".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_jwks() {
+        // Entra keys carry x5t/x5c/issuer but no alg
+        let json = r#"{"keys": [
+            {"kty": "RSA", "use": "sig", "kid": "CNv0OI3RwqlHFEVnaoMAshCH2XE", "x5t": "CNv0OI3RwqlHFEVnaoMAshCH2XE", "n": "0fu2", "e": "AQAB", "x5c": ["MIIC"], "issuer": "https://login.microsoftonline.com/{tenantid}/v2.0"},
+            {"kty": "EC", "kid": "ec", "crv": "P-256", "x": "AA", "y": "AA"},
+            {"kty": "RSA", "alg": "RS256", "use": "sig", "kid": "google", "n": "3C", "e": "AQAB"}
+        ]}"#;
+        let keys = parse_jwks(json).unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].alg, "");
+        assert_eq!(find_key(&keys, "google").unwrap().alg, "RS256");
+        assert!(matches!(
+            find_key(&keys, "ec"),
+            Err(StealthnoteError::InvalidKey(_))
+        ));
+        assert!(parse_jwks("{}").is_err());
+
+        assert_eq!(
+            cache_control_max_age("public, max-age=21388, must-revalidate, no-transform"),
+            Some(21388)
+        );
+        assert_eq!(cache_control_max_age("no-cache"), None);
+    }
+
+    #[test]
+    fn test_store_refreshes_on_expiry_and_unknown_kid() {
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let counter = fetches.clone();
+        let store = JwksStore::with_fetcher(
+            None,
+            Box::new(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Ok(FetchedJwks {
+                    keys: vec![jwk("a"), jwk("b")],
+                    max_age: Some(600),
+                })
+            }),
+        );
+        let now = 1_746_000_000;
+        assert_eq!(
+            store.key_at(GOOGLE_ISSUER, "a", now, None).unwrap().kid,
+            "a"
+        );
+        assert_eq!(
+            store
+                .key_at(GOOGLE_ISSUER, "b", now + 10, None)
+                .unwrap()
+                .kid,
+            "b"
+        );
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        // Unknown kids refetch, but not more than once a minute
+        assert!(store.key_at(GOOGLE_ISSUER, "c", now + 30, None).is_err());
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        let unknown = store
+            .key_at(GOOGLE_ISSUER, "c", now + 90, None)
+            .unwrap_err();
+        assert!(matches!(unknown, StealthnoteError::InvalidKey(_)));
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+
+        // max-age=600 from the last fetch
+        store.key_at(GOOGLE_ISSUER, "a", now + 600, None).unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        store.key_at(GOOGLE_ISSUER, "a", now + 700, None).unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 3);
+
+        assert!(matches!(
+            store.key_at("https://unknown.example", "a", now, None),
+            Err(StealthnoteError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_store_falls_back_to_archive() {
+        let rotated = Arc::new(AtomicUsize::new(0));
+        let fetches = rotated.clone();
+        let store = JwksStore::with_fetcher(
+            None,
+            Box::new(move |_| {
+                let kid = if fetches.fetch_add(1, Ordering::SeqCst) == 0 {
+                    "old"
+                } else {
+                    "new"
+                };
+                Ok(FetchedJwks {
+                    keys: vec![jwk(kid)],
+                    max_age: Some(600),
+                })
+            }),
+        );
+        let now = 1_746_000_000;
+        store.key_at(GOOGLE_ISSUER, "old", now, None).unwrap();
+        // The issuer rotated "old" out; proofs made with it still verify
+        store.key_at(GOOGLE_ISSUER, "new", now + 700, None).unwrap();
+        assert!(store
+            .keys(GOOGLE_ISSUER.to_string())
+            .iter()
+            .all(|key| key.kid == "new"));
+        assert!(store.key_at(GOOGLE_ISSUER, "old", now + 800, None).is_err());
+        let expiry = now + EPHEMERAL_KEY_LIFETIME;
+        assert_eq!(
+            store
+                .key_at(GOOGLE_ISSUER, "old", now + 800, Some(expiry))
+                .unwrap()
+                .kid,
+            "old"
+        );
+        // Ephemeral keys made after the key was rotated out can't use it
+        assert!(store
+            .key_at(GOOGLE_ISSUER, "old", now + 800, Some(expiry + 1))
+            .is_err());
+        let history = store.archive().history(GOOGLE_ISSUER.to_string());
+        assert_eq!(history.len(), 2);
+        assert_eq!((history[0].first_seen, history[0].last_seen), (now, now));
+
+        // An imported archive answers for issuers the store has no JWKS for
+        let archive = JwksArchive::new();
+        archive
+            .record("https://gitlab.com".to_string(), vec![jwk("gl")], now)
+            .unwrap();
+        store.set_archive(archive);
+        assert!(store
+            .key_at("https://gitlab.com", "gl", now, Some(now))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_store_fixtures_and_persistence() {
+        let store = JwksStore::with_fetcher(
+            None,
+            Box::new(|_| Err(StealthnoteError::Network("offline".to_string()))),
+        );
+        let issuers = store.load_fixtures("public/jwks".to_string()).unwrap();
+        assert!(issuers.contains(&GOOGLE_ISSUER.to_string()));
+        let kid = "07b80a365428525f8bf7cd0846d74a8ee4ef3625";
+        assert!(store.key_at(GOOGLE_ISSUER, kid, i64::MAX - 1, None).is_ok());
+        // Fixtures are never refetched
+        assert!(matches!(
+            store.key_at(GOOGLE_ISSUER, "rotated", 0, None),
+            Err(StealthnoteError::InvalidKey(_))
+        ));
+        assert!(matches!(
+            store.key_at(MICROSOFT_ISSUER, "any", 0, None),
+            Err(StealthnoteError::Network(_))
+        ));
+
+        let path = std::env::temp_dir().join("stealthnote_jwks_store_test.json");
+        store.save(path.display().to_string()).unwrap();
+        let reopened = JwksStore::open(path.display().to_string()).unwrap();
+        assert_eq!(reopened.keys(GOOGLE_ISSUER.to_string()).len(), 1);
+
+        // The archive is kept next to the cache and survives reopening
+        reopened
+            .archive()
+            .record(GOOGLE_ISSUER.to_string(), vec![jwk("archived")], 100)
+            .unwrap();
+        let archive_path = path.with_extension("archive.json");
+        assert!(archive_path.exists());
+        let reopened = JwksStore::open(path.display().to_string()).unwrap();
+        assert!(reopened
+            .archive()
+            .lookup(GOOGLE_ISSUER.to_string(), "archived".to_string())
+            .is_some());
+        let _ = fs::remove_file(path);
+        let _ = fs::remove_file(archive_path);
+    }
+}
@@ -0,0 +1,382 @@
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use super::jwt_proof::JsonWebKey;
+use crate::error::StealthnoteError;
+
+pub const JWKS_SNAPSHOT_VERSION: u32 = 1;
+
+/// A signing key as the archive has seen it served by its issuer.
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedJwk {
+    pub issuer: String,
+    pub key: JsonWebKey,
+    /// RFC 7638 thumbprint of the key material.
+    pub thumbprint: String,
+    /// Seconds since the Unix epoch.
+    pub first_seen: i64,
+    pub last_seen: i64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ArchiveFile {
+    keys: Vec<ArchivedJwk>,
+}
+
+/// The signed part of a snapshot.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SnapshotBody {
+    version: u32,
+    created_at: i64,
+    keys: Vec<ArchivedJwk>,
+}
+
+/// `{"version", "createdAt", "keys", "signer", "signature"}`: the ed25519
+/// signature (hex) by `signer` (hex public key) covers the compact JSON of
+/// the first three fields.
+#[derive(Debug, Serialize, Deserialize)]
+struct SignedSnapshot {
+    #[serde(flatten)]
+    body: SnapshotBody,
+    signer: String,
+    signature: String,
+}
+
+/// Every JWK ever seen per issuer, so memberships proven with a key the issuer
+/// has since rotated out stay verifiable. Entries are never removed; seeing a
+/// key again only widens its first/last seen window.
+#[derive(uniffi::Object, Default)]
+pub struct JwksArchive {
+    keys: RwLock<Vec<ArchivedJwk>>,
+    /// Rewritten after every change.
+    path: Option<PathBuf>,
+}
+
+#[uniffi::export]
+impl JwksArchive {
+    #[uniffi::constructor]
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Archive kept in `path`, loaded from it if it exists.
+    #[uniffi::constructor]
+    pub fn open(path: String) -> Result<Arc<Self>, StealthnoteError> {
+        let keys = if Path::new(&path).exists() {
+            serde_json::from_str::<ArchiveFile>(&fs::read_to_string(&path)?)
+                .map_err(|e| StealthnoteError::Storage(format!("JWKS archive {}: {}", path, e)))?
+                .keys
+        } else {
+            Vec::new()
+        };
+        Ok(Arc::new(JwksArchive {
+            keys: RwLock::new(keys),
+            path: Some(PathBuf::from(path)),
+        }))
+    }
+
+    /// Note that `issuer` served `keys` at `seen_at`.
+    pub fn record(
+        &self,
+        issuer: String,
+        keys: Vec<JsonWebKey>,
+        seen_at: i64,
+    ) -> Result<(), StealthnoteError> {
+        let entries = keys
+            .into_iter()
+            .map(|key| ArchivedJwk {
+                issuer: issuer.clone(),
+                thumbprint: jwk_thumbprint(&key),
+                key,
+                first_seen: seen_at,
+                last_seen: seen_at,
+            })
+            .collect();
+        self.merge(entries)
+    }
+
+    /// The key `kid` of `issuer`; the most recently seen one if the issuer
+    /// reused the kid.
+    pub fn lookup(&self, issuer: String, kid: String) -> Option<ArchivedJwk> {
+        self.read()
+            .iter()
+            .filter(|entry| entry.issuer == issuer && entry.key.kid == kid)
+            .max_by_key(|entry| entry.last_seen)
+            .cloned()
+    }
+
+    /// Every key of `issuer`, oldest first.
+    pub fn history(&self, issuer: String) -> Vec<ArchivedJwk> {
+        let mut history: Vec<ArchivedJwk> = self
+            .read()
+            .iter()
+            .filter(|entry| entry.issuer == issuer)
+            .cloned()
+            .collect();
+        history.sort_by_key(|entry| entry.first_seen);
+        history
+    }
+
+    /// The whole archive as a snapshot signed with the ed25519 key `signing_key`
+    /// (32-byte seed).
+    pub fn export_snapshot(&self, signing_key: Vec<u8>) -> Result<String, StealthnoteError> {
+        let seed: [u8; 32] = signing_key.as_slice().try_into().map_err(|_| {
+            StealthnoteError::InvalidKey(format!(
+                "snapshot signing key must be 32 bytes, got {}",
+                signing_key.len()
+            ))
+        })?;
+        let signing_key = SigningKey::from_bytes(&seed);
+        let body = SnapshotBody {
+            version: JWKS_SNAPSHOT_VERSION,
+            created_at: Utc::now().timestamp(),
+            keys: self.read().clone(),
+        };
+        let signature = signing_key.sign(&signed_bytes(&body)?);
+        serde_json::to_string_pretty(&SignedSnapshot {
+            body,
+            signer: hex::encode(signing_key.verifying_key().as_bytes()),
+            signature: hex::encode(signature.to_bytes()),
+        })
+        .map_err(|e| StealthnoteError::Storage(e.to_string()))
+    }
+
+    /// Merge a snapshot signed by one of `trusted_signers` (hex ed25519 public
+    /// keys). Returns how many keys it holds.
+    pub fn import_snapshot(
+        &self,
+        snapshot: String,
+        trusted_signers: Vec<String>,
+    ) -> Result<u32, StealthnoteError> {
+        let snapshot: SignedSnapshot = serde_json::from_str(&snapshot)
+            .map_err(|e| StealthnoteError::InvalidInput(format!("JWKS snapshot: {}", e)))?;
+        if snapshot.body.version != JWKS_SNAPSHOT_VERSION {
+            return Err(StealthnoteError::Unsupported(format!(
+                "JWKS snapshot version {}",
+                snapshot.body.version
+            )));
+        }
+        let signer = snapshot.signer.trim_start_matches("0x").to_lowercase();
+        if !trusted_signers.iter().any(|trusted| {
+            trusted
+                .trim_start_matches("0x")
+                .eq_ignore_ascii_case(&signer)
+        }) {
+            return Err(StealthnoteError::InvalidKey(format!(
+                "JWKS snapshot signer {} is not trusted",
+                signer
+            )));
+        }
+        let verifying_key = hex::decode(&signer)
+            .ok()
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+            .ok_or_else(|| {
+                StealthnoteError::InvalidKey(format!("JWKS snapshot signer {}", signer))
+            })?;
+        let signature = hex::decode(snapshot.signature.trim_start_matches("0x"))
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+            .ok_or_else(|| {
+                StealthnoteError::InvalidInput("JWKS snapshot signature is malformed".to_string())
+            })?;
+        verifying_key
+            .verify(&signed_bytes(&snapshot.body)?, &signature)
+            .map_err(|_| {
+                StealthnoteError::InvalidKey("JWKS snapshot signature does not verify".to_string())
+            })?;
+
+        let count = snapshot.body.keys.len() as u32;
+        for entry in &snapshot.body.keys {
+            if entry.thumbprint != jwk_thumbprint(&entry.key) || entry.first_seen > entry.last_seen
+            {
+                return Err(StealthnoteError::InvalidInput(format!(
+                    "JWKS snapshot entry {} of {} is inconsistent",
+                    entry.key.kid, entry.issuer
+                )));
+            }
+        }
+        self.merge(snapshot.body.keys)?;
+        Ok(count)
+    }
+}
+
+impl JwksArchive {
+    fn merge(&self, entries: Vec<ArchivedJwk>) -> Result<(), StealthnoteError> {
+        {
+            let mut keys = self.keys.write().unwrap_or_else(|e| e.into_inner());
+            for entry in entries {
+                let existing = keys.iter_mut().find(|known| {
+                    known.issuer == entry.issuer
+                        && known.key.kid == entry.key.kid
+                        && known.thumbprint == entry.thumbprint
+                });
+                match existing {
+                    Some(known) => {
+                        known.first_seen = known.first_seen.min(entry.first_seen);
+                        known.last_seen = known.last_seen.max(entry.last_seen);
+                    }
+                    None => keys.push(entry),
+                }
+            }
+        }
+        self.persist()
+    }
+
+    fn persist(&self) -> Result<(), StealthnoteError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_string_pretty(&ArchiveFile {
+            keys: self.read().clone(),
+        })
+        .map_err(|e| StealthnoteError::Storage(e.to_string()))?;
+        Ok(fs::write(path, json)?)
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Vec<ArchivedJwk>> {
+        self.keys.read().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// RFC 7638 JWK thumbprint: base64url SHA-256 of the required RSA members in
+/// lexicographic order.
+pub fn jwk_thumbprint(jwk: &JsonWebKey) -> String {
+    let canonical = format!(r#"{{"e":"{}","kty":"{}","n":"{}"}}"#, jwk.e, jwk.kty, jwk.n);
+    BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes()))
+}
+
+fn signed_bytes(body: &SnapshotBody) -> Result<Vec<u8>, StealthnoteError> {
+    serde_json::to_vec(body).map_err(|e| StealthnoteError::Storage(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGNING_SEED: [u8; 32] = [7u8; 32];
+
+    fn jwk(kid: &str, n: &str) -> JsonWebKey {
+        JsonWebKey {
+            kid: kid.to_string(),
+            n: n.to_string(),
+            use_: "sig".to_string(),
+            alg: "RS256".to_string(),
+            kty: "RSA".to_string(),
+            e: "AQAB".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_thumbprint() {
+        // RFC 7638, section 3.1
+        let key = JsonWebKey {
+            kid: "2011-04-29".to_string(),
+            n: "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw".to_string(),
+            use_: String::new(),
+            alg: String::new(),
+            kty: "RSA".to_string(),
+            e: "AQAB".to_string(),
+        };
+        assert_eq!(
+            jwk_thumbprint(&key),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+    }
+
+    #[test]
+    fn test_archive_is_append_only() {
+        let archive = JwksArchive::default();
+        let issuer = "https://accounts.google.com".to_string();
+        archive
+            .record(issuer.clone(), vec![jwk("a", "AQ"), jwk("b", "Ag")], 100)
+            .unwrap();
+        archive
+            .record(issuer.clone(), vec![jwk("b", "Ag")], 200)
+            .unwrap();
+        // `a` rotated out; `c` reuses nothing
+        archive
+            .record(issuer.clone(), vec![jwk("c", "Aw")], 300)
+            .unwrap();
+
+        let a = archive.lookup(issuer.clone(), "a".to_string()).unwrap();
+        assert_eq!((a.first_seen, a.last_seen), (100, 100));
+        let b = archive.lookup(issuer.clone(), "b".to_string()).unwrap();
+        assert_eq!((b.first_seen, b.last_seen), (100, 200));
+        assert_eq!(archive.history(issuer.clone()).len(), 3);
+
+        // A reused kid keeps both entries and resolves to the latest
+        archive
+            .record(issuer.clone(), vec![jwk("a", "BA")], 400)
+            .unwrap();
+        assert_eq!(archive.history(issuer.clone()).len(), 4);
+        assert_eq!(
+            archive
+                .lookup(issuer.clone(), "a".to_string())
+                .unwrap()
+                .key
+                .n,
+            "BA"
+        );
+        assert!(archive
+            .lookup("https://gitlab.com".to_string(), "a".to_string())
+            .is_none());
+    }
+
+    #[test]
+    fn test_signed_snapshot() {
+        let archive = JwksArchive::default();
+        let issuer = "https://accounts.google.com".to_string();
+        archive
+            .record(issuer.clone(), vec![jwk("a", "AQ")], 100)
+            .unwrap();
+        let snapshot = archive.export_snapshot(SIGNING_SEED.to_vec()).unwrap();
+        let signer = hex::encode(
+            SigningKey::from_bytes(&SIGNING_SEED)
+                .verifying_key()
+                .as_bytes(),
+        );
+
+        let imported = JwksArchive::default();
+        assert!(matches!(
+            imported.import_snapshot(snapshot.clone(), vec![hex::encode([1u8; 32])]),
+            Err(StealthnoteError::InvalidKey(_))
+        ));
+        assert_eq!(
+            imported
+                .import_snapshot(snapshot.clone(), vec![signer.clone()])
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            imported.lookup(issuer.clone(), "a".to_string()),
+            archive.lookup(issuer.clone(), "a".to_string())
+        );
+
+        let tampered = snapshot.replace("\"AQ\"", "\"BA\"");
+        assert!(matches!(
+            JwksArchive::default().import_snapshot(tampered, vec![signer]),
+            Err(StealthnoteError::InvalidKey(_))
+        ));
+
+        let path = std::env::temp_dir().join("stealthnote_jwks_archive_test.json");
+        let _ = fs::remove_file(&path);
+        let persisted = JwksArchive::open(path.display().to_string()).unwrap();
+        persisted
+            .record(issuer.clone(), vec![jwk("a", "AQ")], 100)
+            .unwrap();
+        let reopened = JwksArchive::open(path.display().to_string()).unwrap();
+        assert!(reopened.lookup(issuer, "a".to_string()).is_some());
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,319 @@
+use super::ephemeral_key::ephemeral_pubkey_hash;
+use super::jwt_proof::JsonWebKey;
+use super::rsa::RsaPublicKey;
+use crate::error::StealthnoteError;
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use num_bigint::BigUint;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Deserialize)]
+pub struct JwtHeader {
+    pub alg: String,
+    pub kid: Option<String>,
+    pub typ: Option<String>,
+}
+
+/// The ID token claims the app and the circuit care about.
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Deserialize)]
+pub struct JwtClaims {
+    pub iss: String,
+    #[serde(deserialize_with = "one_or_many")]
+    pub aud: Vec<String>,
+    pub email: Option<String>,
+    #[serde(default, deserialize_with = "bool_or_string")]
+    pub email_verified: Option<bool>,
+    pub hd: Option<String>,
+    pub nonce: Option<String>,
+    pub exp: u64,
+    pub iat: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct DecodedJwt {
+    pub header: JwtHeader,
+    pub claims: JwtClaims,
+    /// Decoded payload JSON, for issuer-specific claims.
+    pub payload: Vec<u8>,
+    /// `<header>.<payload>`, the bytes covered by the signature.
+    pub signed_data: String,
+    pub signature: Vec<u8>,
+}
+
+impl DecodedJwt {
+    pub fn decode(token: &str) -> Result<Self, StealthnoteError> {
+        let parts: Vec<&str> = token.split('.').collect();
+        let [header, payload, signature] = parts[..] else {
+            return Err(invalid_jwt("expected three dot-separated parts"));
+        };
+
+        let header_json = decode_part(header, "header")?;
+        let payload_json = decode_part(payload, "payload")?;
+        Ok(DecodedJwt {
+            header: serde_json::from_slice(&header_json)
+                .map_err(|e| invalid_jwt(&format!("header: {}", e)))?,
+            claims: serde_json::from_slice(&payload_json)
+                .map_err(|e| invalid_jwt(&format!("claims: {}", e)))?,
+            payload: payload_json,
+            signed_data: format!("{}.{}", header, payload),
+            signature: decode_part(signature, "signature")?,
+        })
+    }
+
+    /// The payload read as an issuer's own claim set.
+    pub fn claims_as<T: DeserializeOwned>(&self) -> Result<T, StealthnoteError> {
+        serde_json::from_slice(&self.payload).map_err(|e| invalid_jwt(&format!("claims: {}", e)))
+    }
+
+    /// Check the RS256 signature against `jwk`, which must be the key the header names.
+    pub fn verify_rs256(&self, jwk: &JsonWebKey) -> Result<(), StealthnoteError> {
+        if self.header.alg != "RS256" || jwk.kty != "RSA" {
+            return Err(StealthnoteError::InvalidKey(format!(
+                "unsupported algorithm {} with {} key, expected RS256/RSA",
+                self.header.alg, jwk.kty
+            )));
+        }
+        if let Some(kid) = &self.header.kid {
+            if *kid != jwk.kid {
+                return Err(StealthnoteError::InvalidKey(format!(
+                    "JWT was signed with key {}, got key {}",
+                    kid, jwk.kid
+                )));
+            }
+        }
+
+        let key = RsaPublicKey {
+            modulus: BigUint::from_bytes_be(&decode_key_part(&jwk.n, "modulus")?),
+            exponent: BigUint::from_bytes_be(&decode_key_part(&jwk.e, "exponent")?),
+        };
+        if !key.verify_pkcs1_sha256(self.signed_data.as_bytes(), &self.signature) {
+            return Err(invalid_jwt("RS256 signature does not verify"));
+        }
+        Ok(())
+    }
+
+    /// The checks the JWT circuit will make, so a bad token fails here instead of
+    /// after seconds of proving: signature, nonce binding, email domain and expiry.
+    pub fn check_consistency(
+        &self,
+        jwk: &JsonWebKey,
+        expected_nonce: &str,
+        domain: &str,
+    ) -> Result<(), StealthnoteError> {
+        let now = u64::try_from(Utc::now().timestamp()).unwrap_or_default();
+        self.check_consistency_at(jwk, expected_nonce, domain, now)
+    }
+
+    fn check_consistency_at(
+        &self,
+        jwk: &JsonWebKey,
+        expected_nonce: &str,
+        domain: &str,
+        now: u64,
+    ) -> Result<(), StealthnoteError> {
+        self.verify_rs256(jwk)?;
+
+        let claims = &self.claims;
+        if claims.nonce.as_deref() != Some(expected_nonce) {
+            return Err(invalid_jwt(&format!(
+                "nonce {:?} does not match the ephemeral pubkey hash {}",
+                claims.nonce, expected_nonce
+            )));
+        }
+        if claims.email_verified != Some(true) {
+            return Err(invalid_jwt("email is not verified"));
+        }
+        let email = claims
+            .email
+            .as_deref()
+            .ok_or_else(|| invalid_jwt("missing email claim"))?;
+        let email_domain = email.rsplit_once('@').map(|(_, d)| d).unwrap_or_default();
+        if !email_domain.eq_ignore_ascii_case(domain) {
+            return Err(invalid_jwt(&format!(
+                "email domain {} does not match domain {}",
+                email_domain, domain
+            )));
+        }
+        if claims.exp <= now {
+            return Err(invalid_jwt(&format!("token expired at {}", claims.exp)));
+        }
+        Ok(())
+    }
+}
+
+/// Pre-flight check of a sign-in before `prove_jwt`: decodes the token and
+/// runs [`DecodedJwt::check_consistency`] against the ephemeral key's hash.
+pub fn preflight_jwt(
+    token: &str,
+    jwk: &JsonWebKey,
+    ephemeral_pubkey: &BigUint,
+    ephemeral_salt: &BigUint,
+    ephemeral_expiry: u32,
+    domain: &str,
+) -> Result<JwtClaims, StealthnoteError> {
+    let now = u64::try_from(Utc::now().timestamp()).unwrap_or_default();
+    preflight_jwt_at(
+        token,
+        jwk,
+        ephemeral_pubkey,
+        ephemeral_salt,
+        ephemeral_expiry,
+        domain,
+        now,
+    )
+}
+
+/// [`preflight_jwt`] at `now` (seconds since the epoch).
+pub(crate) fn preflight_jwt_at(
+    token: &str,
+    jwk: &JsonWebKey,
+    ephemeral_pubkey: &BigUint,
+    ephemeral_salt: &BigUint,
+    ephemeral_expiry: u32,
+    domain: &str,
+    now: u64,
+) -> Result<JwtClaims, StealthnoteError> {
+    let jwt = DecodedJwt::decode(token)?;
+    let nonce = ephemeral_pubkey_hash(ephemeral_pubkey, ephemeral_salt, ephemeral_expiry)
+        .ok_or_else(|| StealthnoteError::InvalidKey("ephemeral key is not a field".to_string()))?;
+    jwt.check_consistency_at(jwk, &nonce.to_string(), domain, now)?;
+    Ok(jwt.claims)
+}
+
+fn decode_part(part: &str, name: &str) -> Result<Vec<u8>, StealthnoteError> {
+    BASE64_URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|e| invalid_jwt(&format!("{}: {}", name, e)))
+}
+
+fn decode_key_part(part: &str, name: &str) -> Result<Vec<u8>, StealthnoteError> {
+    BASE64_URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|e| StealthnoteError::InvalidKey(format!("JWK {}: {}", name, e)))
+}
+
+fn invalid_jwt(reason: &str) -> StealthnoteError {
+    StealthnoteError::InvalidJwt(reason.to_string())
+}
+
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(aud) => vec![aud],
+        OneOrMany::Many(aud) => aud,
+    })
+}
+
+// Some issuers send `"email_verified": "true"`
+fn bool_or_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<bool>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum BoolOrString {
+        Bool(bool),
+        String(String),
+    }
+    Ok(match Option::<BoolOrString>::deserialize(deserializer)? {
+        Some(BoolOrString::Bool(b)) => Some(b),
+        Some(BoolOrString::String(s)) => Some(s == "true"),
+        None => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID_TOKEN: &str = "eyJhbGciOiJSUzI1NiIsImtpZCI6IjA3YjgwYTM2NTQyODUyNWY4YmY3Y2QwODQ2ZDc0YThlZTRlZjM2MjUiLCJ0eXAiOiJKV1QifQ.eyJpc3MiOiJodHRwczovL2FjY291bnRzLmdvb2dsZS5jb20iLCJhenAiOiIxMDA2NzAxMjkzNzQ4LTFpcm1ndTkxMHAybjd2am1vYTQ0MXJhbW02ZGNydmViLmFwcHMuZ29vZ2xldXNlcmNvbnRlbnQuY29tIiwiYXVkIjoiMTAwNjcwMTI5Mzc0OC0xaXJtZ3U5MTBwMm43dmptb2E0NDFyYW1tNmRjcnZlYi5hcHBzLmdvb2dsZXVzZXJjb250ZW50LmNvbSIsInN1YiI6IjEwODUyMjA3NzcyMTgyNjQzOTM2NCIsImhkIjoicHNlLmRldiIsImVtYWlsIjoidml2aWFuamVuZ0Bwc2UuZGV2IiwiZW1haWxfdmVyaWZpZWQiOnRydWUsIm5vbmNlIjoiNjIyNjE4NzE4OTI2NDIwNDg2NDk4MTI3MDAxMDcxODU2NTA0MzIyNDkyNjUwNjU2MjgzOTM2NTk2NDc3ODY5OTY1NDU5ODg3NTQ2IiwibmJmIjoxNzQ2MDAzNzgwLCJpYXQiOjE3NDYwMDQwODAsImV4cCI6MTc0NjAwNzY4MCwianRpIjoiZmZhNGNhMWQ1NDZlZGZlOWI1Mjc0NDY3ZTE5ODJhOTgyMTU5MjRkOSJ9.naERF4rIB5L3a6I3FBC--_b25O2P6zbymSKkXHgOy44PvZU1LLSQ5ORzxHT93YIpbSzx5eF_FAMuXeN9uwLPrpFRw5Zlt9RlrbfQVNHZj1izHxj0IEYBudGESMRKjef7vfvtsYm_s_iHwE5M6H9UATi9xJw4U34iVn664xZFxhtdqbvCXW-YrjNliNK7dSEKAdHgi4MxiASlHXishGVwmFwe116c3HfEcyAJMxv9pGZEhmh4IZ7jVuwiUFEjroZ7svpGLiNx1grEnqGCJa8gcHEI4t1Lpip9d9CMuEctudLiH0Bk_bFofV-s-VvEOdFfEW8WYdE_YhKS0G9qYnevlQ";
+    /// `iat` of `ID_TOKEN`, while it was valid
+    const ISSUED_AT: u64 = 1746004080;
+    const NONCE: &str =
+        "622618718926420486498127001071856504322492650656283936596477869965459887546";
+
+    fn google_jwk() -> JsonWebKey {
+        JsonWebKey {
+            kid: "07b80a365428525f8bf7cd0846d74a8ee4ef3625".to_string(),
+            n: "03Cww27F2O7JxB5Ji9iT9szfKZ4MK-iPzVpQkdLjCuGKfpjaCVAz9zIQ0-7gbZ-8cJRaSLfByWTGMIHRYiX2efdjz1Z9jck0DK9W3mapFrBPvM7AlRni4lPlwUigDd8zxAMDCheqyK3vCOLFW-1xYHt_YGwv8b0dP7rjujarEYlWjeppO_QMNtXdKdT9eZtBEcj_9ms9W0aLdCFNR5AAR3y0kLkKR1H4DW7vncB46rqCJLenhlCbcW0MZ3asqcjqBQ2t9QMRnY83Zf_pNEsCcXlKp4uOQqEvzjAc9ZSr2sOmd_ESZ_3jMlNkCZ4J41TuG-My5illFcW5LajSKvxD3w".to_string(),
+            use_: "sig".to_string(),
+            alg: "RS256".to_string(),
+            kty: "RSA".to_string(),
+            e: "AQAB".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_decode_and_verify() {
+        let jwt = DecodedJwt::decode(ID_TOKEN).unwrap();
+        assert_eq!(
+            jwt.header.kid.as_deref(),
+            Some("07b80a365428525f8bf7cd0846d74a8ee4ef3625")
+        );
+        assert_eq!(jwt.claims.iss, "https://accounts.google.com");
+        assert_eq!(jwt.claims.email.as_deref(), Some("vivianjeng@pse.dev"));
+        assert_eq!(jwt.claims.email_verified, Some(true));
+        assert_eq!(jwt.claims.hd.as_deref(), Some("pse.dev"));
+        assert_eq!(jwt.claims.nonce.as_deref(), Some(NONCE));
+        assert_eq!((jwt.claims.iat, jwt.claims.exp), (1746004080, 1746007680));
+
+        jwt.verify_rs256(&google_jwk()).unwrap();
+        jwt.check_consistency_at(&google_jwk(), NONCE, "pse.dev", jwt.claims.iat)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_preflight_errors() {
+        let jwt = DecodedJwt::decode(ID_TOKEN).unwrap();
+        let jwk = google_jwk();
+
+        let mut tampered = jwt.clone();
+        tampered.signed_data.push('x');
+        assert!(matches!(
+            tampered.verify_rs256(&jwk),
+            Err(StealthnoteError::InvalidJwt(_))
+        ));
+
+        let mut other_kid = jwk.clone();
+        other_kid.kid = "another".to_string();
+        assert!(matches!(
+            jwt.verify_rs256(&other_kid),
+            Err(StealthnoteError::InvalidKey(_))
+        ));
+
+        let nonce = jwt.check_consistency(&jwk, "123", "pse.dev").unwrap_err();
+        assert!(nonce.to_string().contains("nonce"));
+        let domain = jwt.check_consistency(&jwk, NONCE, "gmail.com").unwrap_err();
+        assert!(domain.to_string().contains("email domain pse.dev"));
+        let expired = jwt
+            .check_consistency_at(&jwk, NONCE, "pse.dev", jwt.claims.exp)
+            .unwrap_err();
+        assert!(expired.to_string().contains("expired"));
+
+        assert!(matches!(
+            DecodedJwt::decode("a.b"),
+            Err(StealthnoteError::InvalidJwt(_))
+        ));
+    }
+
+    #[test]
+    fn test_preflight_with_ephemeral_key() {
+        let claims = preflight_jwt_at(
+            ID_TOKEN,
+            &google_jwk(),
+            &"17302102366996071265028731047581517700208166805377449770193522591062772282670"
+                .parse()
+                .unwrap(),
+            &"646645587996092179008704451306999156519169540151959619716525865713892520"
+                .parse()
+                .unwrap(),
+            1746608877,
+            "pse.dev",
+            ISSUED_AT,
+        )
+        .unwrap();
+        assert_eq!(claims.nonce.as_deref(), Some(NONCE));
+    }
+}
@@ -0,0 +1,526 @@
+use chrono::{DateTime, Utc};
+use num_bigint::BigUint;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{fs, path::Path, str::FromStr, sync::Arc};
+
+use super::cache::CircuitHandle;
+use super::circuit::CircuitArtifact;
+use super::ephemeral_key::EphemeralKey;
+use super::jwt::preflight_jwt_at;
+use super::jwt_proof::{
+    generate_inputs, pubkey_modulus_from_jwk, JWTCircuitInputs, JsonWebKey, StorageBlock,
+};
+use super::rsa::rsa_key_bits;
+use super::versions::JwtCircuitVersion;
+use crate::error::StealthnoteError;
+
+// Limits of the compiled `stealthnote_jwt` circuit
+pub const MAX_DOMAIN_LENGTH: usize = 64;
+const PRECOMPUTE_CLAIMS: [&str; 3] = ["email", "email_verified", "nonce"];
+
+/// Every parameter of the JWT circuit, laid out like `public/jwt_input.json`.
+///
+/// Circuits either take the full signed data (`data`) or a SHA-256 state
+/// precomputed up to the first disclosed claim (`partial_*`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JwtCircuitInput {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<StorageBlock>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial_data: Option<StorageBlock>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial_hash: Option<Vec<u32>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_data_length: Option<u32>,
+    pub base64_decode_offset: u32,
+    pub jwt_pubkey_modulus_limbs: Vec<String>,
+    pub jwt_pubkey_redc_params_limbs: Vec<String>,
+    pub jwt_signature_limbs: Vec<String>,
+    /// Ephemeral pubkey already shifted right by 3 bits to fit in a field
+    pub ephemeral_pubkey: String,
+    pub ephemeral_pubkey_salt: String,
+    /// Unix timestamp in seconds, stored as a string in the snapshot file
+    #[serde(with = "u32_as_string")]
+    pub ephemeral_pubkey_expiry: u32,
+    pub domain: StorageBlock,
+}
+
+/// Which claims stay in the signed data the circuit hashes, and how long it may be.
+#[derive(uniffi::Record, Debug, Clone, Default, PartialEq)]
+pub struct JwtInputOptions {
+    /// Everything before the first of these claims is hashed outside the
+    /// circuit. `None` keeps email, email_verified and nonce; an empty list
+    /// passes the full signed data, for circuits that take it.
+    pub precompute_claims: Option<Vec<String>>,
+    /// Defaults to, and may not exceed, the circuit's capacity.
+    pub max_signed_data_length: Option<u32>,
+}
+
+/// Input capacities of a JWT circuit, read from its ABI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JwtCircuitLimits {
+    pub max_partial_data_length: Option<usize>,
+    pub max_data_length: Option<usize>,
+    pub max_domain_length: usize,
+}
+
+impl JwtCircuitLimits {
+    pub fn from_artifact(artifact: &CircuitArtifact) -> Result<Self, StealthnoteError> {
+        let capacity = |name: &str| {
+            artifact
+                .parameter(name)
+                .and_then(|p| p.typ.bounded_vec_capacity())
+        };
+        let limits = JwtCircuitLimits {
+            max_partial_data_length: capacity("partial_data"),
+            max_data_length: capacity("data"),
+            max_domain_length: capacity("domain").ok_or_else(|| {
+                StealthnoteError::CircuitMismatch("JWT circuit has no domain input".to_string())
+            })?,
+        };
+        if limits.max_partial_data_length.is_none() && limits.max_data_length.is_none() {
+            return Err(StealthnoteError::CircuitMismatch(
+                "JWT circuit takes neither data nor partial_data".to_string(),
+            ));
+        }
+        Ok(limits)
+    }
+
+    /// Signed data length to pad to for `options`, and whether it is the full data.
+    fn signed_data_length(&self, options: &JwtInputOptions) -> Result<usize, StealthnoteError> {
+        let full_data = options
+            .precompute_claims
+            .as_ref()
+            .is_some_and(|claims| claims.is_empty());
+        let capacity = if full_data {
+            self.max_data_length
+        } else {
+            self.max_partial_data_length
+        }
+        .ok_or_else(|| {
+            StealthnoteError::Unsupported(format!(
+                "JWT circuit has no {} input",
+                if full_data {
+                    "full data"
+                } else {
+                    "partial data"
+                }
+            ))
+        })?;
+        match options.max_signed_data_length.map(|len| len as usize) {
+            Some(len) if len > capacity => Err(StealthnoteError::InvalidInput(format!(
+                "max signed data length {} exceeds the circuit's {}",
+                len, capacity
+            ))),
+            Some(len) => Ok(len),
+            None => Ok(capacity),
+        }
+    }
+}
+
+impl JwtCircuitInput {
+    pub fn new(
+        jwt: &str,
+        jwk: &JsonWebKey,
+        ephemeral_key: &EphemeralKey,
+        domain: &str,
+    ) -> Result<Self, StealthnoteError> {
+        Self::from_parts(
+            jwt,
+            jwk,
+            &ephemeral_key.get_ephemeral_public_key(),
+            &ephemeral_key.get_ephemeral_salt(),
+            &ephemeral_key.get_ephemeral_expiry(),
+            domain,
+        )
+    }
+
+    /// Same as [`JwtCircuitInput::new`], from the stringified ephemeral key
+    /// fields the app keeps in storage.
+    pub fn from_parts(
+        jwt: &str,
+        jwk: &JsonWebKey,
+        ephemeral_pubkey: &str,
+        ephemeral_salt: &str,
+        ephemeral_expiry: &str,
+        domain: &str,
+    ) -> Result<Self, StealthnoteError> {
+        Self::from_parts_with_options(
+            jwt,
+            jwk,
+            ephemeral_pubkey,
+            ephemeral_salt,
+            ephemeral_expiry,
+            domain,
+            &JwtInputOptions::default(),
+        )
+    }
+
+    pub fn from_parts_with_options(
+        jwt: &str,
+        jwk: &JsonWebKey,
+        ephemeral_pubkey: &str,
+        ephemeral_salt: &str,
+        ephemeral_expiry: &str,
+        domain: &str,
+        options: &JwtInputOptions,
+    ) -> Result<Self, StealthnoteError> {
+        Self::from_parts_at(
+            jwt,
+            jwk,
+            ephemeral_pubkey,
+            ephemeral_salt,
+            ephemeral_expiry,
+            domain,
+            options,
+            u64::try_from(Utc::now().timestamp()).unwrap_or_default(),
+        )
+    }
+
+    /// [`Self::from_parts_with_options`] with the token checked at `now`
+    /// (seconds since the epoch).
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn from_parts_at(
+        jwt: &str,
+        jwk: &JsonWebKey,
+        ephemeral_pubkey: &str,
+        ephemeral_salt: &str,
+        ephemeral_expiry: &str,
+        domain: &str,
+        options: &JwtInputOptions,
+        now: u64,
+    ) -> Result<Self, StealthnoteError> {
+        if jwk.kty != "RSA" || !matches!(jwk.alg.as_str(), "RS256" | "") {
+            return Err(StealthnoteError::InvalidKey(format!(
+                "unsupported JWK {}/{}, expected RSA/RS256",
+                jwk.kty, jwk.alg
+            )));
+        }
+        let modulus = pubkey_modulus_from_jwk(&jwk.n)
+            .map_err(|e| StealthnoteError::InvalidKey(format!("JWK modulus: {}", e)))?;
+        let circuit = JwtCircuitVersion::for_rsa_key(rsa_key_bits(&modulus))?.prover_circuit()?;
+        let limits = JwtCircuitLimits::from_artifact(&circuit.artifact)?;
+        if domain.is_empty() || domain.len() > limits.max_domain_length {
+            return Err(StealthnoteError::InvalidInput(format!(
+                "domain must be 1 to {} bytes, got {:?}",
+                limits.max_domain_length, domain
+            )));
+        }
+
+        let pubkey = BigUint::from_str(ephemeral_pubkey)
+            .map_err(|e| StealthnoteError::InvalidKey(format!("ephemeral pubkey: {}", e)))?;
+        let salt = BigUint::from_str(ephemeral_salt)
+            .map_err(|e| StealthnoteError::InvalidKey(format!("ephemeral salt: {}", e)))?;
+        let expiry: DateTime<Utc> = ephemeral_expiry.parse().map_err(|e| {
+            StealthnoteError::InvalidInput(format!("ephemeral expiry {}: {}", ephemeral_expiry, e))
+        })?;
+        let expiry = u32::try_from(expiry.timestamp()).map_err(|_| {
+            StealthnoteError::InvalidInput(format!(
+                "ephemeral expiry {} is out of range",
+                ephemeral_expiry
+            ))
+        })?;
+        // Fail on a stale nonce, wrong account or rotated key before proving
+        preflight_jwt_at(jwt, jwk, &pubkey, &salt, expiry, domain, now)?;
+
+        let inputs = signed_data_inputs(jwt, jwk, &limits, options)?;
+
+        Ok(JwtCircuitInput {
+            data: inputs.data,
+            partial_data: inputs.partial_data,
+            partial_hash: inputs.partial_hash,
+            full_data_length: inputs.full_data_length.map(|len| len as u32),
+            base64_decode_offset: inputs.base64_decode_offset as u32,
+            jwt_pubkey_modulus_limbs: inputs.pubkey_modulus_limbs,
+            jwt_pubkey_redc_params_limbs: inputs.redc_params_limbs,
+            jwt_signature_limbs: inputs.signature_limbs,
+            // Equivalent to JavaScript: publicKey >> 3n
+            ephemeral_pubkey: (pubkey >> 3u32).to_string(),
+            ephemeral_pubkey_salt: salt.to_string(),
+            ephemeral_pubkey_expiry: expiry,
+            domain: encode_domain_field(domain, limits.max_domain_length),
+        })
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, StealthnoteError> {
+        serde_json::from_str(json)
+            .map_err(|e| StealthnoteError::InvalidInput(format!("JWT circuit input: {}", e)))
+    }
+
+    pub fn to_json(&self) -> Result<String, StealthnoteError> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| StealthnoteError::InvalidInput(e.to_string()))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, StealthnoteError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), StealthnoteError> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+
+    /// The circuit for the RSA key size these inputs were generated for.
+    pub fn circuit(&self) -> Result<Arc<CircuitHandle>, StealthnoteError> {
+        JwtCircuitVersion::for_modulus_limbs(self.jwt_pubkey_modulus_limbs.len())?.prover_circuit()
+    }
+
+    /// Flatten into the JWT circuit witness, type-checked against its ABI.
+    pub fn to_witness(&self) -> Result<Vec<String>, StealthnoteError> {
+        let circuit = self.circuit()?;
+        let value = serde_json::to_value(self)
+            .map_err(|e| StealthnoteError::InvalidInput(e.to_string()))?;
+        circuit.artifact.witness_from_value(&value)
+    }
+}
+
+/// Run [`generate_inputs`] for `options`, padding the signed data to the
+/// circuit's capacity even when `options` asks for a lower limit.
+fn signed_data_inputs(
+    jwt: &str,
+    jwk: &JsonWebKey,
+    limits: &JwtCircuitLimits,
+    options: &JwtInputOptions,
+) -> Result<JWTCircuitInputs, StealthnoteError> {
+    let max_len = limits.signed_data_length(options)?;
+    let claims = match &options.precompute_claims {
+        Some(claims) => claims.iter().map(String::as_str).collect(),
+        None => PRECOMPUTE_CLAIMS.to_vec(),
+    };
+    let mut inputs = generate_inputs(jwt, jwk, Some(claims), max_len)
+        .map_err(|e| StealthnoteError::InvalidJwt(e.to_string()))?;
+
+    let (block, capacity) = match (&mut inputs.data, &mut inputs.partial_data) {
+        (Some(data), _) => (data, limits.max_data_length),
+        (None, Some(partial)) => (partial, limits.max_partial_data_length),
+        (None, None) => {
+            return Err(StealthnoteError::InvalidJwt(
+                "missing signed data".to_string(),
+            ))
+        }
+    };
+    block.storage.resize(capacity.unwrap_or(max_len), 0);
+    Ok(inputs)
+}
+
+pub fn encode_domain_field(domain: &str, fixed_len: usize) -> StorageBlock {
+    let mut bytes = domain.as_bytes().to_vec();
+    let original_len = bytes.len();
+
+    // padding 0 to fixed length
+    bytes.resize(fixed_len, 0);
+
+    StorageBlock {
+        storage: bytes,
+        len: original_len,
+    }
+}
+
+mod u32_as_string {
+    use super::*;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrNumber {
+        String(String),
+        Number(u32),
+    }
+
+    pub fn serialize<S: Serializer>(value: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+        match StringOrNumber::deserialize(deserializer)? {
+            StringOrNumber::String(s) => s.parse().map_err(serde::de::Error::custom),
+            StringOrNumber::Number(n) => Ok(n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+
+    const ID_TOKEN: &str = "eyJhbGciOiJSUzI1NiIsImtpZCI6IjA3YjgwYTM2NTQyODUyNWY4YmY3Y2QwODQ2ZDc0YThlZTRlZjM2MjUiLCJ0eXAiOiJKV1QifQ.eyJpc3MiOiJodHRwczovL2FjY291bnRzLmdvb2dsZS5jb20iLCJhenAiOiIxMDA2NzAxMjkzNzQ4LTFpcm1ndTkxMHAybjd2am1vYTQ0MXJhbW02ZGNydmViLmFwcHMuZ29vZ2xldXNlcmNvbnRlbnQuY29tIiwiYXVkIjoiMTAwNjcwMTI5Mzc0OC0xaXJtZ3U5MTBwMm43dmptb2E0NDFyYW1tNmRjcnZlYi5hcHBzLmdvb2dsZXVzZXJjb250ZW50LmNvbSIsInN1YiI6IjEwODUyMjA3NzcyMTgyNjQzOTM2NCIsImhkIjoicHNlLmRldiIsImVtYWlsIjoidml2aWFuamVuZ0Bwc2UuZGV2IiwiZW1haWxfdmVyaWZpZWQiOnRydWUsIm5vbmNlIjoiNjIyNjE4NzE4OTI2NDIwNDg2NDk4MTI3MDAxMDcxODU2NTA0MzIyNDkyNjUwNjU2MjgzOTM2NTk2NDc3ODY5OTY1NDU5ODg3NTQ2IiwibmJmIjoxNzQ2MDAzNzgwLCJpYXQiOjE3NDYwMDQwODAsImV4cCI6MTc0NjAwNzY4MCwianRpIjoiZmZhNGNhMWQ1NDZlZGZlOWI1Mjc0NDY3ZTE5ODJhOTgyMTU5MjRkOSJ9.naERF4rIB5L3a6I3FBC--_b25O2P6zbymSKkXHgOy44PvZU1LLSQ5ORzxHT93YIpbSzx5eF_FAMuXeN9uwLPrpFRw5Zlt9RlrbfQVNHZj1izHxj0IEYBudGESMRKjef7vfvtsYm_s_iHwE5M6H9UATi9xJw4U34iVn664xZFxhtdqbvCXW-YrjNliNK7dSEKAdHgi4MxiASlHXishGVwmFwe116c3HfEcyAJMxv9pGZEhmh4IZ7jVuwiUFEjroZ7svpGLiNx1grEnqGCJa8gcHEI4t1Lpip9d9CMuEctudLiH0Bk_bFofV-s-VvEOdFfEW8WYdE_YhKS0G9qYnevlQ";
+
+    fn google_jwk() -> JsonWebKey {
+        JsonWebKey {
+            kid: "07b80a365428525f8bf7cd0846d74a8ee4ef3625".to_string(),
+            n: "03Cww27F2O7JxB5Ji9iT9szfKZ4MK-iPzVpQkdLjCuGKfpjaCVAz9zIQ0-7gbZ-8cJRaSLfByWTGMIHRYiX2efdjz1Z9jck0DK9W3mapFrBPvM7AlRni4lPlwUigDd8zxAMDCheqyK3vCOLFW-1xYHt_YGwv8b0dP7rjujarEYlWjeppO_QMNtXdKdT9eZtBEcj_9ms9W0aLdCFNR5AAR3y0kLkKR1H4DW7vncB46rqCJLenhlCbcW0MZ3asqcjqBQ2t9QMRnY83Zf_pNEsCcXlKp4uOQqEvzjAc9ZSr2sOmd_ESZ_3jMlNkCZ4J41TuG-My5illFcW5LajSKvxD3w".to_string(),
+            use_: "sig".to_string(),
+            alg: "RS256".to_string(),
+            kty: "RSA".to_string(),
+            e: "AQAB".to_string(),
+        }
+    }
+
+    /// `ID_TOKEN` as proven while it was valid.
+    fn real_input(domain: &str) -> Result<JwtCircuitInput, StealthnoteError> {
+        JwtCircuitInput::from_parts_at(
+            ID_TOKEN,
+            &google_jwk(),
+            "17302102366996071265028731047581517700208166805377449770193522591062772282670",
+            "646645587996092179008704451306999156519169540151959619716525865713892520",
+            "2025-05-07T09:07:57.379Z",
+            domain,
+            &JwtInputOptions::default(),
+            1746004080,
+        )
+    }
+
+    #[test]
+    fn test_snapshot_roundtrip() {
+        let input = JwtCircuitInput::load("public/jwt_input.json").unwrap();
+        assert_eq!(input.ephemeral_pubkey_expiry, 1746510294);
+        assert_eq!(input.domain.len, 7);
+
+        let reloaded = JwtCircuitInput::from_json(&input.to_json().unwrap()).unwrap();
+        assert_eq!(reloaded, input);
+        assert_eq!(input.to_witness().unwrap().len(), 773);
+    }
+
+    #[test]
+    fn test_from_parts() {
+        let input = real_input("pse.dev").unwrap();
+        assert_eq!(input.partial_data.as_ref().unwrap().storage.len(), 640);
+        assert!(input.data.is_none());
+        assert_eq!(input.domain.storage[..7], *b"pse.dev");
+        assert_eq!(input.ephemeral_pubkey_expiry, 1746608877);
+        assert_eq!(input.jwt_pubkey_modulus_limbs.len(), 18);
+        assert!(input.to_witness().is_ok());
+    }
+
+    #[test]
+    fn test_from_parts_rejects_bad_input() {
+        assert!(matches!(
+            real_input(""),
+            Err(StealthnoteError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            real_input(&"a".repeat(65)),
+            Err(StealthnoteError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            JwtCircuitInput::from_parts(
+                "header.payload",
+                &google_jwk(),
+                "1",
+                "2",
+                "2025-05-07T09:07:57.379Z",
+                "pse.dev"
+            ),
+            Err(StealthnoteError::InvalidJwt(_))
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_key_size() {
+        let jwk = JsonWebKey {
+            n: BASE64_URL_SAFE_NO_PAD.encode([0xffu8; 384]),
+            ..google_jwk()
+        };
+        let err = JwtCircuitInput::from_parts(
+            ID_TOKEN,
+            &jwk,
+            "1",
+            "2",
+            "2025-05-07T09:07:57.379Z",
+            "pse.dev",
+        )
+        .unwrap_err();
+        assert!(matches!(err, StealthnoteError::Unsupported(_)));
+        assert!(err.to_string().contains("3072-bit"));
+    }
+
+    #[test]
+    fn test_circuit_limits() {
+        let limits =
+            JwtCircuitLimits::from_artifact(&CircuitHandle::jwt().unwrap().artifact).unwrap();
+        assert_eq!(
+            limits,
+            JwtCircuitLimits {
+                max_partial_data_length: Some(640),
+                max_data_length: None,
+                max_domain_length: MAX_DOMAIN_LENGTH,
+            }
+        );
+
+        let options = |claims: Option<Vec<&str>>, max: Option<u32>| JwtInputOptions {
+            precompute_claims: claims.map(|c| c.into_iter().map(str::to_string).collect()),
+            max_signed_data_length: max,
+        };
+        assert_eq!(
+            limits.signed_data_length(&options(None, None)).unwrap(),
+            640
+        );
+        assert_eq!(
+            limits
+                .signed_data_length(&options(None, Some(600)))
+                .unwrap(),
+            600
+        );
+        assert!(matches!(
+            limits.signed_data_length(&options(None, Some(641))),
+            Err(StealthnoteError::InvalidInput(_))
+        ));
+        assert!(matches!(
+            limits.signed_data_length(&options(Some(vec![]), None)),
+            Err(StealthnoteError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_signed_data_paths() {
+        let limits = JwtCircuitLimits {
+            max_partial_data_length: Some(640),
+            max_data_length: Some(1024),
+            max_domain_length: MAX_DOMAIN_LENGTH,
+        };
+        let signed_len = ID_TOKEN.rsplit_once('.').unwrap().0.len();
+
+        let full = signed_data_inputs(
+            ID_TOKEN,
+            &google_jwk(),
+            &limits,
+            &JwtInputOptions {
+                precompute_claims: Some(vec![]),
+                max_signed_data_length: None,
+            },
+        )
+        .unwrap();
+        let data = full.data.unwrap();
+        assert_eq!((data.storage.len(), data.len), (1024, signed_len));
+        assert!(full.partial_data.is_none());
+
+        // Fewer disclosed claims leave less data for the circuit to hash
+        let nonce_only = signed_data_inputs(
+            ID_TOKEN,
+            &google_jwk(),
+            &limits,
+            &JwtInputOptions {
+                precompute_claims: Some(vec!["nonce".to_string()]),
+                max_signed_data_length: None,
+            },
+        )
+        .unwrap();
+        let default =
+            signed_data_inputs(ID_TOKEN, &google_jwk(), &limits, &Default::default()).unwrap();
+        let partial = nonce_only.partial_data.unwrap();
+        assert_eq!(partial.storage.len(), 640);
+        assert!(partial.len < default.partial_data.unwrap().len);
+
+        let too_long = signed_data_inputs(
+            ID_TOKEN,
+            &google_jwk(),
+            &limits,
+            &JwtInputOptions {
+                precompute_claims: None,
+                max_signed_data_length: Some(64),
+            },
+        );
+        assert!(matches!(too_long, Err(StealthnoteError::InvalidJwt(_))));
+    }
+}
@@ -3,24 +3,27 @@ use base64::engine::general_purpose::URL_SAFE_NO_PAD;
 use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
 use byteorder::{BigEndian, ByteOrder};
 use chrono::{DateTime, Utc};
-use noir::{
-    barretenberg::{
-        prove::prove_ultra_honk, srs::setup_srs_from_bytecode, utils::get_honk_verification_key,
-        verify::verify_ultra_honk,
-    },
-    witness::from_vec_str_to_witness_map,
-};
 use num_bigint::BigUint;
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, str::FromStr};
-
-#[derive(uniffi::Record, Debug, Deserialize, Serialize, Clone)]
+use std::str::FromStr;
+
+use super::cache::CircuitHandle;
+use super::jwt_input::JwtCircuitInput;
+use super::progress::ProofProgress;
+use super::public_inputs::JwtPublicInputLayout;
+use super::rsa::{redc_param, rsa_key_bits, rsa_limb_count, RSA_LIMB_BITS};
+use super::vk::verify_with_vk;
+use crate::error::StealthnoteError;
+
+/// An RSA JWK. `use` and `alg` are optional in JWKS documents (Microsoft
+/// omits `alg`) and read as empty when absent.
+#[derive(uniffi::Record, Debug, Deserialize, Serialize, Clone, PartialEq)]
 pub struct JsonWebKey {
     pub kid: String,
     pub n: String,
-    #[serde(rename = "use")]
+    #[serde(rename = "use", default)]
     pub use_: String,
+    #[serde(default)]
     pub alg: String,
     pub kty: String,
     pub e: String,
@@ -38,7 +41,7 @@ pub struct JWTCircuitInputs {
     pub full_data_length: Option<usize>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct StorageBlock {
     pub storage: Vec<u8>,
     pub len: usize,
@@ -66,12 +69,13 @@ pub fn generate_inputs(
 
     let n_bytes = base64_url_to_bytes(&pubkey.n)?;
     let n_big = biguint_from_bytes(&n_bytes);
-    let redc = ((BigUint::from(1u64)) << (2 * 2048 + 4)) / &n_big;
+    let limbs = rsa_limb_count(rsa_key_bits(&n_big));
+    let redc = redc_param(&n_big);
 
     let mut inputs = JWTCircuitInputs {
-        pubkey_modulus_limbs: split_biguint(&n_big, 120, 18),
-        redc_params_limbs: split_biguint(&redc, 120, 18),
-        signature_limbs: split_biguint(&signature, 120, 18),
+        pubkey_modulus_limbs: split_biguint(&n_big, RSA_LIMB_BITS, limbs),
+        redc_params_limbs: split_biguint(&redc, RSA_LIMB_BITS, limbs),
+        signature_limbs: split_biguint(&signature, RSA_LIMB_BITS, limbs),
         data: None,
         base64_decode_offset: 0,
         partial_data: None,
@@ -81,7 +85,11 @@ pub fn generate_inputs(
 
     if sha_precompute_keys.is_none() || sha_precompute_keys.as_ref().unwrap().is_empty() {
         if signed_data.len() > max_signed_data_len {
-            return Err(anyhow!("signed data too long"));
+            return Err(anyhow!(
+                "signed data too long: {} bytes, max {}",
+                signed_data.len(),
+                max_signed_data_len
+            ));
         }
 
         let mut padded = vec![0u8; max_signed_data_len];
@@ -105,7 +113,11 @@ pub fn generate_inputs(
         let (partial_hash, remaining) = generate_partial_sha256(&signed_data, slice_start);
 
         if remaining.len() > max_signed_data_len {
-            return Err(anyhow!("remaining data too long"));
+            return Err(anyhow!(
+                "remaining data too long: {} bytes after the precomputed hash, max {}",
+                remaining.len(),
+                max_signed_data_len
+            ));
         }
 
         let mut padded = vec![0u8; max_signed_data_len];
@@ -127,72 +139,29 @@ pub fn generate_inputs(
     Ok(inputs)
 }
 
-pub fn generate_jwt_proof(srs_path: String, inputs: HashMap<String, Vec<String>>) -> Vec<u8> {
-    const JWT_JSON: &str = include_str!("../../circuit/stealthnote_jwt.json");
-    let bytecode_json: serde_json::Value = serde_json::from_str(&JWT_JSON).unwrap();
-    let bytecode = bytecode_json["bytecode"].as_str().unwrap();
-
-    // Setup SRS
-    setup_srs_from_bytecode(bytecode, Some(&srs_path), false).unwrap();
-
-    // Define the expected order of witness values based on the JwtInput struct
-    let witness_key_order = [
-        "partial_data_storage",
-        "partial_data_len",
-        "partial_hash",
-        "full_data_length",
-        "base64_decode_offset",
-        "jwt_pubkey_modulus_limbs",
-        "jwt_pubkey_redc_params_limbs",
-        "jwt_signature_limbs",
-        "domain_storage",
-        "domain_len",
-        "ephemeral_pubkey",
-        "ephemeral_pubkey_salt",
-        "ephemeral_pubkey_expiry",
-    ];
-
-    let mut witness_vec_string: Vec<String> = Vec::new();
-    for key in witness_key_order {
-        match inputs.get(key) {
-            Some(values) => witness_vec_string.extend(values.iter().cloned()),
-            None => panic!("Missing required input key in HashMap: {}", key),
-        }
-    }
-
-    // Convert Vec<String> to Vec<&str> for the function call
-    let witness_vec_str: Vec<&str> = witness_vec_string.iter().map(AsRef::as_ref).collect();
-
-    let initial_witness = from_vec_str_to_witness_map(witness_vec_str).unwrap();
-
-    // Start timing the proof generation
-    let start = std::time::Instant::now();
-    let proof = prove_ultra_honk(bytecode, initial_witness, false).unwrap();
-
-    println!("Proof generation time: {:?}", start.elapsed());
+pub const JWT_CIRCUIT_JSON: &str = include_str!("../../circuit/stealthnote_jwt.json");
+/// `jwtCircuitVersion` the app sends in `proofArgs` for the compiled-in circuit.
+pub const JWT_CIRCUIT_VERSION: &str = "0.3.1";
 
-    proof
+pub fn generate_jwt_proof(
+    srs_path: String,
+    input: &JwtCircuitInput,
+) -> Result<Vec<u8>, StealthnoteError> {
+    generate_jwt_proof_with_progress(srs_path, input, &ProofProgress::default())
 }
 
-pub fn verify_jwt(srs_path: String, proof: Vec<u8>) -> bool {
-    const JWT_JSON: &str = include_str!("../../circuit/stealthnote_jwt.json");
-    let bytecode_json: serde_json::Value = serde_json::from_str(&JWT_JSON).unwrap();
-    let bytecode = bytecode_json["bytecode"].as_str().unwrap();
-
-    // Setup SRS
-    setup_srs_from_bytecode(bytecode, Some(&srs_path), false).unwrap();
-
-    // Get the verification key
-    let vk = get_honk_verification_key(bytecode, false).unwrap();
-
-    // Start timing the proof verification
-    let start = std::time::Instant::now();
-    let verdict = verify_ultra_honk(proof, vk).unwrap();
-
-    println!("Proof verification time: {:?}", start.elapsed());
-    println!("Proof verification verdict: {}", verdict);
+pub fn generate_jwt_proof_with_progress(
+    srs_path: String,
+    input: &JwtCircuitInput,
+    progress: &ProofProgress,
+) -> Result<Vec<u8>, StealthnoteError> {
+    let circuit = input.circuit()?;
+    circuit.prove_witness_with_progress(Some(&srs_path), input.to_witness()?, progress)
+}
 
-    verdict
+pub fn verify_jwt(srs_path: String, proof: Vec<u8>) -> Result<bool, StealthnoteError> {
+    let circuit = CircuitHandle::jwt()?;
+    circuit.verify(Some(&srs_path), proof)
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -237,47 +206,9 @@ fn get_ephemeral_pubkey() -> Option<String> {
     Some("dummy_pubkey_value".to_string())
 }
 
-#[derive(Debug, Deserialize)]
-struct GoogleCertsResponse {
-    keys: Vec<GooglePublicKey>,
-}
-
-#[derive(Debug, Deserialize, Serialize, Clone)]
-struct GooglePublicKey {
-    kid: String,
-    kty: String,
-    alg: String,
-    // use_: String,
-    n: String,
-    e: String,
-    // x5c: Option<Vec<String>>,
-    // add other fields if needed
-}
-
-async fn fetch_google_public_key(key_id: &str) -> Result<Option<GooglePublicKey>, reqwest::Error> {
-    if key_id.is_empty() {
-        return Ok(None);
-    }
-
-    let client = Client::new();
-    let res = client
-        .get("https://www.googleapis.com/oauth2/v3/certs")
-        .send()
-        .await?
-        .error_for_status()?; // returns error if not 2xx
-
-    let certs: GoogleCertsResponse = res.json().await?;
-
-    let key = certs.keys.into_iter().find(|k| k.kid == key_id);
-
-    if key.is_none() {
-        eprintln!("Google public key with id {} not found", key_id);
-    }
-
-    Ok(key)
-}
-
-fn pubkey_modulus_from_jwk(jwk_n: &String) -> Result<BigUint, Box<dyn std::error::Error>> {
+pub(crate) fn pubkey_modulus_from_jwk(
+    jwk_n: &String,
+) -> Result<BigUint, Box<dyn std::error::Error>> {
     // Decode base64url `n` (modulus)
     let modulus_bytes = BASE64_URL_SAFE_NO_PAD.decode(&jwk_n)?;
     let modulus = BigUint::from_bytes_be(&modulus_bytes);
@@ -328,7 +259,7 @@ fn flatten_u8_arrays(arrays: Vec<Vec<u8>>) -> Vec<u8> {
     result
 }
 
-fn flatten_fields_as_array(fields: &[String]) -> Vec<u8> {
+pub(crate) fn flatten_fields_as_array(fields: &[String]) -> Vec<u8> {
     let parsed_fields: Vec<Vec<u8>> = fields.iter().map(|s| hex_to_u8_array(s)).collect();
     flatten_u8_arrays(parsed_fields)
 }
@@ -340,7 +271,11 @@ fn num_to_uint32_be(n: u32, buffer_size: usize) -> Vec<u8> {
     buf
 }
 
-fn reconstruct_honk_proof(public_inputs: &[u8], proof: &[u8], field_byte_size: usize) -> Vec<u8> {
+pub(crate) fn reconstruct_honk_proof(
+    public_inputs: &[u8],
+    proof: &[u8],
+    field_byte_size: usize,
+) -> Vec<u8> {
     let total_size = (public_inputs.len() + proof.len()) / field_byte_size;
     let proof_size = num_to_uint32_be(total_size as u32, 4);
 
@@ -353,6 +288,7 @@ fn reconstruct_honk_proof(public_inputs: &[u8], proof: &[u8], field_byte_size: u
 }
 
 fn prepare_public_inputs(
+    layout: &JwtPublicInputLayout,
     jwt_pubkey: BigUint,
     domain: String,
     ephemeral_pubkey: BigUint,
@@ -360,14 +296,15 @@ fn prepare_public_inputs(
 ) -> Vec<String> {
     let mut public_inputs = Vec::new();
 
-    // === 1. Modulus limbs (18 limbs of 120 bits each) ===
-    let modulus_limbs = split_bigint_to_limbs(&jwt_pubkey, 120, 18);
+    // === 1. Modulus limbs (18 limbs of 120 bits each for 0.3.x) ===
+    let modulus_limbs =
+        split_bigint_to_limbs(&jwt_pubkey, layout.modulus_limb_bits, layout.modulus_limbs);
     for limb in modulus_limbs.clone() {
         public_inputs.push(format!("0x{:0>64x}", limb));
     }
 
     // === 2. Domain as 64-byte padded array ===
-    let mut domain_bytes = [0u8; 64];
+    let mut domain_bytes = vec![0u8; layout.max_domain_length];
     let domain_encoded = domain.as_bytes();
     domain_bytes[..domain_encoded.len()].copy_from_slice(domain_encoded);
 
@@ -396,36 +333,113 @@ fn prepare_public_inputs(
     public_inputs
 }
 
-fn extract_proof(result: &[u8], public_inputs_len: usize) -> &[u8] {
-    let offset = 4 + public_inputs_len;
-    &result[offset..]
+pub fn verify_jwt_proof(
+    srs_path: String,
+    proof: Vec<u8>,
+    domain: String,
+    google_jwt_pubkey_modulus: String,
+    ephemeral_pubkey: String,
+    ephemeral_pubkey_expiry: String,
+) -> Result<bool, StealthnoteError> {
+    let proof = with_jwt_public_inputs(
+        &JwtPublicInputLayout::V0_3,
+        proof,
+        domain,
+        google_jwt_pubkey_modulus,
+        ephemeral_pubkey,
+        ephemeral_pubkey_expiry,
+    )?;
+    verify_jwt(srs_path, proof)
 }
 
-pub fn verify_jwt_proof(
+/// Same as [`verify_jwt_proof`], against a precomputed JWT verification key
+/// instead of one derived from the circuit bytecode.
+#[allow(clippy::too_many_arguments)]
+pub fn verify_jwt_proof_with_vk(
     srs_path: String,
+    vk: Vec<u8>,
+    expected_vk_hash: Option<String>,
+    proof: Vec<u8>,
+    domain: String,
+    google_jwt_pubkey_modulus: String,
+    ephemeral_pubkey: String,
+    ephemeral_pubkey_expiry: String,
+) -> Result<bool, StealthnoteError> {
+    let proof = with_jwt_public_inputs(
+        &JwtPublicInputLayout::V0_3,
+        proof,
+        domain,
+        google_jwt_pubkey_modulus,
+        ephemeral_pubkey,
+        ephemeral_pubkey_expiry,
+    )?;
+    verify_with_vk(Some(&srs_path), vk, expected_vk_hash.as_deref(), proof)
+}
+
+/// Rebuild the full bb proof by prepending the public inputs the verifier expects.
+pub(crate) fn with_jwt_public_inputs(
+    layout: &JwtPublicInputLayout,
     proof: Vec<u8>,
     domain: String,
     google_jwt_pubkey_modulus: String,
     ephemeral_pubkey: String,
     ephemeral_pubkey_expiry: String,
-) -> bool {
-    let jwt_pubkey = pubkey_modulus_from_jwk(&google_jwt_pubkey_modulus).unwrap();
-    let ephemeral_pubkey_biguint = BigUint::from_str(&ephemeral_pubkey).unwrap();
+) -> Result<Vec<u8>, StealthnoteError> {
+    let public_inputs = jwt_public_inputs(
+        layout,
+        domain,
+        google_jwt_pubkey_modulus,
+        ephemeral_pubkey,
+        ephemeral_pubkey_expiry,
+    )?;
+    Ok(reconstruct_honk_proof(
+        &flatten_fields_as_array(&public_inputs),
+        &proof,
+        32,
+    ))
+}
+
+/// Public inputs of the JWT circuit for the claimed membership metadata, as 0x-prefixed fields.
+pub(crate) fn jwt_public_inputs(
+    layout: &JwtPublicInputLayout,
+    domain: String,
+    google_jwt_pubkey_modulus: String,
+    ephemeral_pubkey: String,
+    ephemeral_pubkey_expiry: String,
+) -> Result<Vec<String>, StealthnoteError> {
+    let jwt_pubkey = pubkey_modulus_from_jwk(&google_jwt_pubkey_modulus)
+        .map_err(|e| StealthnoteError::InvalidKey(format!("JWT pubkey modulus: {}", e)))?;
+    let ephemeral_pubkey_biguint = BigUint::from_str(&ephemeral_pubkey)
+        .map_err(|e| StealthnoteError::InvalidKey(format!("ephemeral pubkey: {}", e)))?;
     let parsed_ephemeral_pubkey_expiry: DateTime<Utc> = ephemeral_pubkey_expiry
         .parse::<DateTime<Utc>>()
-        .expect("Invalid datetime format");
+        .map_err(|e| {
+            StealthnoteError::InvalidInput(format!(
+                "ephemeral pubkey expiry {}: {}",
+                ephemeral_pubkey_expiry, e
+            ))
+        })?;
+    if domain.len() > layout.max_domain_length {
+        return Err(StealthnoteError::InvalidInput(format!(
+            "domain {} is longer than {} bytes",
+            domain, layout.max_domain_length
+        )));
+    }
+
+    if jwt_pubkey.bits() > (layout.modulus_limbs * layout.modulus_limb_bits) as u64 {
+        return Err(StealthnoteError::InvalidKey(format!(
+            "JWT pubkey modulus of {} bits does not fit the circuit",
+            jwt_pubkey.bits()
+        )));
+    }
 
-    let public_inputs = prepare_public_inputs(
+    Ok(prepare_public_inputs(
+        layout,
         jwt_pubkey,
         domain,
         ephemeral_pubkey_biguint,
         parsed_ephemeral_pubkey_expiry,
-    );
-
-    let proof = reconstruct_honk_proof(&flatten_fields_as_array(&public_inputs), &proof, 32);
-
-    let verified = verify_jwt(srs_path, proof);
-    verified
+    ))
 }
 
 //
@@ -564,6 +578,29 @@ fn sha256_block(h: &mut [u32; 8], block: &[u8; 64]) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::proof::jwks::{JwksStore, GOOGLE_ISSUER};
+    use crate::proof::versions::JwtCircuitVersion;
+    use reqwest::Client;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_rsa_limbs_follow_key_size() {
+        for (key_bits, limbs) in [(2048usize, 18), (3072, 26), (4096, 35)] {
+            let n: BigUint = (BigUint::from(1u64) << (key_bits - 1)) + 12345u64;
+            let jwk = JsonWebKey {
+                kid: "test".to_string(),
+                n: URL_SAFE_NO_PAD.encode(n.to_bytes_be()),
+                use_: "sig".to_string(),
+                alg: "RS256".to_string(),
+                kty: "RSA".to_string(),
+                e: "AQAB".to_string(),
+            };
+            let inputs = generate_inputs("e30.e30.AQAB", &jwk, None, 64).unwrap();
+            assert_eq!(inputs.pubkey_modulus_limbs.len(), limbs);
+            assert_eq!(inputs.redc_params_limbs.len(), limbs);
+            assert_eq!(inputs.signature_limbs.len(), limbs);
+        }
+    }
 
     #[tokio::test]
     async fn test_verify_jwt_from_database() -> Result<(), anyhow::Error> {
@@ -602,10 +639,13 @@ mod tests {
 
         let message = response.json::<MessageResponse>().await?;
 
-        let google_public_key = fetch_google_public_key(&message.proofArgs.keyId)
-            .await
-            .unwrap()
-            .unwrap();
+        let google_public_key = JwksStore::global().key_for_proof(
+            GOOGLE_ISSUER.to_string(),
+            message.proofArgs.keyId.clone(),
+            i64::from(crate::api_server::parse_expiry(
+                &message.ephemeralPubkeyExpiry,
+            )?),
+        )?;
 
         let domain = message.anonGroupId.clone();
 
@@ -622,15 +662,16 @@ mod tests {
         //     ephemeralPubkeyExpiry: ephemeralPubkeyExpiry,
         //   });
 
-        let srs_path = "public/jwt-srs.local".to_string();
-        let verified = verify_jwt_proof(
-            srs_path,
+        let srs_path = "public/jwt-srs.local";
+        let circuit = JwtCircuitVersion::get(&message.proofArgs.jwtCircuitVersion)?;
+        let verified = circuit.verify(
+            Some(srs_path),
             proof,
             domain,
             google_jwt_pubkey_modulus,
             ephemeral_pubkey,
             ephemeral_pubkey_expiry,
-        );
+        )?;
         println!("verified: {}", verified);
         Ok(())
         // assert!(result);
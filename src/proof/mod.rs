@@ -1,3 +1,21 @@
+pub mod batch;
+pub mod cache;
+pub mod circuit;
+pub mod dkim;
+pub mod dkim_registry;
+pub mod envelope;
 pub mod ephemeral_key;
+pub mod jwks;
+pub mod jwks_archive;
+pub mod jwt;
+pub mod jwt_input;
 pub mod jwt_proof;
-pub mod poseidon2;
\ No newline at end of file
+pub mod pedersen;
+pub mod poseidon2;
+pub mod progress;
+pub mod public_inputs;
+pub mod rsa;
+pub mod versions;
+pub mod vk;
+pub mod zkemail_input;
+pub mod zkemail_proof;
@@ -0,0 +1,102 @@
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, Field, PrimeField, Zero};
+use bn254_blackbox_solver::derive_generators;
+use num_bigint::BigUint;
+
+use super::rsa::{redc_param, rsa_key_bits, rsa_limb_count, to_limbs, RsaPublicKey};
+
+/// A Grumpkin point (`y^2 = x^3 - 17` over the BN254 scalar field), `None`
+/// being the point at infinity.
+type Point = Option<(Fr, Fr)>;
+
+/// Noir's `std::hash::pedersen_hash`: the x coordinate of the inputs times the
+/// default-domain generators, plus the input count times the length generator.
+pub fn pedersen_hash(inputs: &[BigUint]) -> BigUint {
+    let input_generators = generators(b"DEFAULT_DOMAIN_SEPARATOR", inputs.len() as u32);
+    let length_generator = generators(b"pedersen_hash_length", 1)[0];
+
+    let mut sum = mul(length_generator, &Fr::from(inputs.len() as u64));
+    for (input, generator) in inputs.iter().zip(input_generators) {
+        sum = add(sum, mul(generator, &Fr::from(input.clone())));
+    }
+    sum.map(|(x, _)| x).unwrap_or_default().into_bigint().into()
+}
+
+/// The DKIM key hash the zkEmail circuit outputs: [`pedersen_hash`] of the
+/// modulus limbs followed by the Barrett reduction parameter limbs.
+pub fn dkim_pubkey_hash(key: &RsaPublicKey) -> BigUint {
+    let limbs = rsa_limb_count(rsa_key_bits(&key.modulus));
+    let mut inputs = to_limbs(&key.modulus, limbs);
+    inputs.extend(to_limbs(&redc_param(&key.modulus), limbs));
+    pedersen_hash(&inputs)
+}
+
+/// The first `count` generators of a domain, as Noir's `derive_generators`.
+fn generators(domain_separator: &[u8], count: u32) -> Vec<Point> {
+    derive_generators(domain_separator, count, 0)
+        .iter()
+        .map(|g| (!g.infinity).then_some((g.x, g.y)))
+        .collect()
+}
+
+fn add(p: Point, q: Point) -> Point {
+    let ((x1, y1), (x2, y2)) = match (p, q) {
+        (None, q) => return q,
+        (p, None) => return p,
+        (Some(p), Some(q)) => (p, q),
+    };
+    let lambda = if x1 == x2 {
+        if y1 != y2 || y1.is_zero() {
+            return None;
+        }
+        (x1.square() * Fr::from(3u64)) * (y1 + y1).inverse()?
+    } else {
+        (y2 - y1) * (x2 - x1).inverse()?
+    };
+    let x3 = lambda.square() - x1 - x2;
+    Some((x3, lambda * (x1 - x3) - y1))
+}
+
+fn mul(p: Point, scalar: &Fr) -> Point {
+    scalar
+        .into_bigint()
+        .to_bits_be()
+        .into_iter()
+        .fold(None, |acc, bit| {
+            let doubled = add(acc, acc);
+            if bit {
+                add(doubled, p)
+            } else {
+                doubled
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grumpkin_arithmetic() {
+        let g = generators(b"DEFAULT_DOMAIN_SEPARATOR", 1)[0];
+        let (x, y) = g.unwrap();
+        assert_eq!(y.square(), x.square() * x - Fr::from(17u64));
+
+        let three = add(add(g, g), g);
+        assert_eq!(mul(g, &Fr::from(3u64)), three);
+        let (x3, y3) = three.unwrap();
+        assert_eq!(add(three, Some((x3, -y3))), None);
+        assert_eq!(mul(g, &Fr::zero()), None);
+    }
+
+    /// barretenberg's `pedersen_hash::hash({1, 1})`
+    #[test]
+    fn test_pedersen_hash() {
+        let one = BigUint::from(1u8);
+        let expected = BigUint::parse_bytes(
+            b"07ebfbf4df29888c6cd6dca13d4bb9d1a923013ddbbcbdc3378ab8845463297b",
+            16,
+        );
+        assert_eq!(Some(pedersen_hash(&[one.clone(), one])), expected);
+    }
+}
@@ -0,0 +1,135 @@
+use crate::error::StealthnoteError;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Steps of a proof run, reported in this order.
+#[derive(uniffi::Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofStage {
+    InputGeneration,
+    SrsSetup,
+    WitnessSolving,
+    Proving,
+    Done,
+}
+
+/// Implemented by the app to follow a proof run, e.g. to drive a progress bar.
+#[uniffi::export(with_foreign)]
+pub trait ProofProgressListener: Send + Sync {
+    fn on_stage(&self, stage: ProofStage);
+}
+
+/// Lets the app abandon a proof run, e.g. when the user backs out of sign-in.
+/// bb can't be interrupted mid-proof, so the run stops at the next stage
+/// boundary and a proof finished after cancellation is discarded.
+#[derive(uniffi::Object, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: AtomicBool,
+}
+
+#[uniffi::export]
+impl CancellationToken {
+    #[uniffi::constructor]
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Listener and cancellation token threaded through one proof run.
+#[derive(Clone, Default)]
+pub struct ProofProgress {
+    listener: Option<Arc<dyn ProofProgressListener>>,
+    cancel: Option<Arc<CancellationToken>>,
+}
+
+impl ProofProgress {
+    pub fn new(
+        listener: Option<Arc<dyn ProofProgressListener>>,
+        cancel: Option<Arc<CancellationToken>>,
+    ) -> Self {
+        ProofProgress { listener, cancel }
+    }
+
+    /// Enter `stage`, unless the run was cancelled.
+    pub fn stage(&self, stage: ProofStage) -> Result<(), StealthnoteError> {
+        self.check_cancelled()?;
+        if let Some(listener) = &self.listener {
+            listener.on_stage(stage);
+        }
+        Ok(())
+    }
+
+    pub fn check_cancelled(&self) -> Result<(), StealthnoteError> {
+        match &self.cancel {
+            Some(token) if token.is_cancelled() => Err(StealthnoteError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Run blocking proving/verification work on its own thread so async callers
+/// (the `*_async` exports) don't stall the thread they were called from.
+pub async fn run_blocking<T, F>(work: F) -> Result<T, StealthnoteError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, StealthnoteError> + Send + 'static,
+{
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(work());
+    });
+    rx.await
+        .map_err(|_| StealthnoteError::ProofFailed("proof worker thread panicked".to_string()))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<ProofStage>>);
+
+    impl ProofProgressListener for Recorder {
+        fn on_stage(&self, stage: ProofStage) {
+            self.0.lock().unwrap().push(stage);
+        }
+    }
+
+    #[test]
+    fn test_stages_and_cancellation() {
+        let recorder = Arc::new(Recorder::default());
+        let token = CancellationToken::new();
+        let progress = ProofProgress::new(Some(recorder.clone()), Some(token.clone()));
+
+        progress.stage(ProofStage::InputGeneration).unwrap();
+        progress.stage(ProofStage::SrsSetup).unwrap();
+        token.cancel();
+        assert!(matches!(
+            progress.stage(ProofStage::WitnessSolving),
+            Err(StealthnoteError::Cancelled)
+        ));
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec![ProofStage::InputGeneration, ProofStage::SrsSetup]
+        );
+
+        assert!(ProofProgress::default().stage(ProofStage::Done).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_run_blocking() {
+        assert_eq!(run_blocking(|| Ok(42)).await.unwrap(), 42);
+        let result: Result<(), _> = run_blocking(|| panic!("boom")).await;
+        assert!(matches!(result, Err(StealthnoteError::ProofFailed(_))));
+    }
+}
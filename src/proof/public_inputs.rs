@@ -0,0 +1,251 @@
+use super::envelope::{split_bb_proof, ProofEnvelope};
+use super::jwt_input::MAX_DOMAIN_LENGTH;
+use super::jwt_proof::jwt_public_inputs;
+use super::rsa::{rsa_limb_count, RSA_LIMB_BITS};
+use crate::error::StealthnoteError;
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use chrono::DateTime;
+use num_bigint::BigUint;
+
+/// Shape of a JWT circuit version's public inputs: modulus limbs, domain
+/// bytes, domain length, shifted ephemeral pubkey, expiry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JwtPublicInputLayout {
+    /// Size of the RSA signing keys the circuit takes.
+    pub rsa_key_bits: usize,
+    pub modulus_limbs: usize,
+    pub modulus_limb_bits: usize,
+    pub max_domain_length: usize,
+}
+
+impl JwtPublicInputLayout {
+    /// Circuit 0.3.x: 18 limbs of 120 bits (RSA-2048) and a 64-byte domain.
+    pub const V0_3: Self = Self::rsa(2048);
+
+    /// Layout of a 0.3.x-style circuit for `rsa_key_bits`-bit keys.
+    pub const fn rsa(rsa_key_bits: usize) -> Self {
+        JwtPublicInputLayout {
+            rsa_key_bits,
+            modulus_limbs: rsa_limb_count(rsa_key_bits),
+            modulus_limb_bits: RSA_LIMB_BITS,
+            max_domain_length: MAX_DOMAIN_LENGTH,
+        }
+    }
+
+    pub const fn num_public_inputs(&self) -> usize {
+        self.modulus_limbs + self.max_domain_length + 3
+    }
+}
+
+pub const JWT_NUM_PUBLIC_INPUTS: usize = JwtPublicInputLayout::V0_3.num_public_inputs();
+
+/// What a JWT membership proof attests to, decoded from its public inputs.
+#[derive(uniffi::Record, Debug, Clone, PartialEq)]
+pub struct JwtPublicInputs {
+    /// RSA modulus of the signing key, base64url like the JWK `n`.
+    pub jwt_pubkey_modulus: String,
+    pub domain: String,
+    /// The ephemeral pubkey shifted right by 3 bits, as the circuit exposes it.
+    pub ephemeral_pubkey_shifted: String,
+    /// Seconds since the Unix epoch.
+    pub ephemeral_pubkey_expiry: i64,
+}
+
+/// A full bb JWT proof split into its decoded public inputs and the proof
+/// body (what stealthnote.xyz stores).
+#[derive(uniffi::Record, Debug, Clone, PartialEq)]
+pub struct JwtProofParts {
+    pub public_inputs: JwtPublicInputs,
+    pub proof_body: Vec<u8>,
+}
+
+impl JwtPublicInputs {
+    pub fn from_fields(fields: &[String]) -> Result<Self, StealthnoteError> {
+        Self::from_fields_with_layout(fields, &JwtPublicInputLayout::V0_3)
+    }
+
+    /// Inverse of the layout built by `prepare_public_inputs`.
+    pub fn from_fields_with_layout(
+        fields: &[String],
+        layout: &JwtPublicInputLayout,
+    ) -> Result<Self, StealthnoteError> {
+        if fields.len() != layout.num_public_inputs() {
+            return Err(StealthnoteError::CircuitMismatch(format!(
+                "JWT circuit has {} public inputs, got {}",
+                layout.num_public_inputs(),
+                fields.len()
+            )));
+        }
+        let values = fields
+            .iter()
+            .map(|f| parse_field(f))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (limbs, rest) = values.split_at(layout.modulus_limbs);
+        let (domain_bytes, rest) = rest.split_at(layout.max_domain_length);
+
+        let mut modulus = BigUint::default();
+        for limb in limbs.iter().rev() {
+            if limb.bits() > layout.modulus_limb_bits as u64 {
+                return Err(invalid("modulus limb exceeds the limb size"));
+            }
+            modulus = (modulus << layout.modulus_limb_bits) + limb;
+        }
+
+        let domain_len = to_usize(&rest[0], "domain length")?;
+        if domain_len > layout.max_domain_length {
+            return Err(invalid("domain length exceeds the domain field"));
+        }
+        let domain_bytes = domain_bytes
+            .iter()
+            .map(|b| u8::try_from(b).map_err(|_| invalid("domain byte exceeds 255")))
+            .collect::<Result<Vec<u8>, _>>()?;
+        if domain_bytes[domain_len..].iter().any(|b| *b != 0) {
+            return Err(invalid("domain has bytes past its length"));
+        }
+        let domain = String::from_utf8(domain_bytes[..domain_len].to_vec())
+            .map_err(|_| invalid("domain is not UTF-8"))?;
+
+        let expiry =
+            i64::try_from(&rest[2]).map_err(|_| invalid("expiry does not fit in a timestamp"))?;
+
+        Ok(JwtPublicInputs {
+            jwt_pubkey_modulus: BASE64_URL_SAFE_NO_PAD.encode(modulus.to_bytes_be()),
+            domain,
+            ephemeral_pubkey_shifted: rest[1].to_string(),
+            ephemeral_pubkey_expiry: expiry,
+        })
+    }
+
+    /// Public inputs a proof must carry for the given membership metadata.
+    pub fn from_claims(
+        domain: &str,
+        google_jwt_pubkey_modulus: &str,
+        ephemeral_pubkey: &str,
+        ephemeral_pubkey_expiry: &str,
+    ) -> Result<Self, StealthnoteError> {
+        Self::from_fields(&jwt_public_inputs(
+            &JwtPublicInputLayout::V0_3,
+            domain.to_string(),
+            google_jwt_pubkey_modulus.to_string(),
+            ephemeral_pubkey.to_string(),
+            ephemeral_pubkey_expiry.to_string(),
+        )?)
+    }
+
+    pub fn from_envelope(envelope: &ProofEnvelope) -> Result<Self, StealthnoteError> {
+        Self::from_fields(&envelope.public_inputs)
+    }
+
+    /// RFC 3339 form of the expiry, matching the app's `ephemeralPubkeyExpiry`
+    /// up to millisecond precision.
+    pub fn expiry_rfc3339(&self) -> Result<String, StealthnoteError> {
+        DateTime::from_timestamp(self.ephemeral_pubkey_expiry, 0)
+            .map(|d| d.to_rfc3339())
+            .ok_or_else(|| invalid("expiry out of range"))
+    }
+}
+
+impl JwtProofParts {
+    /// Split a proof as returned by `prove_jwt`, checking the bb length prefix.
+    pub fn from_bb_proof(bb_proof: &[u8]) -> Result<Self, StealthnoteError> {
+        let (fields, proof_body) = split_bb_proof(bb_proof, JWT_NUM_PUBLIC_INPUTS)?;
+        Ok(JwtProofParts {
+            public_inputs: JwtPublicInputs::from_fields(&fields)?,
+            proof_body,
+        })
+    }
+}
+
+fn parse_field(field: &str) -> Result<BigUint, StealthnoteError> {
+    let digits = field.strip_prefix("0x").unwrap_or(field);
+    BigUint::parse_bytes(digits.as_bytes(), 16)
+        .ok_or_else(|| invalid(&format!("{} is not a hex field", field)))
+}
+
+fn to_usize(value: &BigUint, what: &str) -> Result<usize, StealthnoteError> {
+    usize::try_from(value).map_err(|_| invalid(&format!("{} out of range", what)))
+}
+
+fn invalid(reason: &str) -> StealthnoteError {
+    StealthnoteError::InvalidInput(format!("JWT public inputs: {}", reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODULUS: &str = "03Cww27F2O7JxB5Ji9iT9szfKZ4MK-iPzVpQkdLjCuGKfpjaCVAz9zIQ0-7gbZ-8cJRaSLfByWTGMIHRYiX2efdjz1Z9jck0DK9W3mapFrBPvM7AlRni4lPlwUigDd8zxAMDCheqyK3vCOLFW-1xYHt_YGwv8b0dP7rjujarEYlWjeppO_QMNtXdKdT9eZtBEcj_9ms9W0aLdCFNR5AAR3y0kLkKR1H4DW7vncB46rqCJLenhlCbcW0MZ3asqcjqBQ2t9QMRnY83Zf_pNEsCcXlKp4uOQqEvzjAc9ZSr2sOmd_ESZ_3jMlNkCZ4J41TuG-My5illFcW5LajSKvxD3w";
+    const EPHEMERAL_PUBKEY: &str =
+        "17302102366996071265028731047581517700208166805377449770193522591062772282670";
+
+    fn claimed_fields() -> Vec<String> {
+        jwt_public_inputs(
+            &JwtPublicInputLayout::V0_3,
+            "pse.dev".to_string(),
+            MODULUS.to_string(),
+            EPHEMERAL_PUBKEY.to_string(),
+            "2025-05-07T09:07:57.379Z".to_string(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_decode_jwt_public_inputs() {
+        let decoded = JwtPublicInputs::from_fields(&claimed_fields()).unwrap();
+        assert_eq!(decoded.jwt_pubkey_modulus, MODULUS);
+        assert_eq!(decoded.domain, "pse.dev");
+        assert_eq!(
+            decoded.ephemeral_pubkey_shifted,
+            (BigUint::parse_bytes(EPHEMERAL_PUBKEY.as_bytes(), 10).unwrap() >> 3u32).to_string()
+        );
+        assert_eq!(decoded.ephemeral_pubkey_expiry, 1746608877);
+        assert_eq!(
+            decoded.expiry_rfc3339().unwrap(),
+            "2025-05-07T09:07:57+00:00"
+        );
+    }
+
+    #[test]
+    fn test_split_jwt_proof() {
+        let fields = claimed_fields();
+        let body = vec![7u8; 64];
+        let mut bb_proof = ((fields.len() + 2) as u32).to_be_bytes().to_vec();
+        for field in &fields {
+            bb_proof.extend(hex::decode(field.trim_start_matches("0x")).unwrap());
+        }
+        bb_proof.extend(&body);
+
+        let parts = JwtProofParts::from_bb_proof(&bb_proof).unwrap();
+        assert_eq!(parts.proof_body, body);
+        assert_eq!(
+            parts.public_inputs,
+            JwtPublicInputs::from_claims(
+                "pse.dev",
+                MODULUS,
+                EPHEMERAL_PUBKEY,
+                "2025-05-07T09:07:57.379Z"
+            )
+            .unwrap()
+        );
+
+        assert!(JwtProofParts::from_bb_proof(&bb_proof[..bb_proof.len() - 32]).is_err());
+        assert!(JwtProofParts::from_bb_proof(&bb_proof[..2000]).is_err());
+    }
+
+    #[test]
+    fn test_decode_rejects_bad_layout() {
+        let mut fields = claimed_fields();
+        assert!(JwtPublicInputs::from_fields(&fields[1..]).is_err());
+
+        // Byte past the declared domain length
+        fields[18 + 10] = format!("0x{:0>64x}", b'x');
+        assert!(matches!(
+            JwtPublicInputs::from_fields(&fields),
+            Err(StealthnoteError::InvalidInput(_))
+        ));
+
+        let mut fields = claimed_fields();
+        fields[18] = format!("0x{:0>64x}", 256);
+        assert!(JwtPublicInputs::from_fields(&fields).is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use crate::error::StealthnoteError;
+use num_bigint::BigUint;
+use sha2::{Digest, Sha256};
+
+/// Bits per limb of the RSA bignums in the circuits.
+pub const RSA_LIMB_BITS: usize = 120;
+
+// DER prefix of the PKCS#1 v1.5 DigestInfo for SHA-256 (RFC 8017, section 9.2)
+const SHA256_DIGEST_INFO: [u8; 19] = [
+    0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05,
+    0x00, 0x04, 0x20,
+];
+
+// 1.2.840.113549.1.1.1
+const RSA_ENCRYPTION_OID: [u8; 9] = [0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RsaPublicKey {
+    pub modulus: BigUint,
+    pub exponent: BigUint,
+}
+
+impl RsaPublicKey {
+    /// Parse a DER `SubjectPublicKeyInfo` (what DKIM `p=` holds) or a bare
+    /// PKCS#1 `RSAPublicKey`.
+    pub fn from_der(der: &[u8]) -> Result<Self, StealthnoteError> {
+        let mut outer = Der::new(der).sequence()?;
+        let mut key = if outer.peek() == Some(0x30) {
+            // SubjectPublicKeyInfo: algorithm, then the PKCS#1 key in a bit string
+            let mut algorithm = outer.sequence()?;
+            if algorithm.read(0x06)? != RSA_ENCRYPTION_OID {
+                return Err(invalid_key("public key is not an RSA key"));
+            }
+            let bits = outer.read(0x03)?;
+            match bits.split_first() {
+                Some((0, key)) => Der::new(key).sequence()?,
+                _ => return Err(invalid_key("malformed public key bit string")),
+            }
+        } else {
+            outer
+        };
+        Ok(RsaPublicKey {
+            modulus: BigUint::from_bytes_be(key.read(0x02)?),
+            exponent: BigUint::from_bytes_be(key.read(0x02)?),
+        })
+    }
+
+    pub fn key_bits(&self) -> usize {
+        rsa_key_bits(&self.modulus)
+    }
+
+    /// Check an RSASSA-PKCS1-v1_5 signature over SHA-256(`message`).
+    pub fn verify_pkcs1_sha256(&self, message: &[u8], signature: &[u8]) -> bool {
+        let key_len = self.key_bits() / 8;
+        let s = BigUint::from_bytes_be(signature);
+        let t_len = SHA256_DIGEST_INFO.len() + 32;
+        if signature.len() != key_len || s >= self.modulus || key_len < t_len + 11 {
+            return false;
+        }
+
+        // EMSA-PKCS1-v1_5: 0x00 0x01 0xff.. 0x00 DigestInfo SHA256(message)
+        let mut expected = vec![0xffu8; key_len];
+        expected[0] = 0x00;
+        expected[1] = 0x01;
+        expected[key_len - t_len - 1] = 0x00;
+        expected[key_len - t_len..key_len - 32].copy_from_slice(&SHA256_DIGEST_INFO);
+        expected[key_len - 32..].copy_from_slice(&Sha256::digest(message));
+
+        let m = s.modpow(&self.exponent, &self.modulus).to_bytes_be();
+        let mut encoded = vec![0u8; key_len - m.len()];
+        encoded.extend(m);
+        encoded == expected
+    }
+}
+
+/// Size of the RSA key with modulus `n`, in whole bytes as JWKs encode it.
+pub fn rsa_key_bits(n: &BigUint) -> usize {
+    (n.bits() as usize).div_ceil(8) * 8
+}
+
+/// Limbs a `key_bits`-bit bignum takes in the circuits (18 for RSA-2048).
+pub const fn rsa_limb_count(key_bits: usize) -> usize {
+    key_bits.div_ceil(RSA_LIMB_BITS)
+}
+
+/// Barrett reduction parameter noir-bignum expects: 2^(2k+4) / n.
+pub fn redc_param(n: &BigUint) -> BigUint {
+    (BigUint::from(1u64) << (2 * rsa_key_bits(n) + 4)) / n
+}
+
+/// Split `value` into `count` little-endian limbs of [`RSA_LIMB_BITS`] bits.
+pub fn to_limbs(value: &BigUint, count: usize) -> Vec<BigUint> {
+    let mask = (BigUint::from(1u64) << RSA_LIMB_BITS) - 1u64;
+    (0..count)
+        .map(|i| (value >> (i * RSA_LIMB_BITS)) & &mask)
+        .collect()
+}
+
+/// Minimal DER reader, enough for RSA public keys.
+struct Der<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Der<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Der { bytes }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.first().copied()
+    }
+
+    fn sequence(&mut self) -> Result<Der<'a>, StealthnoteError> {
+        Ok(Der::new(self.read(0x30)?))
+    }
+
+    /// Read one element with tag `tag` and return its contents.
+    fn read(&mut self, tag: u8) -> Result<&'a [u8], StealthnoteError> {
+        let [found, first, rest @ ..] = self.bytes else {
+            return Err(invalid_key("truncated public key"));
+        };
+        if *found != tag {
+            return Err(invalid_key(&format!(
+                "expected DER tag {:#04x}, got {:#04x}",
+                tag, found
+            )));
+        }
+        let (len, rest) = match *first {
+            len @ 0..=0x7f => (len as usize, rest),
+            0x81..=0x84 => {
+                let n = (*first & 0x7f) as usize;
+                if rest.len() < n {
+                    return Err(invalid_key("truncated public key"));
+                }
+                let len = rest[..n]
+                    .iter()
+                    .fold(0usize, |acc, b| acc << 8 | *b as usize);
+                (len, &rest[n..])
+            }
+            _ => return Err(invalid_key("unsupported DER length")),
+        };
+        if rest.len() < len {
+            return Err(invalid_key("truncated public key"));
+        }
+        let (contents, rest) = rest.split_at(len);
+        self.bytes = rest;
+        Ok(contents)
+    }
+}
+
+fn invalid_key(reason: &str) -> StealthnoteError {
+    StealthnoteError::InvalidKey(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{prelude::BASE64_STANDARD, Engine};
+
+    // gmail.com selector 20230601
+    const GMAIL_DKIM_KEY: &str = "MIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEAntvSKT1hkqhKe0xcaZ0x+QbouDsJuBfby/S82jxsoC/SodmfmVs2D1KAH3mi1AqdMdU12h2VfETeOJkgGYq5ljd996AJ7ud2SyOLQmlhaNHH7Lx+Mdab8/zDN1SdxPARDgcM7AsRECHwQ15R20FaKUABGu4NTbR2fDKnYwiq5jQyBkLWP+LgGOgfUF4T4HZb2PY2bQtEP6QeqOtcW4rrsH24L7XhD+HSZb1hsitrE0VPbhJzxDwI4JF815XMnSVjZgYUXP8CxI1Y0FONlqtQYgsorZ9apoW1KPQe8brSSlRsi9sXB/tu56LmG7tEDNmrZ5XUwQYUUADBOu7t1niwXwIDAQAB";
+
+    #[test]
+    fn test_parse_public_key() {
+        let key = RsaPublicKey::from_der(&BASE64_STANDARD.decode(GMAIL_DKIM_KEY).unwrap()).unwrap();
+        assert_eq!(key.key_bits(), 2048);
+        assert_eq!(key.exponent, BigUint::from(65537u32));
+        assert_eq!(to_limbs(&key.modulus, 18)[17], BigUint::from(0x9eu32));
+
+        assert!(RsaPublicKey::from_der(&[0x30, 0x05, 0x02, 0x01]).is_err());
+        assert!(RsaPublicKey::from_der(&[]).is_err());
+    }
+
+    #[test]
+    fn test_limbs_and_redc() {
+        for (key_bits, limbs) in [(2048usize, 18), (3072, 26), (4096, 35)] {
+            let n: BigUint = (BigUint::from(1u64) << (key_bits - 1)) + 12345u64;
+            assert_eq!(rsa_key_bits(&n), key_bits);
+            assert_eq!(rsa_limb_count(key_bits), limbs);
+
+            let redc = to_limbs(&redc_param(&n), limbs)
+                .iter()
+                .rev()
+                .fold(BigUint::default(), |acc, limb| {
+                    (acc << RSA_LIMB_BITS) + limb
+                });
+            assert_eq!(redc, (BigUint::from(1u64) << (2 * key_bits + 4)) / &n);
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use super::cache::CircuitHandle;
+use super::jwt_proof::{with_jwt_public_inputs, JWT_CIRCUIT_JSON, JWT_CIRCUIT_VERSION};
+use super::public_inputs::JwtPublicInputLayout;
+use super::vk::verify_with_vk;
+use crate::error::StealthnoteError;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitStatus {
+    Active,
+    /// Proofs for this version are no longer accepted.
+    Retired,
+}
+
+/// A released JWT circuit, as named by `proofArgs.jwtCircuitVersion`.
+#[derive(Debug)]
+pub struct JwtCircuitVersion {
+    pub version: &'static str,
+    /// Compiled artifact; dropped from the binary once a version is retired.
+    pub artifact_json: Option<&'static str>,
+    /// SHA-256 the derived VK must match, once published for the version.
+    pub vk_hash: Option<&'static str>,
+    pub layout: JwtPublicInputLayout,
+    pub status: CircuitStatus,
+}
+
+/// Every JWT circuit version stored memberships may reference. Append new
+/// versions instead of replacing entries so older proofs stay verifiable.
+pub const JWT_CIRCUIT_VERSIONS: &[JwtCircuitVersion] = &[JwtCircuitVersion {
+    version: JWT_CIRCUIT_VERSION,
+    artifact_json: Some(JWT_CIRCUIT_JSON),
+    vk_hash: None,
+    layout: JwtPublicInputLayout::V0_3,
+    status: CircuitStatus::Active,
+}];
+
+impl JwtCircuitVersion {
+    pub fn get(version: &str) -> Result<&'static Self, StealthnoteError> {
+        find(JWT_CIRCUIT_VERSIONS, version)
+    }
+
+    /// The active circuit for JWTs signed with `rsa_key_bits`-bit keys.
+    pub fn for_rsa_key(rsa_key_bits: usize) -> Result<&'static Self, StealthnoteError> {
+        find_for_key(JWT_CIRCUIT_VERSIONS, rsa_key_bits)
+    }
+
+    /// The active circuit whose modulus takes `limbs` limbs, as found in a
+    /// saved [`JwtCircuitInput`](super::jwt_input::JwtCircuitInput).
+    pub fn for_modulus_limbs(limbs: usize) -> Result<&'static Self, StealthnoteError> {
+        active(JWT_CIRCUIT_VERSIONS)
+            .find(|v| v.layout.modulus_limbs == limbs)
+            .ok_or_else(|| {
+                StealthnoteError::Unsupported(format!(
+                    "no JWT circuit takes a {}-limb RSA modulus",
+                    limbs
+                ))
+            })
+    }
+
+    /// The version's circuit, parsed once per process.
+    pub fn circuit(&self) -> Result<Arc<CircuitHandle>, StealthnoteError> {
+        static PARSED: OnceLock<Mutex<HashMap<&'static str, Arc<CircuitHandle>>>> = OnceLock::new();
+        let parsed = PARSED.get_or_init(Default::default);
+        let cached = parsed
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(self.version)
+            .cloned();
+        let circuit = match cached {
+            Some(circuit) => circuit,
+            None => {
+                let json = self.artifact_json.ok_or_else(|| {
+                    StealthnoteError::Unsupported(format!(
+                        "JWT circuit version {} has no artifact",
+                        self.version
+                    ))
+                })?;
+                let circuit = CircuitHandle::from_json(json)?;
+                parsed
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .insert(self.version, circuit.clone());
+                circuit
+            }
+        };
+        if circuit.artifact.num_public_inputs() != self.layout.num_public_inputs() {
+            return Err(StealthnoteError::CircuitMismatch(format!(
+                "JWT circuit {} has {} public inputs, layout expects {}",
+                self.version,
+                circuit.artifact.num_public_inputs(),
+                self.layout.num_public_inputs()
+            )));
+        }
+        Ok(circuit)
+    }
+
+    /// Circuit to prove with. The compiled-in version honours an artifact
+    /// loaded with `load_circuit_artifact`.
+    pub fn prover_circuit(&self) -> Result<Arc<CircuitHandle>, StealthnoteError> {
+        if self.version == JWT_CIRCUIT_VERSION {
+            CircuitHandle::jwt()
+        } else {
+            self.circuit()
+        }
+    }
+
+    /// Verify a stored membership proof (public inputs stripped) with this
+    /// version's circuit. Without `srs_path` the SRS is downloaded.
+    pub fn verify(
+        &self,
+        srs_path: Option<&str>,
+        proof: Vec<u8>,
+        domain: String,
+        google_jwt_pubkey_modulus: String,
+        ephemeral_pubkey: String,
+        ephemeral_pubkey_expiry: String,
+    ) -> Result<bool, StealthnoteError> {
+        let circuit = self.circuit()?;
+        let proof = with_jwt_public_inputs(
+            &self.layout,
+            proof,
+            domain,
+            google_jwt_pubkey_modulus,
+            ephemeral_pubkey,
+            ephemeral_pubkey_expiry,
+        )?;
+        match self.vk_hash {
+            Some(vk_hash) => {
+                let vk = circuit.verification_key(srs_path)?;
+                verify_with_vk(srs_path, vk, Some(vk_hash), proof)
+            }
+            None => circuit.verify(srs_path, proof),
+        }
+    }
+}
+
+fn find(
+    versions: &'static [JwtCircuitVersion],
+    version: &str,
+) -> Result<&'static JwtCircuitVersion, StealthnoteError> {
+    let entry = versions
+        .iter()
+        .find(|v| v.version == version)
+        .ok_or_else(|| {
+            let known: Vec<&str> = versions.iter().map(|v| v.version).collect();
+            StealthnoteError::Unsupported(format!(
+                "unknown JWT circuit version {} (known: {})",
+                version,
+                known.join(", ")
+            ))
+        })?;
+    if entry.status == CircuitStatus::Retired {
+        return Err(StealthnoteError::Unsupported(format!(
+            "JWT circuit version {} is retired",
+            version
+        )));
+    }
+    Ok(entry)
+}
+
+fn active(
+    versions: &'static [JwtCircuitVersion],
+) -> impl Iterator<Item = &'static JwtCircuitVersion> {
+    versions
+        .iter()
+        .filter(|v| v.status == CircuitStatus::Active)
+}
+
+fn find_for_key(
+    versions: &'static [JwtCircuitVersion],
+    rsa_key_bits: usize,
+) -> Result<&'static JwtCircuitVersion, StealthnoteError> {
+    active(versions)
+        .find(|v| v.layout.rsa_key_bits == rsa_key_bits)
+        .ok_or_else(|| {
+            let mut supported: Vec<String> = active(versions)
+                .map(|v| v.layout.rsa_key_bits.to_string())
+                .collect();
+            supported.dedup();
+            StealthnoteError::Unsupported(format!(
+                "no JWT circuit for {}-bit RSA keys (supported: {})",
+                rsa_key_bits,
+                supported.join(", ")
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::vk::vk_hash;
+
+    const TEST_VERSIONS: &[JwtCircuitVersion] = &[
+        JwtCircuitVersion {
+            version: "0.2.0",
+            artifact_json: None,
+            vk_hash: None,
+            layout: JwtPublicInputLayout::V0_3,
+            status: CircuitStatus::Retired,
+        },
+        JwtCircuitVersion {
+            version: "0.3.1",
+            artifact_json: Some(JWT_CIRCUIT_JSON),
+            vk_hash: None,
+            layout: JwtPublicInputLayout::V0_3,
+            status: CircuitStatus::Active,
+        },
+        JwtCircuitVersion {
+            version: "0.3.1-rsa4096",
+            artifact_json: None,
+            vk_hash: None,
+            layout: JwtPublicInputLayout::rsa(4096),
+            status: CircuitStatus::Active,
+        },
+    ];
+
+    #[test]
+    fn test_version_lookup() {
+        let current = JwtCircuitVersion::get(JWT_CIRCUIT_VERSION).unwrap();
+        assert_eq!(current.version, "0.3.1");
+        assert_eq!(current.circuit().unwrap().artifact.num_public_inputs(), 85);
+        assert!(Arc::ptr_eq(
+            &current.circuit().unwrap(),
+            &CircuitHandle::jwt().unwrap()
+        ));
+
+        assert_eq!(find(TEST_VERSIONS, "0.3.1").unwrap().version, "0.3.1");
+        let unknown = find(TEST_VERSIONS, "9.9.9").unwrap_err();
+        assert!(matches!(unknown, StealthnoteError::Unsupported(_)));
+        assert!(unknown.to_string().contains("0.2.0, 0.3.1"));
+        let retired = find(TEST_VERSIONS, "0.2.0").unwrap_err();
+        assert!(retired.to_string().contains("retired"));
+    }
+
+    #[test]
+    fn test_select_by_rsa_key_size() {
+        assert_eq!(
+            JwtCircuitVersion::for_rsa_key(2048).unwrap().version,
+            "0.3.1"
+        );
+        assert_eq!(
+            JwtCircuitVersion::for_modulus_limbs(18).unwrap().version,
+            "0.3.1"
+        );
+        assert_eq!(
+            find_for_key(TEST_VERSIONS, 4096)
+                .unwrap()
+                .layout
+                .modulus_limbs,
+            35
+        );
+
+        let unsupported = find_for_key(TEST_VERSIONS, 3072).unwrap_err();
+        assert!(matches!(unsupported, StealthnoteError::Unsupported(_)));
+        assert!(unsupported
+            .to_string()
+            .contains("no JWT circuit for 3072-bit RSA keys (supported: 2048, 4096)"));
+    }
+
+    /// Needs the SRS to derive the VKs of versions that pin one.
+    #[test]
+    fn test_pinned_vks_match_their_circuits() {
+        for version in active(JWT_CIRCUIT_VERSIONS) {
+            let Some(pinned) = version.vk_hash else {
+                continue;
+            };
+            let vk = version
+                .circuit()
+                .unwrap()
+                .verification_key(Some("public/jwt-srs.local"))
+                .unwrap();
+            assert_eq!(
+                pinned,
+                vk_hash(&vk),
+                "JWT circuit {} pins another VK",
+                version.version
+            );
+        }
+    }
+
+    #[test]
+    fn test_layout_mismatch_is_rejected() {
+        let wider = JwtCircuitVersion {
+            version: "test",
+            artifact_json: Some(JWT_CIRCUIT_JSON),
+            vk_hash: None,
+            layout: JwtPublicInputLayout {
+                modulus_limbs: 35,
+                ..JwtPublicInputLayout::V0_3
+            },
+            status: CircuitStatus::Active,
+        };
+        assert!(matches!(
+            wider.circuit(),
+            Err(StealthnoteError::CircuitMismatch(_))
+        ));
+    }
+}
@@ -0,0 +1,160 @@
+use super::cache::{ensure_verifier_srs, CircuitHandle};
+use crate::error::StealthnoteError;
+use noir::barretenberg::verify::verify_ultra_honk;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::{fs, path::Path};
+
+/// A precomputed verification key together with the SHA-256 it is pinned by.
+/// Saved as JSON with the key bytes hex-encoded.
+#[derive(uniffi::Record, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VerificationKey {
+    pub circuit_name: String,
+    pub circuit_hash: u64,
+    pub vk_hash: String,
+    #[serde(with = "hex_bytes")]
+    pub vk: Vec<u8>,
+}
+
+impl VerificationKey {
+    pub fn for_circuit(
+        circuit_name: &str,
+        circuit: &CircuitHandle,
+        srs_path: Option<&str>,
+    ) -> Result<Self, StealthnoteError> {
+        let vk = circuit.verification_key(srs_path)?;
+        Ok(VerificationKey {
+            circuit_name: circuit_name.to_string(),
+            circuit_hash: circuit.artifact.hash,
+            vk_hash: vk_hash(&vk),
+            vk,
+        })
+    }
+
+    pub fn jwt(srs_path: Option<&str>) -> Result<Self, StealthnoteError> {
+        Self::for_circuit("stealthnote_jwt", &*CircuitHandle::jwt()?, srs_path)
+    }
+
+    pub fn zkemail(srs_path: Option<&str>) -> Result<Self, StealthnoteError> {
+        Self::for_circuit("zkemail", &*CircuitHandle::zkemail()?, srs_path)
+    }
+
+    /// Parse a saved key, rejecting it if the bytes don't match the recorded
+    /// hash or, when given, the hash the caller pinned.
+    pub fn from_json(json: &str, expected_vk_hash: Option<&str>) -> Result<Self, StealthnoteError> {
+        let key: VerificationKey = serde_json::from_str(json).map_err(|e| {
+            StealthnoteError::CircuitMismatch(format!("Invalid verification key file: {}", e))
+        })?;
+        check_vk_hash(&key.vk, Some(&key.vk_hash))?;
+        check_vk_hash(&key.vk, expected_vk_hash)?;
+        Ok(key)
+    }
+
+    pub fn to_json(&self) -> Result<String, StealthnoteError> {
+        serde_json::to_string_pretty(self).map_err(|e| StealthnoteError::Storage(e.to_string()))
+    }
+
+    pub fn load(
+        path: impl AsRef<Path>,
+        expected_vk_hash: Option<&str>,
+    ) -> Result<Self, StealthnoteError> {
+        Self::from_json(&fs::read_to_string(path)?, expected_vk_hash)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), StealthnoteError> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+}
+
+/// Hex-encoded SHA-256 of the raw VK bytes.
+pub fn vk_hash(vk: &[u8]) -> String {
+    hex::encode(Sha256::digest(vk))
+}
+
+pub fn check_vk_hash(vk: &[u8], expected: Option<&str>) -> Result<(), StealthnoteError> {
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+    let expected = expected.trim_start_matches("0x").to_lowercase();
+    let actual = vk_hash(vk);
+    if actual != expected {
+        return Err(StealthnoteError::CircuitMismatch(format!(
+            "Verification key hash {} does not match pinned hash {}",
+            actual, expected
+        )));
+    }
+    Ok(())
+}
+
+/// Verify a full bb proof (public inputs included) against a precomputed VK.
+pub fn verify_with_vk(
+    srs_path: Option<&str>,
+    vk: Vec<u8>,
+    expected_vk_hash: Option<&str>,
+    proof: Vec<u8>,
+) -> Result<bool, StealthnoteError> {
+    check_vk_hash(&vk, expected_vk_hash)?;
+    ensure_verifier_srs(srs_path)?;
+    verify_ultra_honk(proof, vk).map_err(|e| StealthnoteError::ProofFailed(e.to_string()))
+}
+
+pub(super) mod hex_bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        hex::decode(s.trim_start_matches("0x")).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_key() -> VerificationKey {
+        let vk = vec![0u8, 1, 2, 3, 0xfe, 0xff];
+        VerificationKey {
+            circuit_name: "stealthnote_jwt".to_string(),
+            circuit_hash: 2226591547107946498,
+            vk_hash: vk_hash(&vk),
+            vk,
+        }
+    }
+
+    #[test]
+    fn test_vk_file_roundtrip() {
+        let key = sample_key();
+        let path = std::env::temp_dir().join("stealthnote_test_vk.json");
+        key.save(&path).unwrap();
+
+        assert_eq!(VerificationKey::load(&path, None).unwrap(), key);
+        let pinned = format!("0x{}", key.vk_hash.to_uppercase());
+        assert_eq!(VerificationKey::load(&path, Some(&pinned)).unwrap(), key);
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_vk_hash_pinning() {
+        let key = sample_key();
+        let json = key.to_json().unwrap();
+
+        let wrong = vk_hash(b"another key");
+        let result = VerificationKey::from_json(&json, Some(&wrong));
+        assert!(matches!(result, Err(StealthnoteError::CircuitMismatch(_))));
+
+        // Key bytes edited without updating the recorded hash
+        let mut tampered = key.clone();
+        tampered.vk[0] ^= 1;
+        let json = serde_json::to_string(&tampered).unwrap();
+        let result = VerificationKey::from_json(&json, None);
+        assert!(matches!(result, Err(StealthnoteError::CircuitMismatch(_))));
+
+        let result = verify_with_vk(None, key.vk.clone(), Some(&wrong), vec![]);
+        assert!(matches!(result, Err(StealthnoteError::CircuitMismatch(_))));
+    }
+}
@@ -0,0 +1,272 @@
+use num_bigint::BigUint;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+use super::cache::CircuitHandle;
+use super::circuit::AbiType;
+use super::dkim::{DkimSignature, Email, SignedHeaders};
+use super::dkim_registry::DkimKeyRegistry;
+use super::jwt_proof::StorageBlock;
+use super::rsa::{redc_param, rsa_limb_count, to_limbs, RsaPublicKey};
+use crate::error::StealthnoteError;
+
+/// `zkemail::Sequence`: a span of the signed header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sequence {
+    pub index: u32,
+    pub length: u32,
+}
+
+/// `zkemail::dkim::RSAPubkey`, as hex limbs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ZkEmailPubkey {
+    pub modulus: Vec<String>,
+    pub redc: Vec<String>,
+}
+
+/// Every parameter of the zkEmail circuit, laid out like `public/zkemail_input.json`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ZkEmailCircuitInput {
+    /// Canonicalized signed headers, ending with the DKIM-Signature header
+    pub header: StorageBlock,
+    pub pubkey: ZkEmailPubkey,
+    pub signature: Vec<String>,
+    pub date_index: u32,
+    pub subject_sequence: Sequence,
+    pub from_header_sequence: Sequence,
+    pub from_address_sequence: Sequence,
+}
+
+impl ZkEmailCircuitInput {
+    /// Build the inputs from a raw `.eml` and the sender's DKIM key. The DKIM
+    /// signature is checked here; the body hash is not, as the circuit only
+    /// covers the header.
+    pub fn from_eml(eml: &[u8], dkim_key: &RsaPublicKey) -> Result<Self, StealthnoteError> {
+        let email = Email::parse(eml)?;
+        let signature = email.dkim_signature(None)?;
+        Self::from_email(&email, &signature, dkim_key)
+    }
+
+    /// [`Self::from_eml`] with the key the registry has for the signature's
+    /// domain and selector at its `t=` timestamp.
+    pub fn from_eml_with_registry(
+        eml: &[u8],
+        registry: &DkimKeyRegistry,
+    ) -> Result<Self, StealthnoteError> {
+        let email = Email::parse(eml)?;
+        let signature = email.dkim_signature(None)?;
+        let dkim_key = registry.key_for(&signature)?;
+        Self::from_email(&email, &signature, &dkim_key)
+    }
+
+    pub fn from_email(
+        email: &Email,
+        signature: &DkimSignature,
+        dkim_key: &RsaPublicKey,
+    ) -> Result<Self, StealthnoteError> {
+        let circuit = CircuitHandle::zkemail()?;
+        let (max_header_length, limbs) = circuit_limits(&circuit.artifact)?;
+        if rsa_limb_count(dkim_key.key_bits()) != limbs {
+            return Err(StealthnoteError::Unsupported(format!(
+                "zkEmail circuit takes {}-limb RSA keys, DKIM key is {} bits",
+                limbs,
+                dkim_key.key_bits()
+            )));
+        }
+
+        let headers = email.signed_headers(signature);
+        signature.verify(&headers, dkim_key)?;
+        let mut header = sha256_pad(&headers.data);
+        if header.len() > max_header_length {
+            return Err(StealthnoteError::InvalidInput(format!(
+                "signed email header is {} bytes ({} padded), the circuit takes at most {}",
+                headers.data.len(),
+                header.len(),
+                max_header_length
+            )));
+        }
+        header.resize(max_header_length, 0);
+
+        let subject = signed_field(&headers, "subject")?;
+        let from_header = signed_field(&headers, "from")?;
+        let date = signed_field(&headers, "date")?;
+        let from_address = from_address(&headers.data, from_header)?;
+
+        let hex_limbs = |value: &BigUint| -> Vec<String> {
+            to_limbs(value, limbs)
+                .iter()
+                .map(|limb| format!("0x{:x}", limb))
+                .collect()
+        };
+        Ok(ZkEmailCircuitInput {
+            header: StorageBlock {
+                storage: header,
+                len: headers.data.len(),
+            },
+            pubkey: ZkEmailPubkey {
+                modulus: hex_limbs(&dkim_key.modulus),
+                redc: hex_limbs(&redc_param(&dkim_key.modulus)),
+            },
+            signature: hex_limbs(&BigUint::from_bytes_be(&signature.signature)),
+            date_index: date.index,
+            subject_sequence: subject,
+            from_header_sequence: from_header,
+            from_address_sequence: from_address,
+        })
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, StealthnoteError> {
+        serde_json::from_str(json)
+            .map_err(|e| StealthnoteError::InvalidInput(format!("zkEmail circuit input: {}", e)))
+    }
+
+    pub fn to_json(&self) -> Result<String, StealthnoteError> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| StealthnoteError::InvalidInput(e.to_string()))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, StealthnoteError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Flatten into the zkEmail circuit witness, type-checked against its ABI.
+    pub fn to_witness(&self) -> Result<Vec<String>, StealthnoteError> {
+        let circuit = CircuitHandle::zkemail()?;
+        let value = serde_json::to_value(self)
+            .map_err(|e| StealthnoteError::InvalidInput(e.to_string()))?;
+        circuit.artifact.witness_from_value(&value)
+    }
+}
+
+/// SHA-256 message padding, which the header storage carries past `len` like
+/// the zkEmail SDK's `sha256Pad`.
+fn sha256_pad(data: &[u8]) -> Vec<u8> {
+    let mut padded = data.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend(((data.len() as u64) * 8).to_be_bytes());
+    padded
+}
+
+/// Header capacity and RSA limb count of a zkEmail circuit.
+fn circuit_limits(
+    artifact: &super::circuit::CircuitArtifact,
+) -> Result<(usize, usize), StealthnoteError> {
+    let header = artifact
+        .parameter("header")
+        .and_then(|p| p.typ.bounded_vec_capacity());
+    let limbs = match artifact.parameter("signature").map(|p| &p.typ) {
+        Some(AbiType::Array { length, .. }) => Some(*length),
+        _ => None,
+    };
+    header.zip(limbs).ok_or_else(|| {
+        StealthnoteError::CircuitMismatch(
+            "zkEmail circuit has no header or signature input".to_string(),
+        )
+    })
+}
+
+fn signed_field(headers: &SignedHeaders, name: &str) -> Result<Sequence, StealthnoteError> {
+    let (index, length) = headers.field(name).ok_or_else(|| {
+        StealthnoteError::InvalidInput(format!(
+            "email: the {} header is not covered by the DKIM signature",
+            name
+        ))
+    })?;
+    Ok(Sequence {
+        index: index as u32,
+        length: length as u32,
+    })
+}
+
+/// The address in a `from:` field: inside `<...>` if present, else the whole value.
+fn from_address(header: &[u8], from: Sequence) -> Result<Sequence, StealthnoteError> {
+    let start = from.index as usize;
+    let field = &header[start..start + from.length as usize];
+    let colon = field.iter().position(|b| *b == b':').unwrap_or(0) + 1;
+    let (index, length) = match (
+        field.iter().rposition(|b| *b == b'<'),
+        field.iter().rposition(|b| *b == b'>'),
+    ) {
+        (Some(open), Some(close)) if open < close => (open + 1, close - open - 1),
+        _ => {
+            let value = &field[colon..];
+            let leading = value.iter().take_while(|b| b.is_ascii_whitespace()).count();
+            let trimmed = value.trim_ascii();
+            (colon + leading, trimmed.len())
+        }
+    };
+    if !field[index..index + length].contains(&b'@') {
+        return Err(StealthnoteError::InvalidInput(
+            "email: no address in the From header".to_string(),
+        ));
+    }
+    Ok(Sequence {
+        index: (start + index) as u32,
+        length: length as u32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proof::dkim::parse_dkim_key;
+
+    // gmail.com selector 20230601
+    const GMAIL_DKIM_RECORD: &str = "v=DKIM1; k=rsa; p=MIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEAntvSKT1hkqhKe0xcaZ0x+QbouDsJuBfby/S82jxsoC/SodmfmVs2D1KAH3mi1AqdMdU12h2VfETeOJkgGYq5ljd996AJ7ud2SyOLQmlhaNHH7Lx+Mdab8/zDN1SdxPARDgcM7AsRECHwQ15R20FaKUABGu4NTbR2fDKnYwiq5jQyBkLWP+LgGOgfUF4T4HZb2PY2bQtEP6QeqOtcW4rrsH24L7XhD+HSZb1hsitrE0VPbhJzxDwI4JF815XMnSVjZgYUXP8CxI1Y0FONlqtQYgsorZ9apoW1KPQe8brSSlRsi9sXB/tu56LmG7tEDNmrZ5XUwQYUUADBOu7t1niwXwIDAQAB";
+
+    #[test]
+    fn test_inputs_from_eml() {
+        let eml = fs::read("public/zkemail_test.eml").unwrap();
+        let key = parse_dkim_key(GMAIL_DKIM_RECORD).unwrap();
+        let input = ZkEmailCircuitInput::from_eml(&eml, &key).unwrap();
+
+        let expected = ZkEmailCircuitInput::load("public/zkemail_input.json").unwrap();
+        assert_eq!(input.header, expected.header);
+        assert_eq!(input.date_index, 133);
+        assert_eq!(
+            input.subject_sequence,
+            Sequence {
+                index: 22,
+                length: 28
+            }
+        );
+        assert_eq!(input.from_address_sequence, expected.from_address_sequence);
+        assert_eq!(input.to_witness().unwrap(), expected.to_witness().unwrap());
+
+        let registry = DkimKeyRegistry::from_snapshot("public/dkim_keys.zone".to_string()).unwrap();
+        let from_registry = ZkEmailCircuitInput::from_eml_with_registry(&eml, &registry).unwrap();
+        assert_eq!(from_registry, input);
+
+        // Bare LF exports canonicalize the same way
+        let lf = String::from_utf8(eml.clone())
+            .unwrap()
+            .replace("\r\n", "\n");
+        let from_lf = ZkEmailCircuitInput::from_eml(lf.as_bytes(), &key).unwrap();
+        assert_eq!(from_lf, input);
+    }
+
+    #[test]
+    fn test_rejects_tampered_email() {
+        let eml = String::from_utf8(fs::read("public/zkemail_test.eml").unwrap()).unwrap();
+        let key = parse_dkim_key(GMAIL_DKIM_RECORD).unwrap();
+
+        let tampered = eml.replace("This is a test email\r\nTo", "This is another email\r\nTo");
+        let err = ZkEmailCircuitInput::from_eml(tampered.as_bytes(), &key).unwrap_err();
+        assert!(err.to_string().contains("20230601._domainkey.gmail.com"));
+
+        let other_key = RsaPublicKey {
+            modulus: key.modulus.clone() - 2u32,
+            ..key.clone()
+        };
+        assert!(ZkEmailCircuitInput::from_eml(eml.as_bytes(), &other_key).is_err());
+
+        let unsigned = eml.replace("DKIM-Signature", "X-Original-Signature");
+        assert!(matches!(
+            ZkEmailCircuitInput::from_eml(unsigned.as_bytes(), &key),
+            Err(StealthnoteError::InvalidInput(_))
+        ));
+    }
+}
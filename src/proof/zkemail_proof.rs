@@ -0,0 +1,230 @@
+use super::cache::CircuitHandle;
+use super::envelope::split_bb_proof;
+use crate::error::StealthnoteError;
+use num_bigint::BigUint;
+
+/// `zkemail::MAX_EMAIL_ADDRESS_LENGTH`
+pub const MAX_EMAIL_ADDRESS_LENGTH: usize = 320;
+// `MAX_DATE_LENGTH + 1` and `MAX_SUBJECT_LENGTH + 1` in the circuit
+const DATE_CAPACITY: usize = 37;
+const SUBJECT_CAPACITY: usize = 101;
+
+/// `[pubkey hash, nullifier]`, then the from address, date and subject as
+/// `BoundedVec`s (storage followed by length).
+pub const ZKEMAIL_NUM_PUBLIC_INPUTS: usize =
+    2 + (MAX_EMAIL_ADDRESS_LENGTH + 1) + (DATE_CAPACITY + 1) + (SUBJECT_CAPACITY + 1);
+
+/// What a zkEmail proof attests to, decoded from its public outputs.
+#[derive(uniffi::Record, Debug, Clone, PartialEq)]
+pub struct ZkEmailClaims {
+    /// Pedersen hash of the DKIM key (modulus and redc limbs), the root of trust.
+    pub dkim_pubkey_hash: String,
+    /// Pedersen hash of the DKIM signature, unique per email.
+    pub email_nullifier: String,
+    pub from_address: String,
+    /// Lowercased domain part of `from_address`.
+    pub from_domain: String,
+    /// `Date:` header value as signed, e.g. `Tue, 14 Jan 2025 23:00:42 -0700`.
+    pub date: String,
+    pub subject: String,
+}
+
+impl ZkEmailClaims {
+    pub fn from_fields(fields: &[String]) -> Result<Self, StealthnoteError> {
+        if fields.len() != ZKEMAIL_NUM_PUBLIC_INPUTS {
+            return Err(StealthnoteError::CircuitMismatch(format!(
+                "zkEmail circuit has {} public inputs, got {}",
+                ZKEMAIL_NUM_PUBLIC_INPUTS,
+                fields.len()
+            )));
+        }
+        let values = fields
+            .iter()
+            .map(|f| parse_field(f))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (standard, rest) = values.split_at(2);
+        let (from_address, rest) = bounded_string(rest, MAX_EMAIL_ADDRESS_LENGTH, "from address")?;
+        let (date, rest) = bounded_string(rest, DATE_CAPACITY, "date")?;
+        let (subject, _) = bounded_string(rest, SUBJECT_CAPACITY, "subject")?;
+
+        let from_domain = match from_address.rsplit_once('@') {
+            Some((_, domain)) if !domain.is_empty() => domain.to_ascii_lowercase(),
+            _ => return Err(invalid("from address has no domain")),
+        };
+        Ok(ZkEmailClaims {
+            dkim_pubkey_hash: field_hex(&standard[0]),
+            email_nullifier: field_hex(&standard[1]),
+            from_address,
+            from_domain,
+            date,
+            subject,
+        })
+    }
+
+    /// Decode the outputs of a proof as returned by `prove_zkemail`.
+    pub fn from_bb_proof(bb_proof: &[u8]) -> Result<Self, StealthnoteError> {
+        let (fields, _) = split_bb_proof(bb_proof, ZKEMAIL_NUM_PUBLIC_INPUTS)?;
+        Self::from_fields(&fields)
+    }
+
+    /// Fail unless the proof was made with the given DKIM key hash and/or
+    /// sender domain.
+    pub fn check(
+        &self,
+        expected_dkim_pubkey_hash: Option<&str>,
+        expected_from_domain: Option<&str>,
+    ) -> Result<(), StealthnoteError> {
+        if let Some(expected) = expected_dkim_pubkey_hash {
+            let expected = parse_field(expected).map(|f| field_hex(&f))?;
+            if expected != self.dkim_pubkey_hash {
+                return Err(invalid(&format!(
+                    "DKIM key hash {} does not match {}",
+                    self.dkim_pubkey_hash, expected
+                )));
+            }
+        }
+        if let Some(expected) = expected_from_domain {
+            if !expected.eq_ignore_ascii_case(&self.from_domain) {
+                return Err(invalid(&format!(
+                    "sender domain {} does not match {}",
+                    self.from_domain, expected
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Verify a zkEmail proof and return what it attests to. Pins are checked
+/// before the (slow) proof verification; a mismatch or a proof that does not
+/// verify is an error.
+pub fn verify_zkemail_proof(
+    srs_path: Option<&str>,
+    proof: Vec<u8>,
+    expected_dkim_pubkey_hash: Option<&str>,
+    expected_from_domain: Option<&str>,
+) -> Result<ZkEmailClaims, StealthnoteError> {
+    let claims = ZkEmailClaims::from_bb_proof(&proof)?;
+    claims.check(expected_dkim_pubkey_hash, expected_from_domain)?;
+    if !CircuitHandle::zkemail()?.verify(srs_path, proof)? {
+        return Err(StealthnoteError::ProofFailed(
+            "zkEmail proof does not verify".to_string(),
+        ));
+    }
+    Ok(claims)
+}
+
+/// Read a `BoundedVec<u8, capacity>` off the front of `values`.
+fn bounded_string<'a>(
+    values: &'a [BigUint],
+    capacity: usize,
+    what: &str,
+) -> Result<(String, &'a [BigUint]), StealthnoteError> {
+    let (storage, rest) = values.split_at(capacity);
+    let len = usize::try_from(&rest[0])
+        .ok()
+        .filter(|len| *len <= capacity)
+        .ok_or_else(|| invalid(&format!("{} length exceeds its field", what)))?;
+    let bytes = storage[..len]
+        .iter()
+        .map(|b| u8::try_from(b).map_err(|_| invalid(&format!("{} byte exceeds 255", what))))
+        .collect::<Result<Vec<u8>, _>>()?;
+    let value = String::from_utf8(bytes).map_err(|_| invalid(&format!("{} is not UTF-8", what)))?;
+    Ok((value, &rest[1..]))
+}
+
+fn parse_field(field: &str) -> Result<BigUint, StealthnoteError> {
+    let digits = field.strip_prefix("0x").unwrap_or(field);
+    BigUint::parse_bytes(digits.as_bytes(), 16)
+        .ok_or_else(|| invalid(&format!("{} is not a hex field", field)))
+}
+
+fn field_hex(value: &BigUint) -> String {
+    format!("0x{:0>64x}", value)
+}
+
+fn invalid(reason: &str) -> StealthnoteError {
+    StealthnoteError::InvalidInput(format!("zkEmail public inputs: {}", reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DKIM_PUBKEY_HASH: &str =
+        "0x0a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f9";
+
+    fn bounded(value: &str, capacity: usize) -> Vec<String> {
+        let mut storage: Vec<String> = value.bytes().map(|b| format!("0x{:0>64x}", b)).collect();
+        storage.resize(capacity, format!("0x{:0>64x}", 0));
+        storage.push(format!("0x{:0>64x}", value.len()));
+        storage
+    }
+
+    fn claimed_fields(from_address: &str) -> Vec<String> {
+        let mut fields = vec![DKIM_PUBKEY_HASH.to_string(), format!("0x{:0>64x}", 42)];
+        fields.extend(bounded(from_address, MAX_EMAIL_ADDRESS_LENGTH));
+        fields.extend(bounded("Tue, 14 Jan 2025 23:00:42 -0700", DATE_CAPACITY));
+        fields.extend(bounded("This is a test email", SUBJECT_CAPACITY));
+        fields
+    }
+
+    fn bb_proof(fields: &[String]) -> Vec<u8> {
+        let mut bb_proof = ((fields.len() + 2) as u32).to_be_bytes().to_vec();
+        for field in fields {
+            bb_proof.extend(hex::decode(field.trim_start_matches("0x")).unwrap());
+        }
+        bb_proof.extend([7u8; 64]);
+        bb_proof
+    }
+
+    #[test]
+    fn test_decode_zkemail_claims() {
+        let fields = claimed_fields("gilcrest.jack@Gmail.com");
+        let claims = ZkEmailClaims::from_fields(&fields).unwrap();
+        assert_eq!(claims.dkim_pubkey_hash, DKIM_PUBKEY_HASH);
+        assert_eq!(claims.from_address, "gilcrest.jack@Gmail.com");
+        assert_eq!(claims.from_domain, "gmail.com");
+        assert_eq!(claims.date, "Tue, 14 Jan 2025 23:00:42 -0700");
+        assert_eq!(claims.subject, "This is a test email");
+
+        assert_eq!(
+            ZkEmailClaims::from_bb_proof(&bb_proof(&fields)).unwrap(),
+            claims
+        );
+
+        assert!(ZkEmailClaims::from_fields(&fields[1..]).is_err());
+        assert_eq!(
+            CircuitHandle::zkemail()
+                .unwrap()
+                .artifact
+                .num_public_inputs(),
+            ZKEMAIL_NUM_PUBLIC_INPUTS
+        );
+        assert!(ZkEmailClaims::from_fields(&claimed_fields("no-domain")).is_err());
+    }
+
+    #[test]
+    fn test_pinned_key_and_domain() {
+        let claims = ZkEmailClaims::from_fields(&claimed_fields("jack@gmail.com")).unwrap();
+        assert!(claims
+            .check(Some(DKIM_PUBKEY_HASH), Some("GMAIL.com"))
+            .is_ok());
+        assert!(claims.check(None, None).is_ok());
+        // Same field, unpadded
+        assert!(claims
+            .check(Some(DKIM_PUBKEY_HASH.replace("0x0", "0x").as_str()), None)
+            .is_ok());
+
+        assert!(claims.check(Some("0x01"), None).is_err());
+        assert!(matches!(
+            claims.check(None, Some("pse.dev")),
+            Err(StealthnoteError::InvalidInput(_))
+        ));
+
+        // Pins are checked before the proof itself
+        let bb_proof = bb_proof(&claimed_fields("jack@gmail.com"));
+        let result = verify_zkemail_proof(Some("missing.srs"), bb_proof, None, Some("pse.dev"));
+        assert!(matches!(result, Err(StealthnoteError::InvalidInput(_))));
+    }
+}